@@ -4,12 +4,12 @@ use winit_input_helper::WinitInputHelper;
 use crate::math::{
     lerp,
     linear_algebra::{matrix::Matrix4, quaternion::Quaternion, vector::Vector4},
+    transform::Transform,
 };
 
 pub struct Camera {
     pub input: WinitInputHelper,
-    pub position: Vector4,
-    pub direction: Vector4,
+    pub transform: Transform, // looks down its -z axis, the angles below turn it
     pub turbo: bool,
     pub speed: f32,
     pub h_speed: f32,
@@ -24,29 +24,53 @@ pub struct Camera {
 
 impl Camera {
     pub fn new(position: Vector4, direction: Vector4) -> Self {
-        Self {
+        // the angles that turn -z to the direction, so the camera doesn't snap back on the first update
+        let direction = direction.normalized();
+        let h_angle = (-direction.x).atan2(-direction.z);
+        let v_angle = direction.y.clamp(-1.0, 1.0).asin();
+
+        let mut transform = Transform::from_translation(position);
+        transform.set_rotation(Self::rotation(h_angle, v_angle));
+
+        return Self {
             input: WinitInputHelper::new(),
-            position,
-            direction,
+            transform,
             turbo: false,
             speed: 0.0,
             v_speed: 0.0,
             h_speed: 0.0,
-            h_angle: 0.0,
-            v_angle: 0.0,
-            h_angle_f: 0.0,
-            v_angle_f: 0.0,
+            h_angle,
+            v_angle,
+            h_angle_f: h_angle,
+            v_angle_f: v_angle,
             move_friction: 10.0,
             look_friction: 10.0,
-        }
+        };
+    }
+
+    pub fn position(&self) -> Vector4 {
+        return self.transform.translation();
     }
 
-    pub fn transform(&self) -> Matrix4 {
+    pub fn direction(&self) -> Vector4 {
+        return self.transform.forward();
+    }
+
+    // the view matrix, moves the world in front of the camera
+    pub fn view(&self) -> Matrix4 {
+        let position = self.position();
         let mut matrix = Matrix4::new_identity();
-        matrix.look_at(self.position, self.position + self.direction, Vector4::UP);
+        matrix.look_at(position, position + self.direction(), Vector4::UP);
         return matrix;
     }
 
+    // turned around the world's up axis first, then up or down around the camera's right axis
+    fn rotation(h_angle: f32, v_angle: f32) -> Quaternion {
+        let horizontal_quat = Quaternion::from_angle(h_angle, Vector4::UP);
+        let vertical_quat = Quaternion::from_angle(v_angle, Vector4::RIGHT);
+        return horizontal_quat * vertical_quat;
+    }
+
     pub fn handle_event(&mut self, event: &winit::event::Event<()>) {
         self.input.update(event);
     }
@@ -89,23 +113,20 @@ impl Camera {
         self.h_angle_f = lerp(self.h_angle_f, self.h_angle, self.look_friction * dt);
         self.v_angle_f = lerp(self.v_angle_f, self.v_angle, self.look_friction * dt);
 
-        let horizontal_quat = Quaternion::from_angle(self.h_angle_f, Vector4::UP);
-        let vertical_quat = Quaternion::from_angle(self.v_angle_f, Vector4::RIGHT);
-
-        let view_quat = horizontal_quat * vertical_quat;
+        self.transform
+            .set_rotation(Self::rotation(self.h_angle_f, self.v_angle_f));
 
-        self.direction = -Vector4::FORWARD;
-        self.direction = self.direction.rotate_quaternion(view_quat);
+        let direction = self.direction();
 
-        self.position = self.position + self.direction * self.speed * dt;
+        self.transform.translate(direction * self.speed * dt);
         self.speed = lerp(self.speed, 0.0, self.move_friction * dt);
 
-        let right = Vector4::new(-self.direction.z, 0.0, self.direction.x, 0.0);
+        let right = Vector4::new(-direction.z, 0.0, direction.x, 0.0);
 
-        self.position = self.position + right * self.h_speed * dt;
+        self.transform.translate(right * self.h_speed * dt);
         self.h_speed = lerp(self.h_speed, 0.0, self.move_friction * dt);
 
-        self.position = self.position + Vector4::UP * self.v_speed * dt;
+        self.transform.translate(Vector4::UP * self.v_speed * dt);
         self.v_speed = lerp(self.v_speed, 0.0, self.move_friction * dt);
     }
 }
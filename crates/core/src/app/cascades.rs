@@ -0,0 +1,228 @@
+use crate::{
+    graphics::light::{Light, ShadowMap},
+    math::{lerp, Matrix4, Vector4, PI},
+};
+
+use super::{camera::Camera, instance::Instance, renderer::Renderer};
+
+// cascaded shadow maps: the view frustum is split into slices and each slice gets its own shadow-map
+// close slices are small so their texels are tiny, far slices are big and blurry but nobody looks at them closely
+//
+//        near                                  distance
+//  eye  |  0  |    1     |          2          |
+//   <|  |     |          |                     |
+//       |     |          |                     |
+pub struct ShadowCascades {
+    pub renderer: Renderer, // draws the depth of every cascade (one after another)
+    pub count: usize,       // how many slices the view frustum is split into
+    pub distance: f32,      // shadows are only drawn this far from the camera
+    pub split_lambda: f32,  // blend between uniform (0) and logarithmic (1) splits
+    pub caster_range: f32,  // casters this far behind a slice still cast shadows
+}
+
+impl ShadowCascades {
+    pub fn new(resolution: u32, count: usize, distance: f32) -> Self {
+        Self {
+            renderer: Renderer::new(resolution, resolution),
+            count: count.max(1),
+            distance,
+            split_lambda: 0.75,
+            caster_range: 20.0,
+        }
+    }
+
+    // distance from the camera where each cascade ends, the last one ends at `distance`
+    //
+    // uniform:     |    |    |    |
+    // logarithmic: | |  |        |
+    pub fn splits(&self, near: f32) -> Vec<f32> {
+        let far = self.distance;
+
+        (1..=self.count)
+            .map(|i| {
+                let t = i as f32 / self.count as f32;
+                let uniform = near + (far - near) * t;
+                let logarithmic = near * (far / near).powf(t);
+                lerp(uniform, logarithmic, self.split_lambda)
+            })
+            .collect()
+    }
+
+    // corners of a slice of the camera's view frustum (between `near` and `far`) in world-space
+    // `fov` is vertical and in degrees like in `Matrix4::perspective`
+    pub fn frustum_corners(
+        camera: &Camera,
+        fov: f32,
+        aspect_ratio: f32,
+        near: f32,
+        far: f32,
+    ) -> [Vector4; 8] {
+        // the same basis that the camera's look-at builds
+        let forward = camera.direction().normalized();
+        let right = Vector4::UP.cross(forward).normalized();
+        let up = forward.cross(right).normalized();
+
+        let tan_half_fov = (fov * PI / 180.0 / 2.0).tan();
+        let mut corners = [Vector4::ZERO; 8];
+
+        for (i, distance) in [near, far].into_iter().enumerate() {
+            let center = camera.position() + forward * distance;
+            let half_height = distance * tan_half_fov;
+            let half_width = half_height * aspect_ratio;
+
+            corners[i * 4] = center - right * half_width - up * half_height;
+            corners[i * 4 + 1] = center + right * half_width - up * half_height;
+            corners[i * 4 + 2] = center + right * half_width + up * half_height;
+            corners[i * 4 + 3] = center - right * half_width + up * half_height;
+        }
+
+        for corner in corners.iter_mut() {
+            corner.w = 1.0;
+        }
+
+        corners
+    }
+
+    // light view-projection that covers all corners of a frustum slice
+    //
+    // the slice is wrapped in a sphere so the size of the projection doesn't change when the camera turns
+    // and the projection only moves in whole texels so the edges of the shadows don't crawl when the camera moves
+    pub fn fit(&self, corners: &[Vector4; 8], light_direction: Vector4) -> Matrix4 {
+        let resolution = self.renderer.width as f32;
+
+        let mut center = Vector4::ZERO;
+        for corner in corners.iter() {
+            center += *corner;
+        }
+        center = center / corners.len() as f32;
+
+        let mut radius: f32 = 0.0;
+        for corner in corners.iter() {
+            radius = radius.max((*corner - center).length());
+        }
+        // round up so that tiny floating point changes don't resize the projection
+        radius = (radius * 16.0).ceil() / 16.0;
+
+        // rotate the world so the light looks down the z-axis
+        let up = if light_direction.normalized().y.abs() > 0.99 {
+            Vector4::FORWARD
+        } else {
+            Vector4::UP
+        };
+        let mut view = Matrix4::new_identity();
+        view.look_at(Vector4::ZERO, light_direction, up);
+
+        // snap the center to the texel grid of the shadow-map
+        let texel = radius * 2.0 / resolution;
+        let light_center = Matrix4::multiply_vector(&view, center);
+        let x = (light_center.x / texel).floor() * texel;
+        let y = (light_center.y / texel).floor() * texel;
+        let z = light_center.z;
+
+        // the look-at points down +z so near and far are flipped for the orthographic projection
+        let projection = Matrix4::orthographic(
+            x - radius,
+            x + radius,
+            y - radius,
+            y + radius,
+            -(z - radius - self.caster_range),
+            -(z + radius),
+        );
+
+        Matrix4::multiply(&projection, &view)
+    }
+
+    // draw the depth of every cascade and hand the shadow-maps to the light
+    pub fn draw(
+        &mut self,
+        instances: &[Instance],
+        light: &mut Light,
+        camera: &Camera,
+        fov: f32,
+        aspect_ratio: f32,
+        near: f32,
+    ) {
+        let mut slice_near = near;
+
+        // the bitmaps of the last frame become the depth buffers of this frame, nothing is allocated or copied
+        let mut recycled = std::mem::take(&mut light.shadow_maps).into_iter();
+
+        for slice_far in self.splits(near) {
+            let corners = Self::frustum_corners(camera, fov, aspect_ratio, slice_near, slice_far);
+            let view_projection = self.fit(&corners, light.direction);
+
+            self.renderer.clear_depth_buffer();
+            for instance in instances.iter() {
+                instance.draw_depth(&mut self.renderer, &view_projection);
+            }
+
+            let depth = self
+                .renderer
+                .take_depth_buffer(recycled.next().map(|shadow_map| shadow_map.bitmap));
+            light
+                .shadow_maps
+                .push(ShadowMap::new(view_projection, depth));
+
+            slice_near = slice_far;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_camera() -> Camera {
+        Camera::new(
+            Vector4::new(1.0, 2.0, 3.0, 1.0),
+            Vector4::new(0.3, -0.2, -1.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn test_cascade_splits() {
+        let cascades = ShadowCascades::new(64, 3, 50.0);
+        let splits = cascades.splits(0.1);
+
+        assert_eq!(splits.len(), 3);
+        assert!(splits[0] > 0.1 && splits[0] < splits[1] && splits[1] < splits[2]);
+        assert!((splits[2] - 50.0).abs() < 0.001);
+
+        // the closest cascade is smaller than an even split
+        assert!(splits[0] < 50.0 / 3.0);
+    }
+
+    #[test]
+    fn test_cascade_fit() {
+        let cascades = ShadowCascades::new(64, 3, 50.0);
+        let camera = test_camera();
+        let light_direction = Vector4::new(-0.4, -0.6, -0.3, 0.0).normalized();
+
+        // the camera looks where it was made to look
+        let direction = Vector4::new(0.3, -0.2, -1.0, 0.0).normalized();
+        assert!((camera.direction() - direction).length() < 0.0001);
+
+        let corners = ShadowCascades::frustum_corners(&camera, 100.0, 1.5, 2.0, 10.0);
+        let view_projection = cascades.fit(&corners, light_direction);
+
+        // every corner of the slice lands inside of the shadow-map
+        for corner in corners.iter() {
+            let p = Matrix4::multiply_vector(&view_projection, *corner);
+            assert!(p.x.abs() <= 1.0 && p.y.abs() <= 1.0 && p.z.abs() <= 1.0);
+        }
+
+        // moving the camera a little only moves the projection in whole texels
+        let mut moved = camera;
+        moved
+            .transform
+            .translate(Vector4::new(0.013, 0.0, 0.007, 0.0));
+        let corners = ShadowCascades::frustum_corners(&moved, 100.0, 1.5, 2.0, 10.0);
+        let moved_view_projection = cascades.fit(&corners, light_direction);
+
+        let half_resolution = 64.0 / 2.0;
+        let shift_x = (view_projection[3][0] - moved_view_projection[3][0]) * half_resolution;
+        let shift_y = (view_projection[3][1] - moved_view_projection[3][1]) * half_resolution;
+        assert!((shift_x - shift_x.round()).abs() < 0.01);
+        assert!((shift_y - shift_y.round()).abs() < 0.01);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{
+    graphics::light::{CubeShadowMap, Light, LightKind, ShadowMap},
+    math::Vector4,
+};
+
+use super::{instance::Instance, renderer::Renderer};
+
+// omnidirectional shadows: the scene is drawn 6 times around a point light, once for every side of a cube
+//
+//      +y
+//       |  -z
+//       | /
+// -x ---o--- +x
+//      /|
+//   +z  |
+//      -y
+pub struct CubeShadows {
+    pub renderer: Renderer, // draws the depth of every face (one after another)
+    pub near: f32,          // everything closer to the light than this doesn't cast shadows
+}
+
+impl CubeShadows {
+    pub fn new(resolution: u32) -> Self {
+        Self {
+            renderer: Renderer::new(resolution, resolution),
+            near: 0.1,
+        }
+    }
+
+    // draw the depth of every face and hand the cube shadow-map to the light
+    // only point lights can have cube shadow-maps, the far plane of every face is the radius of the light
+    //
+    // six passes are expensive, so the cube shadow-map of the last frame is kept when the light didn't move
+    // and none of the instances that `moved` (indices into `instances`) is or was close enough to cast a shadow
+    pub fn draw(&mut self, instances: &[Instance], light: &mut Light, moved: &[usize]) {
+        let radius = match light.kind {
+            LightKind::Point { radius } => radius,
+            _ => return,
+        };
+
+        if let Some(cube_shadow_map) = &light.cube_shadow_map {
+            let light_moved = cube_shadow_map.position != light.position
+                || cube_shadow_map.near != self.near
+                || cube_shadow_map.far != radius;
+            let casters_moved = moved.iter().any(|&instance| {
+                cube_shadow_map.casters.contains(&instance)
+                    || Self::in_reach(&instances[instance], light)
+            });
+
+            if !light_moved && !casters_moved {
+                return;
+            }
+        }
+
+        // the faces of the last frame become the depth buffers of this frame
+        let mut recycled = light
+            .cube_shadow_map
+            .take()
+            .map(|cube_shadow_map| cube_shadow_map.faces)
+            .unwrap_or_default()
+            .into_iter();
+        let mut faces = Vec::with_capacity(6);
+
+        for face in 0..6 {
+            let view_projection =
+                CubeShadowMap::face_view_projection(light.position, self.near, radius, face);
+
+            self.renderer.clear_depth_buffer();
+            for instance in instances.iter() {
+                instance.draw_depth(&mut self.renderer, &view_projection);
+            }
+
+            let depth = self
+                .renderer
+                .take_depth_buffer(recycled.next().map(|shadow_map| shadow_map.bitmap));
+            faces.push(ShadowMap::new(view_projection, depth));
+        }
+
+        let mut cube_shadow_map = CubeShadowMap::new(light.position, self.near, radius, faces);
+        cube_shadow_map.casters = (0..instances.len())
+            .filter(|&instance| Self::in_reach(&instances[instance], light))
+            .collect();
+        light.cube_shadow_map = Some(cube_shadow_map);
+    }
+
+    // can the instance cast a shadow into the cube shadow-map of the light
+    // the mesh is wrapped in a sphere, so it's sometimes true for an instance that doesn't
+    fn in_reach(instance: &Instance, light: &Light) -> bool {
+        let radius = match light.kind {
+            LightKind::Point { radius } => radius,
+            _ => return false,
+        };
+
+        let matrix = instance.matrix();
+        let column = |col: usize| Vector4::new(matrix[col][0], matrix[col][1], matrix[col][2], 0.0);
+        let scale = column(0)
+            .length()
+            .max(column(1).length())
+            .max(column(2).length());
+
+        let distance = (matrix.translation() - light.position).length();
+        distance <= radius + instance.mesh.radius() * scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{
+        graphics::{material::Material, mesh::Mesh, vertex::Vertex},
+        math::Transform,
+    };
+
+    // a triangle below the light, facing it
+    fn test_instance() -> Instance {
+        let vertex =
+            |x: f32, z: f32| Vertex::new(Vector4::new(x, 0.0, z, 1.0), Vector4::ZERO, Vector4::UP);
+        let mesh = Mesh::new(
+            vec![vertex(-1.0, -1.0), vertex(0.0, 1.0), vertex(1.0, -1.0)],
+            vec![0, 1, 2],
+        );
+        let mut instance = Instance::new(Rc::new(Box::new(mesh)), Rc::new(Material::default()));
+        instance.transform = Transform::from_translation(Vector4::new(0.0, -2.0, 0.0, 1.0));
+        instance
+    }
+
+    #[test]
+    fn test_cube_shadows_redraw() {
+        let mut cube_shadows = CubeShadows::new(16);
+        let mut light = Light::point(Vector4::new(0.0, 0.0, 0.0, 1.0), 10.0);
+        let mut instances = vec![test_instance(), test_instance()];
+        instances[1].transform = Transform::from_translation(Vector4::new(50.0, 0.0, 0.0, 1.0));
+
+        let drawn = |light: &Light| {
+            let faces = &light.cube_shadow_map.as_ref().unwrap().faces;
+            faces
+                .iter()
+                .any(|face| face.bitmap.iter().any(|depth| *depth < 1.0))
+        };
+
+        cube_shadows.draw(&instances, &mut light, &[]);
+        assert!(drawn(&light));
+        assert_eq!(light.cube_shadow_map.as_ref().unwrap().casters, [0]);
+
+        // something far away moved, the old cube shadow-map is kept
+        instances[0]
+            .transform
+            .translate(Vector4::new(0.0, -50.0, 0.0, 0.0));
+        cube_shadows.draw(&instances, &mut light, &[1]);
+        assert!(drawn(&light));
+
+        // a caster left the light, its shadow goes with it
+        cube_shadows.draw(&instances, &mut light, &[0]);
+        assert!(!drawn(&light));
+        assert!(light.cube_shadow_map.as_ref().unwrap().casters.is_empty());
+
+        // the light followed it
+        light.position = Vector4::new(0.0, -50.0, 0.0, 1.0);
+        cube_shadows.draw(&instances, &mut light, &[]);
+        assert!(drawn(&light));
+    }
+}
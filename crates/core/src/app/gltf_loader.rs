@@ -0,0 +1,999 @@
+use std::{path::Path, rc::Rc, sync::Arc};
+
+use gltf::{
+    animation::{util::ReadOutputs, Interpolation as GltfInterpolation},
+    camera::Projection,
+    image::Format,
+    khr_lights_punctual::Kind,
+    material::AlphaMode,
+    mesh::Mode,
+    texture::{MagFilter, MinFilter, WrappingMode},
+};
+
+use crate::{
+    graphics::{
+        bitmap::{self, Bitmap},
+        light::Light,
+        material::Material,
+        sampler::{Sampler, TextureFilter, Wrap},
+        texture::Texture,
+    },
+    math::{
+        lerp,
+        linear_algebra::{matrix::Matrix4, quaternion::Quaternion, vector::Vector4},
+        transform::Transform,
+        PI,
+    },
+};
+
+use super::{
+    instance::Instance,
+    mesh_loader::{to_mesh, IndexedModel, Model, ModelPart},
+    skeleton::{AnimationClip, Interpolation, Joint, JointTrack, Skeleton, Track},
+};
+
+// gltf lights without a range reach forever, ours have to fade out somewhere
+const DEFAULT_LIGHT_RANGE: f32 = 100.0;
+
+// everything in the default scene of a .gltf or .glb file (buffers and images can be embedded or next to it)
+//
+// gltf and obj use the same axes (right handed, +y up) so both kinds of models can be mixed in a world
+// cameras and lights look down their -z axis
+//
+// skinned meshes keep their bind pose, a `SkinnedAnimation` with the skeleton and a clip of their skin moves them
+pub struct GltfScene {
+    // one model for each gltf mesh with a part for each primitive
+    pub meshes: Vec<Model>,
+    // shared by the mesh parts that use them
+    pub materials: Vec<Rc<Material>>,
+    // every node of the scene, parents come before their children
+    pub nodes: Vec<GltfNode>,
+    // cameras and lights (KHR_lights_punctual) are placed where their nodes are
+    pub cameras: Vec<GltfCamera>,
+    pub lights: Vec<Light>,
+    pub skins: Vec<GltfSkin>,
+}
+
+#[derive(Debug)]
+pub struct GltfNode {
+    pub name: String,
+    pub parent: Option<usize>, // index into `GltfScene.nodes`
+    pub mesh: Option<usize>,   // index into `GltfScene.meshes`
+    pub skin: Option<usize>,   // index into `GltfScene.skins`
+    pub translation: Vector4,
+    pub rotation: Quaternion,
+    pub scale: Vector4,
+    pub local: Matrix4, // translation * rotation * scale
+    pub world: Matrix4, // parent.world * local
+}
+
+// the animations of the file that move the joints of this skeleton, the joints are in the order of the skin
+#[derive(Debug)]
+pub struct GltfSkin {
+    pub name: String,
+    pub skeleton: Rc<Skeleton>,
+    pub clips: Vec<Rc<AnimationClip>>,
+}
+
+#[derive(Debug)]
+pub struct GltfCamera {
+    pub name: String,
+    pub projection: GltfProjection,
+    pub position: Vector4,  // world-space
+    pub direction: Vector4, // world-space, normalized
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum GltfProjection {
+    // `fov` is vertical in degrees (like `Matrix4::perspective`), no aspect ratio means the one of the screen
+    Perspective {
+        fov: f32,
+        aspect_ratio: Option<f32>,
+        near: f32,
+        far: Option<f32>,
+    },
+    // half the width and height of the view
+    Orthographic {
+        x_mag: f32,
+        y_mag: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+impl GltfScene {
+    // an instance for every mesh part of every node
+    //
+    // skinned meshes aren't moved by their node (like the spec says), the joints already are where they should be
+    pub fn instances(&self) -> Vec<Instance> {
+        let mut instances = vec![];
+
+        for node in self.nodes.iter() {
+            let Some(mesh) = node.mesh else {
+                continue;
+            };
+
+            for part in self.meshes[mesh].parts.iter() {
+                let mut instance = Instance::new(Rc::clone(&part.mesh), Rc::clone(&part.material));
+                if node.skin.is_none() {
+                    instance.transform = Transform::from_matrix(&node.world);
+                }
+                instances.push(instance);
+            }
+        }
+
+        instances
+    }
+}
+
+impl GltfProjection {
+    // `aspect_ratio` of the screen is used when the camera doesn't have its own
+    pub fn matrix(&self, aspect_ratio: f32) -> Matrix4 {
+        match *self {
+            GltfProjection::Perspective {
+                fov,
+                aspect_ratio: own_aspect_ratio,
+                near,
+                far,
+            } => Matrix4::perspective(
+                fov,
+                own_aspect_ratio.unwrap_or(aspect_ratio),
+                near,
+                far.unwrap_or(DEFAULT_LIGHT_RANGE * 10.0),
+            ),
+            GltfProjection::Orthographic {
+                x_mag,
+                y_mag,
+                near,
+                far,
+            } => Matrix4::orthographic(-x_mag, x_mag, -y_mag, y_mag, near, far),
+        }
+    }
+}
+
+pub fn load_gltf(filepath: &Path) -> Result<GltfScene, gltf::Error> {
+    let (document, buffers, images) = gltf::import(filepath)?;
+
+    // every image is decoded once, even when many textures use it
+    let textures: Vec<Option<Arc<Texture>>> = images.iter().map(to_texture).collect();
+
+    let materials: Vec<Rc<Material>> = document
+        .materials()
+        .map(|material| Rc::new(to_material(&material, &textures)))
+        .collect();
+    let default_material = Rc::new(Material::default());
+
+    let mut meshes = vec![];
+    for mesh in document.meshes() {
+        let mut model = Model::default();
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                log::warn!(
+                    "{:?} primitives aren't supported, only triangles",
+                    primitive.mode()
+                );
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let indexed_model = match to_indexed_model(&reader) {
+                Ok(indexed_model) => indexed_model,
+                Err(err) => {
+                    log::warn!("skipping a primitive of mesh {}: {err}", mesh.index());
+                    continue;
+                }
+            };
+
+            let mut part_mesh = to_mesh(indexed_model);
+
+            // the tangents of the file win over the generated ones (they match the normal maps that were baked)
+            // without normals the triangles are flat shaded and the spec says the tangents must be ignored
+            if let (Some(_), Some(tangents)) = (reader.read_normals(), reader.read_tangents()) {
+                for (vertex, [x, y, z, w]) in part_mesh.vertices.iter_mut().zip(tangents) {
+                    vertex.tangent = Vector4::new(x, y, z, w);
+                }
+            }
+
+            // the joints and weights of skinned meshes, flat shaded primitives have a vertex for every index
+            if let (Some(joints), Some(weights)) = (reader.read_joints(0), reader.read_weights(0)) {
+                let joints: Vec<[u16; 4]> = joints.into_u16().collect();
+                let weights: Vec<[f32; 4]> = weights.into_f32().collect();
+
+                let sources: Vec<usize> = match (reader.read_normals(), reader.read_indices()) {
+                    (None, Some(indices)) => indices.into_u32().map(|i| i as usize).collect(),
+                    _ => (0..part_mesh.vertices.len()).collect(),
+                };
+
+                for (vertex, source) in part_mesh.vertices.iter_mut().zip(sources) {
+                    if let (Some(&joints), Some(&[x, y, z, w])) =
+                        (joints.get(source), weights.get(source))
+                    {
+                        *vertex = vertex.with_skin(joints, Vector4::new(x, y, z, w));
+                    }
+                }
+            }
+
+            let material = primitive.material();
+            model.parts.push(ModelPart {
+                name: material.name().unwrap_or_default().to_string(),
+                mesh: Rc::new(Box::new(part_mesh)),
+                material: match material.index() {
+                    Some(index) => Rc::clone(&materials[index]),
+                    None => Rc::clone(&default_material),
+                },
+            });
+        }
+
+        meshes.push(model);
+    }
+
+    let mut scene = GltfScene {
+        meshes,
+        materials,
+        nodes: vec![],
+        cameras: vec![],
+        lights: vec![],
+        skins: vec![],
+    };
+
+    let Some(gltf_scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    else {
+        return Ok(scene);
+    };
+
+    // walk the tree from the roots, the parents are added before their children
+    let mut stack: Vec<(gltf::Node, Option<usize>)> =
+        gltf_scene.nodes().map(|node| (node, None)).collect();
+    stack.reverse();
+
+    while let Some((node, parent)) = stack.pop() {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let local = Matrix4 {
+            matrix: node.transform().matrix(),
+        };
+
+        let world = match parent {
+            Some(parent) => Matrix4::multiply(&scene.nodes[parent].world, &local),
+            None => local.clone(),
+        };
+
+        // where the node is and where its -z axis points to
+        let position = Matrix4::multiply_vector(&world, Vector4::new(0.0, 0.0, 0.0, 1.0));
+        let forward = Matrix4::multiply_vector(&world, Vector4::new(0.0, 0.0, -1.0, 0.0));
+        let direction = if forward.length() > 0.0 {
+            forward.normalized()
+        } else {
+            Vector4::FORWARD
+        };
+
+        if let Some(camera) = node.camera() {
+            scene.cameras.push(GltfCamera {
+                name: camera.name().unwrap_or_default().to_string(),
+                projection: to_projection(&camera),
+                position,
+                direction,
+            });
+        }
+
+        if let Some(light) = node.light() {
+            scene.lights.push(to_light(&light, position, direction));
+        }
+
+        let index = scene.nodes.len();
+        scene.nodes.push(GltfNode {
+            name: node.name().unwrap_or_default().to_string(),
+            parent,
+            mesh: node.mesh().map(|mesh| mesh.index()),
+            skin: node.skin().map(|skin| skin.index()),
+            translation: Vector4::new(translation[0], translation[1], translation[2], 1.0),
+            rotation: Quaternion::new(rotation[0], rotation[1], rotation[2], rotation[3]),
+            scale: Vector4::new(scale[0], scale[1], scale[2], 0.0),
+            local,
+            world,
+        });
+
+        let first_child = stack.len();
+        stack.extend(node.children().map(|child| (child, Some(index))));
+        stack[first_child..].reverse();
+    }
+
+    let mut parents: Vec<Option<usize>> = vec![None; document.nodes().count()];
+    for node in document.nodes() {
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+        }
+    }
+
+    for skin in document.skins() {
+        let skin = to_skin(&skin, &document, &buffers, &parents);
+        scene.skins.push(skin);
+    }
+
+    Ok(scene)
+}
+
+// the joints of a skin and the tracks of every animation that moves them
+//
+// `parents` has the parent of every gltf node
+fn to_skin(
+    skin: &gltf::Skin,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    parents: &[Option<usize>],
+) -> GltfSkin {
+    let get_buffer = |buffer: gltf::Buffer| Some(&buffers[buffer.index()][..]);
+
+    let nodes: Vec<usize> = skin.joints().map(|node| node.index()).collect();
+    let joint_of = |node: usize| nodes.iter().position(|&joint| joint == node);
+
+    let inverse_binds: Vec<Matrix4> = match skin.reader(get_buffer).read_inverse_bind_matrices() {
+        Some(matrices) => matrices.map(|matrix| Matrix4 { matrix }).collect(),
+        None => vec![],
+    };
+
+    let locals: Vec<Matrix4> = document
+        .nodes()
+        .map(|node| Matrix4 {
+            matrix: node.transform().matrix(),
+        })
+        .collect();
+
+    let joints = skin
+        .joints()
+        .enumerate()
+        .map(|(index, node)| {
+            // the closest joint above this one, the nodes in between become the offset of the joint
+            // a joint without a parent joint gets all the nodes above it (every root joint has its own)
+            let mut offset = Matrix4::new_identity();
+            let mut parent = parents[node.index()];
+            while let Some(above) = parent {
+                if joint_of(above).is_some() {
+                    break;
+                }
+                offset = Matrix4::multiply(&locals[above], &offset);
+                parent = parents[above];
+            }
+
+            let (translation, rotation, scale) = node.transform().decomposed();
+            Joint {
+                name: node.name().unwrap_or_default().to_string(),
+                parent: parent.and_then(joint_of),
+                inverse_bind: inverse_binds
+                    .get(index)
+                    .cloned()
+                    .unwrap_or_else(Matrix4::new_identity),
+                rest: Transform::new(
+                    Vector4::new(translation[0], translation[1], translation[2], 1.0),
+                    Quaternion::new(rotation[0], rotation[1], rotation[2], rotation[3]),
+                    Vector4::new(scale[0], scale[1], scale[2], 0.0),
+                ),
+                offset,
+            }
+        })
+        .collect();
+
+    let skeleton = Skeleton::new(joints);
+
+    let mut clips = vec![];
+    for animation in document.animations() {
+        let mut clip = AnimationClip {
+            name: animation.name().unwrap_or_default().to_string(),
+            duration: 0.0,
+            tracks: vec![],
+        };
+
+        for channel in animation.channels() {
+            let Some(joint) = joint_of(channel.target().node().index()) else {
+                continue;
+            };
+
+            let reader = channel.reader(get_buffer);
+            let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+                continue;
+            };
+            let times: Vec<f32> = times.collect();
+            clip.duration = times
+                .iter()
+                .fold(clip.duration, |duration, &t| duration.max(t));
+
+            let interpolation = channel.sampler().interpolation();
+            let track = match clip.tracks.iter().position(|track| track.joint == joint) {
+                Some(index) => &mut clip.tracks[index],
+                None => {
+                    clip.tracks.push(JointTrack {
+                        joint,
+                        translation: None,
+                        rotation: None,
+                        scale: None,
+                    });
+                    clip.tracks.last_mut().unwrap()
+                }
+            };
+
+            match outputs {
+                ReadOutputs::Translations(values) => {
+                    let values = values.map(|[x, y, z]| Vector4::new(x, y, z, 1.0)).collect();
+                    track.translation = Some(to_track(times, values, interpolation));
+                }
+                ReadOutputs::Rotations(values) => {
+                    let values = values
+                        .into_f32()
+                        .map(|[x, y, z, w]| Quaternion::new(x, y, z, w))
+                        .collect();
+                    track.rotation = Some(to_track(times, values, interpolation));
+                }
+                ReadOutputs::Scales(values) => {
+                    let values = values.map(|[x, y, z]| Vector4::new(x, y, z, 0.0)).collect();
+                    track.scale = Some(to_track(times, values, interpolation));
+                }
+                ReadOutputs::MorphTargetWeights(_) => {
+                    log::warn!("morph target animations aren't supported");
+                }
+            }
+        }
+
+        if !clip.tracks.is_empty() {
+            clips.push(Rc::new(clip));
+        }
+    }
+
+    GltfSkin {
+        name: skin.name().unwrap_or_default().to_string(),
+        skeleton: Rc::new(skeleton),
+        clips,
+    }
+}
+
+// cubic splines keep their keyframes (the middle of every in-tangent, value, out-tangent) and become linear
+fn to_track<T: Copy>(
+    times: Vec<f32>,
+    values: Vec<T>,
+    interpolation: GltfInterpolation,
+) -> Track<T> {
+    let (values, interpolation) = match interpolation {
+        GltfInterpolation::Step => (values, Interpolation::Step),
+        GltfInterpolation::Linear => (values, Interpolation::Linear),
+        GltfInterpolation::CubicSpline => (
+            values.chunks_exact(3).map(|spline| spline[1]).collect(),
+            Interpolation::Linear,
+        ),
+    };
+
+    Track {
+        times,
+        values,
+        interpolation,
+    }
+}
+
+// texture coordinates that are missing are (0, 0), missing normals make the primitive flat shaded (like the spec says)
+fn to_indexed_model<'a, 's, F>(
+    reader: &gltf::mesh::Reader<'a, 's, F>,
+) -> Result<IndexedModel, String>
+where
+    F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+{
+    let vertices: Vec<Vector4> = reader
+        .read_positions()
+        .ok_or("it doesn't have positions")?
+        .map(|[x, y, z]| Vector4::new(x, y, z, 1.0))
+        .collect();
+
+    let tex_coords: Vec<Vector4> = match reader.read_tex_coords(0) {
+        Some(tex_coords) => tex_coords
+            .into_f32()
+            .map(|[u, v]| Vector4::new(u, v, 0.0, 0.0))
+            .collect(),
+        None => vec![Vector4::ZERO; vertices.len()],
+    };
+
+    let indices: Vec<usize> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+        None => (0..vertices.len()).collect(),
+    };
+
+    // the gltf crate doesn't look at the values of the indices
+    if let Some(index) = indices.iter().find(|&&index| index >= vertices.len()) {
+        return Err(format!(
+            "index {index} is out of range, it only has {} vertices",
+            vertices.len()
+        ));
+    }
+
+    let Some(normals) = reader.read_normals() else {
+        return Ok(flat_shaded(&vertices, &tex_coords, &indices));
+    };
+
+    Ok(IndexedModel {
+        vertices,
+        tex_coords,
+        normals: normals
+            .map(|[x, y, z]| Vector4::new(x, y, z, 0.0))
+            .collect(),
+        tangents: vec![],
+        indices,
+    })
+}
+
+// every triangle gets its own vertices so they don't have to share a normal
+fn flat_shaded(vertices: &[Vector4], tex_coords: &[Vector4], indices: &[usize]) -> IndexedModel {
+    let mut model = IndexedModel::default();
+
+    for triangle in indices.chunks_exact(3) {
+        let (p0, p1, p2) = (
+            vertices[triangle[0]],
+            vertices[triangle[1]],
+            vertices[triangle[2]],
+        );
+
+        let normal = (p1 - p0).cross(p2 - p0);
+        let normal = if normal.length() > 0.0 {
+            normal.normalized()
+        } else {
+            normal
+        };
+
+        for &i in triangle {
+            model.indices.push(model.vertices.len());
+            model.vertices.push(vertices[i]);
+            model.tex_coords.push(tex_coords[i]);
+            model.normals.push(normal);
+        }
+    }
+
+    model
+}
+
+// metallic-roughness to blinn-phong:
+// - rough surfaces have wide and dim highlights
+// - metals tint their highlights with the base color
+fn to_material(material: &gltf::Material, textures: &[Option<Arc<Texture>>]) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let texture = |info: Option<gltf::texture::Texture>| -> Option<Arc<Texture>> {
+        textures[info?.source().index()].clone()
+    };
+
+    let [r, g, b, a] = pbr.base_color_factor();
+    let [er, eg, eb] = material.emissive_factor();
+
+    let roughness = pbr.roughness_factor();
+    let metallic = pbr.metallic_factor();
+    let gloss = 1.0 - roughness;
+
+    let mut result = Material {
+        color: Vector4::new(r, g, b, a),
+        specular: Vector4::new(
+            lerp(0.5, r, metallic) * gloss,
+            lerp(0.5, g, metallic) * gloss,
+            lerp(0.5, b, metallic) * gloss,
+            0.0,
+        ),
+        emissive: Vector4::new(er, eg, eb, 0.0),
+        shininess: (2.0 / roughness.powi(4).max(0.0001) - 2.0).clamp(1.0, 256.0),
+        double_sided: material.double_sided(),
+        diffuse_map: texture(pbr.base_color_texture().map(|info| info.texture())),
+        emissive_map: texture(material.emissive_texture().map(|info| info.texture())),
+        normal_map: texture(material.normal_texture().map(|info| info.texture())),
+        ..Default::default()
+    };
+
+    // opaque ignores the alpha, mask cuts out the pixels below the cutoff and draws the rest opaque
+    // blend mixes every pixel with what's already behind it, nothing is sorted so it only looks right when the
+    // see-through surfaces are drawn after the ones behind them
+    (result.alpha_cutoff, result.opaque) = match material.alpha_mode() {
+        AlphaMode::Opaque => (0.0, true),
+        AlphaMode::Mask => (material.alpha_cutoff().unwrap_or(0.5), true),
+        AlphaMode::Blend => (0.0, false),
+    };
+
+    // a material has a single sampler, the one of the base color is the most visible
+    if let Some(info) = pbr.base_color_texture() {
+        if info.tex_coord() != 0 {
+            log::warn!("only the first set of texture coordinates is supported");
+        }
+        result.sampler = to_sampler(&info.texture().sampler());
+    }
+
+    result
+}
+
+fn to_sampler(sampler: &gltf::texture::Sampler) -> Sampler {
+    let wrap = |mode: WrappingMode| match mode {
+        WrappingMode::Repeat => Wrap::Repeat,
+        WrappingMode::MirroredRepeat => Wrap::MirroredRepeat,
+        WrappingMode::ClampToEdge => Wrap::ClampToEdge,
+    };
+
+    let filter = match (sampler.mag_filter(), sampler.min_filter()) {
+        (Some(MagFilter::Nearest), _) => TextureFilter::Nearest,
+        (_, Some(MinFilter::Nearest | MinFilter::Linear)) => TextureFilter::Bilinear,
+        (_, Some(MinFilter::NearestMipmapNearest | MinFilter::LinearMipmapNearest)) => {
+            TextureFilter::Bilinear
+        }
+        _ => TextureFilter::Trilinear,
+    };
+
+    Sampler {
+        filter,
+        wrap_u: wrap(sampler.wrap_s()),
+        wrap_v: wrap(sampler.wrap_t()),
+        ..Default::default()
+    }
+}
+
+// the decoded image as an rgba bitmap, 16 bit channels keep their high byte and float images aren't supported
+fn to_texture(image: &gltf::image::Data) -> Option<Arc<Texture>> {
+    let (channels, bytes_per_channel) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        format => {
+            log::warn!("{format:?} images aren't supported");
+            return None;
+        }
+    };
+
+    let mut bytes = Vec::with_capacity((image.width * image.height * 4) as usize);
+    for pixel in image.pixels.chunks_exact(channels * bytes_per_channel) {
+        // little endian: the high byte is the last one
+        let channel = |c: usize| pixel[c * bytes_per_channel + bytes_per_channel - 1];
+
+        let rgba = match channels {
+            1 => [channel(0), channel(0), channel(0), 255],
+            2 => [channel(0), channel(0), channel(0), channel(1)],
+            3 => [channel(0), channel(1), channel(2), 255],
+            _ => [channel(0), channel(1), channel(2), channel(3)],
+        };
+        bytes.extend_from_slice(&rgba);
+    }
+
+    let bitmap = Bitmap::from_bytes(image.width, image.height, bitmap::Format::Rgba8, bytes);
+    Some(Arc::new(Texture::new(bitmap)))
+}
+
+fn to_projection(camera: &gltf::Camera) -> GltfProjection {
+    match camera.projection() {
+        Projection::Perspective(perspective) => GltfProjection::Perspective {
+            fov: perspective.yfov() * 180.0 / PI,
+            aspect_ratio: perspective.aspect_ratio(),
+            near: perspective.znear(),
+            far: perspective.zfar(),
+        },
+        Projection::Orthographic(orthographic) => GltfProjection::Orthographic {
+            x_mag: orthographic.xmag(),
+            y_mag: orthographic.ymag(),
+            near: orthographic.znear(),
+            far: orthographic.zfar(),
+        },
+    }
+}
+
+// the intensity isn't converted from physical units (lux and candela), 1.0 is full light
+fn to_light(
+    light: &gltf::khr_lights_punctual::Light,
+    position: Vector4,
+    direction: Vector4,
+) -> Light {
+    let range = light.range().unwrap_or(DEFAULT_LIGHT_RANGE);
+    let [r, g, b] = light.color();
+
+    let result = match light.kind() {
+        Kind::Directional => Light::directional(direction),
+        Kind::Point => Light::point(position, range),
+        // both are half angles, from the center of the cone to its edge
+        Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => Light::spot(
+            position,
+            direction,
+            range,
+            inner_cone_angle * 180.0 / PI,
+            outer_cone_angle * 180.0 / PI,
+        ),
+    };
+
+    result.with_color(Vector4::new(r, g, b, 0.0), light.intensity())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app::{morph::Playback, skeleton::SkinnedAnimation, TestDir},
+        graphics::light::LightKind,
+    };
+
+    #[test]
+    fn test_load_gltf() {
+        let directory = TestDir::new("gltf");
+
+        // a triangle facing +z without normals (3 positions, 3 u16 indices, 2 bytes of padding, 3 tangents and
+        // 3 texture coordinates), the padding is an index that's out of range for a broken primitive
+        let mut bytes = vec![];
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for index in [0u16, 1, 2, 7] {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        for _ in 0..3 {
+            for value in [0.0f32, 1.0, 0.0, -1.0] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        // v goes down the image, so u follows +x and v follows -y
+        for value in [0.0f32, 1.0, 1.0, 1.0, 0.0, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        std::fs::write(directory.join("triangle.bin"), &bytes).unwrap();
+
+        let image = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        image.save(directory.join("red.png")).unwrap();
+
+        // root (moved up) -> triangle (moved right), a camera and a point light looking down
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "extensionsUsed": ["KHR_lights_punctual"],
+            "extensions": {
+                "KHR_lights_punctual": {
+                    "lights": [{ "type": "spot", "color": [1, 0.5, 0], "intensity": 2, "range": 10,
+                                 "spot": { "innerConeAngle": 0.5, "outerConeAngle": 1.0 } }]
+                }
+            },
+            "scene": 0,
+            "scenes": [{ "nodes": [0, 2, 3] }],
+            "nodes": [
+                { "name": "root", "translation": [0, 2, 0], "children": [1] },
+                { "name": "triangle", "translation": [1, 0, 0], "mesh": 0 },
+                { "name": "camera", "translation": [0, 0, 5], "camera": 0 },
+                { "name": "light", "translation": [0, 3, 0], "rotation": [-0.7071068, 0, 0, 0.7071068],
+                  "extensions": { "KHR_lights_punctual": { "light": 0 } } }
+            ],
+            "cameras": [{ "type": "perspective", "perspective": { "yfov": 1.0471976, "znear": 0.1 } }],
+            "meshes": [{ "primitives": [
+                { "attributes": { "POSITION": 0, "TANGENT": 2, "TEXCOORD_0": 4 }, "indices": 1, "material": 0 },
+                { "attributes": { "POSITION": 0 }, "indices": 3 }
+            ] }],
+            "materials": [{
+                "name": "red",
+                "pbrMetallicRoughness": { "baseColorFactor": [1, 1, 1, 0.5], "baseColorTexture": { "index": 0 } },
+                "alphaMode": "MASK",
+                "doubleSided": true
+            }],
+            "textures": [{ "source": 0, "sampler": 0 }],
+            "samplers": [{ "magFilter": 9728, "wrapS": 33071, "wrapT": 33648 }],
+            "images": [{ "uri": "red.png" }],
+            "buffers": [{ "uri": "triangle.bin", "byteLength": 116 }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 8 },
+                { "buffer": 0, "byteOffset": 44, "byteLength": 48 },
+                { "buffer": 0, "byteOffset": 92, "byteLength": 24 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" },
+                { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC4" },
+                { "bufferView": 1, "byteOffset": 2, "componentType": 5123, "count": 3, "type": "SCALAR" },
+                { "bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC2" }
+            ]
+        }"#;
+        std::fs::write(directory.join("scene.gltf"), gltf).unwrap();
+
+        let scene = load_gltf(&directory.join("scene.gltf")).unwrap();
+
+        // the broken primitive is skipped
+        assert_eq!(scene.meshes[0].parts.len(), 1);
+
+        // missing normals are the face normal
+        let mesh = &scene.meshes[0].parts[0].mesh;
+        assert_eq!(mesh.indices.len(), 3);
+        assert_eq!(mesh.vertices[0].normal, Vector4::new(0.0, 0.0, 1.0, 0.0));
+
+        // and the tangents of the file are ignored, they're made from the uv-map instead
+        for vertex in mesh.vertices.iter() {
+            assert_eq!(vertex.tangent, Vector4::new(1.0, 0.0, 0.0, 1.0));
+        }
+
+        let material = &scene.materials[0];
+        assert_eq!(material.color, Vector4::new(1.0, 1.0, 1.0, 0.5));
+        assert_eq!(material.diffuse_map.as_ref().unwrap().width(), 2);
+        assert_eq!(material.alpha_cutoff, 0.5);
+        assert!(material.opaque);
+        assert!(material.double_sided);
+        assert_eq!(material.sampler.filter, TextureFilter::Nearest);
+        assert_eq!(
+            (material.sampler.wrap_u, material.sampler.wrap_v),
+            (Wrap::ClampToEdge, Wrap::MirroredRepeat)
+        );
+
+        // the children are moved by their parents
+        let names: Vec<_> = scene.nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, vec!["root", "triangle", "camera", "light"]);
+        assert_eq!(scene.nodes[1].parent, Some(0));
+        assert_eq!(
+            scene.nodes[1].world.translation(),
+            Vector4::new(1.0, 2.0, 0.0, 1.0)
+        );
+
+        let instances = scene.instances();
+        assert_eq!(instances.len(), 1);
+        assert!(Rc::ptr_eq(&instances[0].material, material));
+
+        let camera = &scene.cameras[0];
+        assert_eq!(camera.position, Vector4::new(0.0, 0.0, 5.0, 1.0));
+        assert_eq!(camera.direction, Vector4::new(0.0, 0.0, -1.0, 0.0));
+        assert!(
+            matches!(camera.projection, GltfProjection::Perspective { fov, far: None, .. } if (fov - 60.0).abs() < 0.001)
+        );
+
+        // the light is rotated to look down
+        let light = &scene.lights[0];
+        assert_eq!(light.position, Vector4::new(0.0, 3.0, 0.0, 1.0));
+        assert!((light.direction - Vector4::new(0.0, -1.0, 0.0, 0.0)).length() < 0.0001);
+        assert_eq!(light.intensity, 2.0);
+        assert!(
+            matches!(light.kind, LightKind::Spot { radius, outer, .. } if radius == 10.0 && (outer - 57.29578).abs() < 0.001)
+        );
+    }
+
+    #[test]
+    fn test_load_gltf_skin() {
+        let directory = TestDir::new("gltf-skin");
+
+        // a triangle without normals: the corner at x = 2 hangs on the elbow, the others on the shoulder
+        let mut bytes = vec![];
+        let floats = |bytes: &mut Vec<u8>, values: &[f32]| {
+            for value in values {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+        floats(&mut bytes, &[0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 1.0, 0.0]); // 0: positions
+        for index in [0u16, 1, 2, 0] {
+            bytes.extend_from_slice(&index.to_le_bytes()); // 36: indices (and padding)
+        }
+        bytes.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]); // 44: joints
+        #[rustfmt::skip]
+        floats(&mut bytes, &[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]); // 56: weights
+        #[rustfmt::skip]
+        floats(&mut bytes, &[
+            1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+            1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 1.0,
+        ]); // 104: inverse bind matrices
+        floats(&mut bytes, &[0.0, 1.0]); // 232: times
+        let half_turn = std::f32::consts::FRAC_1_SQRT_2;
+        floats(
+            &mut bytes,
+            &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, half_turn, half_turn],
+        ); // 240: rotations
+        std::fs::write(directory.join("arm.bin"), &bytes).unwrap();
+
+        // the skeleton hangs below a node that moves it up
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "scenes": [{ "nodes": [0, 1] }],
+            "nodes": [
+                { "name": "arm", "mesh": 0, "skin": 0, "translation": [5, 0, 0] },
+                { "name": "armature", "translation": [0, 1, 0], "children": [2] },
+                { "name": "shoulder", "children": [3] },
+                { "name": "elbow", "translation": [1, 0, 0] }
+            ],
+            "meshes": [{ "primitives": [{
+                "attributes": { "POSITION": 0, "JOINTS_0": 2, "WEIGHTS_0": 3 }, "indices": 1
+            }] }],
+            "skins": [{ "joints": [2, 3], "inverseBindMatrices": 4 }],
+            "animations": [{
+                "name": "bend",
+                "channels": [{ "sampler": 0, "target": { "node": 3, "path": "rotation" } }],
+                "samplers": [{ "input": 5, "output": 6, "interpolation": "LINEAR" }]
+            }],
+            "buffers": [{ "uri": "arm.bin", "byteLength": 272 }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 6 },
+                { "buffer": 0, "byteOffset": 44, "byteLength": 12 },
+                { "buffer": 0, "byteOffset": 56, "byteLength": 48 },
+                { "buffer": 0, "byteOffset": 104, "byteLength": 128 },
+                { "buffer": 0, "byteOffset": 232, "byteLength": 8 },
+                { "buffer": 0, "byteOffset": 240, "byteLength": 32 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [2, 1, 0] },
+                { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" },
+                { "bufferView": 2, "componentType": 5121, "count": 3, "type": "VEC4" },
+                { "bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC4" },
+                { "bufferView": 4, "componentType": 5126, "count": 2, "type": "MAT4" },
+                { "bufferView": 5, "componentType": 5126, "count": 2, "type": "SCALAR",
+                  "min": [0], "max": [1] },
+                { "bufferView": 6, "componentType": 5126, "count": 2, "type": "VEC4" }
+            ]
+        }"#;
+        std::fs::write(directory.join("arm.gltf"), gltf).unwrap();
+
+        let scene = load_gltf(&directory.join("arm.gltf")).unwrap();
+
+        let skin = &scene.skins[0];
+        let names: Vec<_> = skin
+            .skeleton
+            .joints
+            .iter()
+            .map(|j| j.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["shoulder", "elbow"]);
+        assert_eq!(skin.skeleton.joints[1].parent, Some(0));
+        assert_eq!(
+            skin.skeleton.joints[0].offset.translation(),
+            Vector4::new(0.0, 1.0, 0.0, 1.0)
+        );
+        assert_eq!(skin.clips[0].name, "bend");
+        assert_eq!(skin.clips[0].duration, 1.0);
+
+        // the node of a skinned mesh doesn't move it
+        let instances = scene.instances();
+        assert_eq!(
+            instances[0].transform.translation(),
+            Vector4::new(0.0, 0.0, 0.0, 1.0)
+        );
+
+        let mesh = &scene.meshes[0].parts[0].mesh;
+        assert_eq!(mesh.vertices[1].joints, [1, 0, 0, 0]);
+        assert_eq!(mesh.vertices[1].weights, Vector4::new(1.0, 0.0, 0.0, 0.0));
+
+        // bent all the way the elbow points up
+        let mut animation = SkinnedAnimation::new(Rc::clone(mesh), Rc::clone(&skin.skeleton));
+        animation.play(Rc::clone(&skin.clips[0]), Playback::Once);
+        animation.update(1.0);
+
+        let skinned = animation.mesh();
+        let expected = [(0.0, 1.0), (1.0, 2.0), (0.0, 2.0)];
+        for (vertex, (x, y)) in skinned.vertices.iter().zip(expected) {
+            assert!((vertex.position - Vector4::new(x, y, 0.0, 1.0)).length() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn test_load_gltf_skin_hierarchy() {
+        let directory = TestDir::new("gltf-skin-hierarchy");
+
+        // the forearm isn't a joint but it still moves (and turns) the elbow,
+        // the tail is a second root joint below a different node
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "scenes": [{ "nodes": [0, 4] }],
+            "nodes": [
+                { "name": "armature", "translation": [0, 1, 0], "children": [1] },
+                { "name": "shoulder", "children": [2] },
+                { "name": "forearm", "translation": [0.5, 0, 0], "rotation": [0, 0, 0.7071068, 0.7071068],
+                  "children": [3] },
+                { "name": "elbow", "translation": [1, 0, 0] },
+                { "name": "body", "translation": [0, 0, 3], "children": [5] },
+                { "name": "tail" }
+            ],
+            "skins": [{ "joints": [1, 3, 5] }]
+        }"#;
+        std::fs::write(directory.join("hierarchy.gltf"), gltf).unwrap();
+
+        let scene = load_gltf(&directory.join("hierarchy.gltf")).unwrap();
+        let skeleton = &scene.skins[0].skeleton;
+
+        let parents: Vec<_> = skeleton.joints.iter().map(|j| j.parent).collect();
+        assert_eq!(parents, vec![None, Some(0), None]);
+
+        let world = skeleton.world_matrices(&skeleton.rest_pose());
+        let expected = [(0.0, 1.0, 0.0), (0.5, 2.0, 0.0), (0.0, 0.0, 3.0)];
+        for (matrix, (x, y, z)) in world.iter().zip(expected) {
+            assert!((matrix.translation() - Vector4::new(x, y, z, 1.0)).length() < 0.0001);
+        }
+    }
+}
@@ -1,40 +1,58 @@
 use std::rc::Rc;
 
-use crate::graphics::bitmap::Bitmap;
 use crate::graphics::light::Light;
 use crate::graphics::material::Material;
 use crate::graphics::mesh::Mesh;
-use crate::math::linear_algebra::matrix::Matrix4;
+use crate::graphics::shader::{DepthShader, StandardShader};
+use crate::math::linear_algebra::{matrix::Matrix4, vector::Vector4};
+use crate::math::transform::Transform;
 
 use super::renderer::Renderer;
 
 #[derive(Debug)]
 pub struct Instance {
     pub mesh: Rc<Box<Mesh>>,
-    // @todo: use material instead of bitmap
-    pub bitmap: Rc<Box<Bitmap<u8>>>,
-    pub transform: Matrix4,
-    pub light: bool,
+    pub material: Rc<Material>, // many instances can share the same material
+    pub transform: Transform,
+    pub world: Option<Matrix4>, // set by the scene when the instance belongs to a node, wins over the transform
 }
 
 impl Instance {
-    pub fn new(mesh: Rc<Box<Mesh>>, bitmap: Rc<Box<Bitmap<u8>>>, light: bool) -> Self {
+    pub fn new(mesh: Rc<Box<Mesh>>, material: Rc<Material>) -> Self {
         Self {
             mesh,
-            bitmap,
-            transform: Matrix4::new_identity(),
-            light,
+            material,
+            transform: Transform::default(),
+            world: None,
         }
     }
 
-    pub fn draw(&self, renderer: &mut Renderer, view_projection: &Matrix4, light: Option<&Light>) {
-        renderer.draw_mesh(
-            self.mesh.as_ref(),
-            view_projection,
-            &self.transform,
-            // @todo: use Rc Box Material instead of Bitmap
-            &Material::new(self.light, self.bitmap.clone()),
-            light,
-        );
+    // the model matrix that the instance is drawn with
+    pub fn matrix(&self) -> &Matrix4 {
+        match &self.world {
+            Some(world) => world,
+            None => self.transform.matrix(),
+        }
+    }
+
+    // `eye` is the camera position in world-space
+    pub fn draw(
+        &self,
+        renderer: &mut Renderer,
+        view_projection: &Matrix4,
+        eye: Vector4,
+        lights: &[Light],
+    ) {
+        let shader =
+            StandardShader::new(view_projection, eye, self.matrix(), &self.material, lights);
+
+        renderer.draw_mesh(self.mesh.as_ref(), &shader);
+    }
+
+    // only the depth, for shadow-maps
+    pub fn draw_depth(&self, renderer: &mut Renderer, view_projection: &Matrix4) {
+        let shader = DepthShader::new(view_projection, self.matrix(), &self.material);
+
+        renderer.draw_mesh(self.mesh.as_ref(), &shader);
     }
 }
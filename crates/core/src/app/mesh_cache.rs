@@ -0,0 +1,262 @@
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    graphics::{mesh::Mesh, vertex::Vertex},
+    math::linear_algebra::vector::Vector4,
+};
+
+// a mesh that's ready to be drawn, saved as it is in memory so nothing has to be parsed or indexed when it's loaded
+//
+// header (little endian):
+//   magic          4 bytes  "SRMC"
+//   version        u32
+//   vertex count   u32
+//   index count    u32
+//   checksum       u32      crc32 of everything after the header
+// vertices:
+//   position xyz, texcoords uv, normal xyz, tangent xyzw, color rgba   16 x f32
+//   joints (as floats), weights                                        8 x f32
+// indices:
+//   u32
+//
+// the version goes up whenever the layout changes, old caches have to be converted again
+// 1: first version
+// 2: vertex colors
+// 3: joints and weights for skinning
+pub const MAGIC: [u8; 4] = *b"SRMC";
+pub const VERSION: u32 = 3;
+pub const EXTENSION: &str = "mesh";
+
+const FLOATS_PER_VERTEX: usize = 24;
+
+#[derive(Debug)]
+pub enum MeshCacheError {
+    Io(io::Error), // also when the file ends too early
+    BadMagic,      // not a mesh cache
+    UnsupportedVersion(u32),
+    ChecksumMismatch { expected: u32, actual: u32 },
+    BadIndex(u32), // an index that points past the vertices
+}
+
+impl fmt::Display for MeshCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshCacheError::Io(err) => write!(f, "{err}"),
+            MeshCacheError::BadMagic => write!(f, "not a mesh cache"),
+            MeshCacheError::UnsupportedVersion(version) => write!(
+                f,
+                "mesh cache version {version} isn't supported (expected {VERSION}), convert it again"
+            ),
+            MeshCacheError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "mesh cache is corrupted (checksum {actual:08x}, expected {expected:08x})"
+            ),
+            MeshCacheError::BadIndex(index) => write!(f, "mesh cache has a bad index {index}"),
+        }
+    }
+}
+
+impl Error for MeshCacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MeshCacheError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MeshCacheError {
+    fn from(err: io::Error) -> Self {
+        MeshCacheError::Io(err)
+    }
+}
+
+pub fn save_mesh_cache(filepath: &Path, mesh: &Mesh) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filepath)?);
+    write_mesh(&mut writer, mesh)?;
+    writer.flush()
+}
+
+pub fn load_mesh_cache(filepath: &Path) -> Result<Mesh, MeshCacheError> {
+    read_mesh(BufReader::new(File::open(filepath)?))
+}
+
+pub fn write_mesh(mut writer: impl Write, mesh: &Mesh) -> io::Result<()> {
+    let mut payload =
+        Vec::with_capacity(mesh.vertices.len() * FLOATS_PER_VERTEX * 4 + mesh.indices.len() * 4);
+
+    for vertex in mesh.vertices.iter() {
+        let (p, t, n, g, c, j, w) = (
+            vertex.position,
+            vertex.texcoords,
+            vertex.normal,
+            vertex.tangent,
+            vertex.color,
+            vertex.joints.map(|joint| joint as f32),
+            vertex.weights,
+        );
+        for value in [
+            p.x, p.y, p.z, t.x, t.y, n.x, n.y, n.z, g.x, g.y, g.z, g.w, c.x, c.y, c.z, c.w, j[0],
+            j[1], j[2], j[3], w.x, w.y, w.z, w.w,
+        ] {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    for &index in mesh.indices.iter() {
+        let index = u32::try_from(index)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many vertices"))?;
+        payload.extend_from_slice(&index.to_le_bytes());
+    }
+
+    writer.write_all(&MAGIC)?;
+    for value in [
+        VERSION,
+        mesh.vertices.len() as u32,
+        mesh.indices.len() as u32,
+        crc32fast::hash(&payload),
+    ] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.write_all(&payload)
+}
+
+pub fn read_mesh(mut reader: impl Read) -> Result<Mesh, MeshCacheError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(MeshCacheError::BadMagic);
+    }
+
+    let version = read_u32(&mut reader)?;
+    if version != VERSION {
+        return Err(MeshCacheError::UnsupportedVersion(version));
+    }
+
+    let vertex_count = read_u32(&mut reader)? as usize;
+    let index_count = read_u32(&mut reader)? as usize;
+    let expected = read_u32(&mut reader)?;
+
+    // the counts decide how much is read, a broken header fails on the checksum (or on the end of the file)
+    let mut payload = vec![];
+    let length = (vertex_count * FLOATS_PER_VERTEX + index_count) * 4;
+    reader.take(length as u64).read_to_end(&mut payload)?;
+    if payload.len() < length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    let actual = crc32fast::hash(&payload);
+    if actual != expected {
+        return Err(MeshCacheError::ChecksumMismatch { expected, actual });
+    }
+
+    let (vertex_bytes, index_bytes) = payload.split_at(vertex_count * FLOATS_PER_VERTEX * 4);
+
+    let vertices = vertex_bytes
+        .chunks_exact(FLOATS_PER_VERTEX * 4)
+        .map(|bytes| {
+            let f = |i: usize| f32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+            Vertex::new(
+                Vector4::new(f(0), f(1), f(2), 1.0),
+                Vector4::new(f(3), f(4), 0.0, 0.0),
+                Vector4::new(f(5), f(6), f(7), 0.0),
+            )
+            .with_tangent(Vector4::new(f(8), f(9), f(10), f(11)))
+            .with_color(Vector4::new(f(12), f(13), f(14), f(15)))
+            .with_skin(
+                [f(16) as u16, f(17) as u16, f(18) as u16, f(19) as u16],
+                Vector4::new(f(20), f(21), f(22), f(23)),
+            )
+        })
+        .collect();
+
+    let indices = index_bytes
+        .chunks_exact(4)
+        .map(|bytes| {
+            let index = u32::from_le_bytes(bytes.try_into().unwrap());
+            if index as usize >= vertex_count {
+                return Err(MeshCacheError::BadIndex(index));
+            }
+            Ok(index as usize)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Mesh::new(vertices, indices))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Mesh {
+        let vertex = |x: f32, y: f32| {
+            Vertex::new(
+                Vector4::new(x, y, 0.5, 1.0),
+                Vector4::new(x, 1.0 - y, 0.0, 0.0),
+                Vector4::new(0.0, 0.0, 1.0, 0.0),
+            )
+            .with_tangent(Vector4::new(1.0, 0.0, 0.0, -1.0))
+            .with_color(Vector4::new(x, y, 0.0, 1.0))
+            .with_skin([0, 1, 2, 3], Vector4::new(0.5, 0.5, 0.0, 0.0))
+        };
+
+        Mesh::new(
+            vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)],
+            vec![0, 1, 2],
+        )
+    }
+
+    #[test]
+    fn test_mesh_cache() {
+        let mesh = triangle();
+
+        let mut bytes = vec![];
+        write_mesh(&mut bytes, &mesh).unwrap();
+        assert_eq!(bytes.len(), 20 + 3 * 96 + 3 * 4);
+
+        let loaded = read_mesh(bytes.as_slice()).unwrap();
+        assert_eq!(loaded.indices, mesh.indices);
+        for (a, b) in loaded.vertices.iter().zip(mesh.vertices.iter()) {
+            assert_eq!(
+                (a.position, a.texcoords, a.normal, a.tangent, a.color),
+                (b.position, b.texcoords, b.normal, b.tangent, b.color)
+            );
+            assert_eq!((a.joints, a.weights), (b.joints, b.weights));
+        }
+
+        // a flipped bit anywhere after the header is caught
+        let mut corrupted = bytes.clone();
+        corrupted[40] ^= 1;
+        assert!(matches!(
+            read_mesh(corrupted.as_slice()),
+            Err(MeshCacheError::ChecksumMismatch { .. })
+        ));
+
+        let mut newer = bytes.clone();
+        newer[4] = 4;
+        assert!(matches!(
+            read_mesh(newer.as_slice()),
+            Err(MeshCacheError::UnsupportedVersion(4))
+        ));
+
+        assert!(matches!(
+            read_mesh(&b"v 0 0 0\n"[..]),
+            Err(MeshCacheError::BadMagic)
+        ));
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(matches!(read_mesh(truncated), Err(MeshCacheError::Io(_))));
+    }
+}
@@ -1,24 +1,46 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{self, BufRead, BufReader},
+    path::Path,
+    rc::Rc,
 };
 
-use rayon::prelude::*;
-
 use crate::{
-    graphics::{mesh::Mesh, vertex::Vertex},
+    graphics::{material::Material, mesh::Mesh, vertex::Vertex},
     math::linear_algebra::vector::Vector4,
 };
 
+use super::mtl_loader::MaterialLibrary;
+
 #[derive(Debug, Default)]
 pub struct OBJModel {
     pub vertices: Vec<Vector4>,
     pub tex_coords: Vec<Vector4>,
     pub normals: Vec<Vector4>,
     pub indices: Vec<OBJIndex>,
+    pub material_libraries: Vec<String>, // mtllib: files relative to the obj
+    pub groups: Vec<OBJGroup>,           // usemtl: the material of the faces that follow
+    pub objects: Vec<OBJObject>,         // o and g: names of the faces that follow
 }
+
+// faces from `start` (index into `OBJModel.indices`) up to the next group use the material
 #[derive(Debug, Default)]
+pub struct OBJGroup {
+    pub material: String,
+    pub start: usize,
+}
+
+// faces from `start` up to the next object belong to an object (o) or a group (g)
+#[derive(Debug, Default)]
+pub struct OBJObject {
+    pub name: String,
+    pub start: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct OBJIndex {
     pub vertex_index: usize,
     pub tex_coord_index: usize,
@@ -34,69 +56,553 @@ pub struct IndexedModel {
     pub indices: Vec<usize>,
 }
 
-pub fn load_mesh(filepath: &str) -> Mesh {
-    let file = File::open(filepath).unwrap();
-    let reader = BufReader::new(file);
+// a model split into one mesh per material, every part can be drawn as its own instance
+#[derive(Debug, Default)]
+pub struct Model {
+    pub parts: Vec<ModelPart>,
+}
+
+#[derive(Debug)]
+pub struct ModelPart {
+    pub name: String, // name of the material, empty for faces without one
+    pub mesh: Rc<Box<Mesh>>,
+    pub material: Rc<Material>,
+}
+
+// load the whole obj as a single mesh, materials are ignored
+pub fn load_mesh(filepath: &str) -> Result<Mesh, MeshLoadError> {
+    let model = parse_obj(filepath)?;
+
+    let indexed_model = to_indexed_model(model);
+    return Ok(to_mesh(indexed_model));
+}
+
+// like `load_mesh`, but positions closer than `epsilon` are welded first (see `weld_vertices`)
+pub fn load_mesh_welded(filepath: &str, epsilon: f32) -> Result<Mesh, MeshLoadError> {
+    let mut model = parse_obj(filepath)?;
+    weld_vertices(&mut model, epsilon);
 
+    let indexed_model = to_indexed_model(model);
+    return Ok(to_mesh(indexed_model));
+}
+
+// load the obj together with its material libraries (mtllib) and split it by material (usemtl)
+pub fn load_model(filepath: &str) -> Result<Model, MeshLoadError> {
+    let obj = parse_obj(filepath)?;
+
+    let directory = Path::new(filepath).parent().unwrap_or(Path::new(""));
+    let mut library = MaterialLibrary::new();
+    for material_library in obj.material_libraries.iter() {
+        library.load(&directory.join(material_library));
+    }
+
+    // collect the faces of each material, a material can be used more than once
+    //
+    // usemtl a -> f f f
+    // usemtl b -> f f
+    // usemtl a -> f
+    let mut names: Vec<String> = vec![];
+    let mut faces: HashMap<String, Vec<OBJIndex>> = HashMap::new();
+
+    let mut starts: Vec<(usize, &str)> = vec![(0, "")];
+    starts.extend(obj.groups.iter().map(|g| (g.start, g.material.as_str())));
+
+    for (i, (start, name)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(obj.indices.len(), |next| next.0);
+        if end <= *start {
+            continue;
+        }
+
+        if !faces.contains_key(*name) {
+            names.push(name.to_string());
+        }
+        faces
+            .entry(name.to_string())
+            .or_default()
+            .extend_from_slice(&obj.indices[*start..end]);
+    }
+
+    let mut model = Model::default();
+
+    for name in names {
+        let material = match library.materials.remove(&name) {
+            Some(material) => material,
+            None => {
+                if !name.is_empty() {
+                    log::warn!("material {name} is missing, it's drawn with the default material");
+                }
+                Material::default()
+            }
+        };
+
+        let indexed_model = index_faces(&obj, &faces[&name]);
+
+        model.parts.push(ModelPart {
+            name,
+            mesh: Rc::new(Box::new(to_mesh(indexed_model))),
+            material: Rc::new(material),
+        });
+    }
+
+    return Ok(model);
+}
+
+// everything that can go wrong when a mesh file is loaded, `line` and `column` start at 1 (0 when unknown or binary)
+#[derive(Debug)]
+pub struct MeshLoadError {
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: MeshLoadErrorKind,
+}
+
+#[derive(Debug)]
+pub enum MeshLoadErrorKind {
+    Io(io::Error),     // the file can't be opened or read
+    BadNumber(String), // a token that isn't a number (empty when the number is missing)
+    // a face index that is missing, zero or points past what's been defined (or a face with less than 3 corners)
+    BadIndex(String),
+    UnsupportedDirective(String), // a statement that the loader doesn't know
+}
+
+impl MeshLoadError {
+    pub fn new(path: &str, line: usize, column: usize, kind: MeshLoadErrorKind) -> Self {
+        return Self {
+            path: path.to_string(),
+            line,
+            column,
+            kind,
+        };
+    }
+}
+
+impl fmt::Display for MeshLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: ", self.path, self.line, self.column)?;
+
+        match &self.kind {
+            MeshLoadErrorKind::Io(err) => write!(f, "{err}"),
+            MeshLoadErrorKind::BadNumber(token) if token.is_empty() => write!(f, "missing number"),
+            MeshLoadErrorKind::BadNumber(token) => write!(f, "bad number '{token}'"),
+            MeshLoadErrorKind::BadIndex(token) => write!(f, "bad index '{token}'"),
+            MeshLoadErrorKind::UnsupportedDirective(directive) => {
+                write!(f, "unsupported directive '{directive}'")
+            }
+        }
+    }
+}
+
+impl Error for MeshLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            MeshLoadErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_obj(filepath: &str) -> Result<OBJModel, MeshLoadError> {
+    let file = File::open(filepath)
+        .map_err(|err| MeshLoadError::new(filepath, 0, 0, MeshLoadErrorKind::Io(err)))?;
+
+    return read_obj(BufReader::new(file), filepath);
+}
+
+// `path` is only used for the errors
+pub fn read_obj(reader: impl BufRead, path: &str) -> Result<OBJModel, MeshLoadError> {
     let mut model = OBJModel::default();
 
-    for line in reader.lines() {
-        if let Ok(text) = line {
-            let tokens: Vec<&str> = text.split(' ').collect();
+    // the corners of every triangle, texture coordinates and normals are filled in at the end
+    let mut corners: Vec<FaceCorner> = vec![];
+    let mut smoothing_groups: Vec<u32> = vec![]; // one for every triangle
+    let mut smoothing_group = 0;
+    let mut skipped: HashSet<String> = HashSet::new(); // every statement that's skipped is only logged once
+
+    for (line_index, line) in reader.lines().enumerate() {
+        let line_number = line_index + 1;
+        let text = line
+            .map_err(|err| MeshLoadError::new(path, line_number, 0, MeshLoadErrorKind::Io(err)))?;
+
+        let mut parser = LineParser::new(&text, path, line_number);
+
+        let directive = match parser.next() {
+            Some(directive) => directive,
+            None => continue, // empty line
+        };
+
+        if directive.starts_with('#') {
+            continue;
+        }
+
+        match directive {
+            "mtllib" => model.material_libraries.push(parser.rest()),
+            "usemtl" => model.groups.push(OBJGroup {
+                material: parser.rest(),
+                start: corners.len(),
+            }),
+            "o" | "g" => model.objects.push(OBJObject {
+                name: parser.rest(),
+                start: corners.len(),
+            }),
+            // faces in the same smoothing group share their normals, 0 (or off) makes them flat
+            "s" => smoothing_group = parser.smoothing_group()?,
+            "v" => model.vertices.push(Vector4::new(
+                parser.float()?,
+                parser.float()?,
+                parser.float()?,
+                1.0,
+            )),
+            "vt" => model.tex_coords.push(Vector4::new(
+                parser.float()?,
+                1.0 - parser.float()?,
+                0.0,
+                0.0,
+            )),
+            "vn" => model.normals.push(Vector4::new(
+                parser.float()?,
+                parser.float()?,
+                parser.float()?,
+                0.0,
+            )),
+            "f" => {
+                let mut polygon = vec![];
+                while let Some(token) = parser.next() {
+                    polygon.push(parser.face_corner(token, &model)?);
+                }
+
+                if polygon.len() < 3 {
+                    let face = text.trim().to_string();
+                    return Err(parser.error(directive, MeshLoadErrorKind::BadIndex(face)));
+                }
 
-            assert!(tokens.len() > 0);
+                let points: Vec<Vector4> = polygon
+                    .iter()
+                    .map(|corner| model.vertices[corner.vertex])
+                    .collect();
 
-            if tokens[0] == "#" {
-                continue;
+                for triangle in triangulate(&points) {
+                    corners.extend(triangle.iter().map(|&i| polygon[i]));
+                    smoothing_groups.push(smoothing_group);
+                }
+            }
+            // free-form curves and surfaces would just be missing from the mesh
+            "curv" | "curv2" | "surf" => {
+                return Err(parser.error(
+                    directive,
+                    MeshLoadErrorKind::UnsupportedDirective(directive.to_string()),
+                ))
             }
+            // everything else doesn't change the triangles (lines, points, merging groups, level of detail, ...)
+            _ => {
+                if skipped.insert(directive.to_string()) {
+                    log::warn!("{path}:{line_number}: skipping '{directive}' statements");
+                }
+            }
+        }
+    }
+
+    resolve_corners(&mut model, &corners, &smoothing_groups);
+
+    return Ok(model);
+}
+
+// a corner of a face as it's written in the obj, the texture coordinates and the normal are optional
+//
+// f v v v
+// f v/vt v/vt v/vt
+// f v//vn v//vn v//vn
+// f v/vt/vn v/vt/vn v/vt/vn
+#[derive(Debug, Clone, Copy)]
+struct FaceCorner {
+    vertex: usize,
+    tex_coord: Option<usize>,
+    normal: Option<usize>,
+}
+
+// turn the face corners into obj indices, the missing attributes are made up:
+// - texture coordinates are all (0, 0)
+// - normals are the average of the faces around the vertex in the same smoothing group, or the face normal when flat
+fn resolve_corners(model: &mut OBJModel, corners: &[FaceCorner], smoothing_groups: &[u32]) {
+    let mut default_tex_coord = None;
+    let mut smooth_normals: HashMap<(usize, u32), usize> = HashMap::new(); // (vertex, group) -> normal
+
+    // the normals are summed first and normalized at the end
+    let first_generated = model.normals.len();
+
+    for (triangle, &group) in corners.chunks_exact(3).zip(smoothing_groups) {
+        let p0 = model.vertices[triangle[0].vertex];
+        let p1 = model.vertices[triangle[1].vertex];
+        let p2 = model.vertices[triangle[2].vertex];
 
-            if tokens[0] == "v" {
-                model.vertices.push(Vector4::new(
-                    tokens[1].parse::<f32>().unwrap(),
-                    tokens[2].parse::<f32>().unwrap(),
-                    tokens[3].parse::<f32>().unwrap(),
-                    1.0,
-                ));
-            } else if tokens[0] == "vt" {
-                model.tex_coords.push(Vector4::new(
-                    tokens[1].parse::<f32>().unwrap(),
-                    1.0 - tokens[2].parse::<f32>().unwrap(),
-                    0.0,
-                    0.0,
-                ));
-            } else if tokens[0] == "vn" {
-                model.normals.push(Vector4::new(
-                    tokens[1].parse::<f32>().unwrap(),
-                    tokens[2].parse::<f32>().unwrap(),
-                    tokens[3].parse::<f32>().unwrap(),
-                    0.0,
-                ));
-            } else if tokens[0] == "f" {
-                for i in 1..tokens.len() {
-                    let index_tokens: Vec<&str> = tokens[i].split('/').collect();
-
-                    let mut index = OBJIndex::default();
-                    index.vertex_index = index_tokens[0].parse::<usize>().unwrap() - 1;
-                    index.tex_coord_index = index_tokens[1].parse::<usize>().unwrap() - 1;
-                    index.normal_index = index_tokens[2].parse::<usize>().unwrap() - 1;
-
-                    model.indices.push(index);
+        // not normalized so bigger faces have more weight
+        let face_normal = (p1 - p0).cross(p2 - p0);
+        let mut flat_normal = None;
+
+        for corner in triangle {
+            let tex_coord_index = match corner.tex_coord {
+                Some(index) => index,
+                None => *default_tex_coord.get_or_insert_with(|| {
+                    model.tex_coords.push(Vector4::ZERO);
+                    model.tex_coords.len() - 1
+                }),
+            };
+
+            let normal_index = match corner.normal {
+                Some(index) => index,
+                None if group == 0 => *flat_normal.get_or_insert_with(|| {
+                    model.normals.push(face_normal);
+                    model.normals.len() - 1
+                }),
+                None => {
+                    let index =
+                        *smooth_normals
+                            .entry((corner.vertex, group))
+                            .or_insert_with(|| {
+                                model.normals.push(Vector4::ZERO);
+                                model.normals.len() - 1
+                            });
+                    model.normals[index] += face_normal;
+                    index
                 }
+            };
+
+            model.indices.push(OBJIndex {
+                vertex_index: corner.vertex,
+                tex_coord_index,
+                normal_index,
+            });
+        }
+    }
+
+    for normal in model.normals[first_generated..].iter_mut() {
+        if normal.length() > 0.0 {
+            *normal = normal.normalized();
+        }
+    }
+}
+
+// split a polygon into triangles by clipping its ears, concave polygons work too (as long as they don't cross themselves)
+// the triangles keep the winding of the polygon and are returned as indices into `points`
+//
+// 4 ------- 3
+// |         |
+// |    1 -- 2      the ear at 0 (5, 0, 1) can't be clipped because 1 isn't convex
+// |    |           but the ear at 2 (1, 2, 3) can
+// 5 -- 0
+pub fn triangulate(points: &[Vector4]) -> Vec<[usize; 3]> {
+    if points.len() == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    // the polygon is flattened onto the plane that it faces the most (newell's method for the normal)
+    let mut normal = Vector4::ZERO;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        normal += Vector4::new(
+            (a.y - b.y) * (a.z + b.z),
+            (a.z - b.z) * (a.x + b.x),
+            (a.x - b.x) * (a.y + b.y),
+            0.0,
+        );
+    }
+
+    let (nx, ny, nz) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+    let flat: Vec<(f32, f32)> = points
+        .iter()
+        .map(|p| {
+            if nx >= ny && nx >= nz {
+                (p.y, p.z)
+            } else if ny >= nz {
+                (p.z, p.x)
+            } else {
+                (p.x, p.y)
             }
+        })
+        .collect();
+
+    // the sign of the dropped axis decides which way counter-clockwise is
+    let dominant = if nx >= ny && nx >= nz {
+        normal.x
+    } else if ny >= nz {
+        normal.y
+    } else {
+        normal.z
+    };
+    let orientation = if dominant < 0.0 { -1.0 } else { 1.0 };
+
+    let cross = |a: usize, b: usize, c: usize| -> f32 {
+        let (ax, ay) = flat[a];
+        let (bx, by) = flat[b];
+        let (cx, cy) = flat[c];
+        return ((bx - ax) * (cy - ay) - (by - ay) * (cx - ax)) * orientation;
+    };
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = vec![];
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+
+        // starting from the second corner makes convex polygons the same as a fan from the first corner
+        let ear = (1..=n).map(|i| i % n).find(|&i| {
+            let a = remaining[(i + n - 1) % n];
+            let b = remaining[i];
+            let c = remaining[(i + 1) % n];
+
+            if cross(a, b, c) <= 0.0 {
+                return false; // reflex (or flat) corner
+            }
+
+            // no other corner can be inside of the ear
+            return !remaining.iter().any(|&p| {
+                p != a
+                    && p != b
+                    && p != c
+                    && cross(a, b, p) >= 0.0
+                    && cross(b, c, p) >= 0.0
+                    && cross(c, a, p) >= 0.0
+            });
+        });
+
+        // a broken polygon (self intersecting or all points on a line) is closed with a fan
+        let Some(i) = ear else {
+            for i in 1..n - 1 {
+                triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+            }
+            return triangles;
+        };
+
+        triangles.push([
+            remaining[(i + n - 1) % n],
+            remaining[i],
+            remaining[(i + 1) % n],
+        ]);
+        remaining.remove(i);
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+
+    return triangles;
+}
+
+// splits a line into tokens and remembers where each token is for the errors (used by the other text formats too)
+pub struct LineParser<'a> {
+    text: &'a str,
+    tokens: std::str::SplitWhitespace<'a>,
+    path: &'a str,
+    line: usize,
+}
+
+// the tokens of the line one after another
+impl<'a> Iterator for LineParser<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        return self.tokens.next();
+    }
+}
+
+impl<'a> LineParser<'a> {
+    pub fn new(text: &'a str, path: &'a str, line: usize) -> Self {
+        return Self {
+            text,
+            tokens: text.split_whitespace(),
+            path,
+            line,
+        };
+    }
+
+    // everything after the directive, names can have spaces in them
+    pub fn rest(&mut self) -> String {
+        return self.tokens.by_ref().collect::<Vec<_>>().join(" ");
+    }
+
+    // column of a token that came out of this line (1 based)
+    pub fn column(&self, token: &str) -> usize {
+        return token.as_ptr() as usize - self.text.as_ptr() as usize + 1;
+    }
+
+    pub fn error(&self, token: &str, kind: MeshLoadErrorKind) -> MeshLoadError {
+        return MeshLoadError::new(self.path, self.line, self.column(token), kind);
+    }
+
+    pub fn float(&mut self) -> Result<f32, MeshLoadError> {
+        match self.next() {
+            Some(token) => token
+                .parse::<f32>()
+                .map_err(|_| self.error(token, MeshLoadErrorKind::BadNumber(token.to_string()))),
+            None => Err(MeshLoadError::new(
+                self.path,
+                self.line,
+                self.text.trim_end().len() + 1,
+                MeshLoadErrorKind::BadNumber(String::new()),
+            )),
         }
     }
 
-    let indexed_model = to_indexed_model(model);
+    fn smoothing_group(&mut self) -> Result<u32, MeshLoadError> {
+        match self.next() {
+            Some("off") => Ok(0),
+            Some(token) => token
+                .parse::<u32>()
+                .map_err(|_| self.error(token, MeshLoadErrorKind::BadNumber(token.to_string()))),
+            None => Ok(0),
+        }
+    }
+
+    // v, v/vt, v//vn or v/vt/vn where every index must already be defined
+    // indices start at 1, negative ones count back from the last one that's been defined (-1 is the last)
+    fn face_corner(&self, token: &str, model: &OBJModel) -> Result<FaceCorner, MeshLoadError> {
+        let bad_index = || self.error(token, MeshLoadErrorKind::BadIndex(token.to_string()));
+
+        let index = |part: &str, count: usize| -> Result<usize, MeshLoadError> {
+            let value = part.parse::<i64>().map_err(|_| bad_index())?;
+            let count = count as i64;
+
+            let index = if value < 0 { count + value } else { value - 1 };
+            if value == 0 || index < 0 || index >= count {
+                return Err(bad_index());
+            }
+            return Ok(index as usize);
+        };
+
+        let parts: Vec<&str> = token.split('/').collect();
+        if parts.len() > 3 {
+            return Err(bad_index());
+        }
+
+        let optional = |i: usize, count: usize| -> Result<Option<usize>, MeshLoadError> {
+            match parts.get(i) {
+                Some(part) if !part.is_empty() => Ok(Some(index(part, count)?)),
+                _ => Ok(None),
+            }
+        };
+
+        return Ok(FaceCorner {
+            vertex: index(parts[0], model.vertices.len())?,
+            tex_coord: optional(1, model.tex_coords.len())?,
+            normal: optional(2, model.normals.len())?,
+        });
+    }
+}
+
+// make a new mesh out of an indexed model, tangents are generated on the way
+pub fn to_mesh(mut indexed_model: IndexedModel) -> Mesh {
+    calc_tangents(&mut indexed_model);
 
     // make a new mesh and add all indexed-vertices/tex-coords/normals into it
     let mut mesh = Mesh::default();
 
     for i in 0..indexed_model.vertices.len() {
-        mesh.vertices.push(Vertex::new(
-            indexed_model.vertices[i],
-            indexed_model.tex_coords[i],
-            indexed_model.normals[i],
-        ));
+        mesh.vertices.push(
+            Vertex::new(
+                indexed_model.vertices[i],
+                indexed_model.tex_coords[i],
+                indexed_model.normals[i],
+            )
+            .with_tangent(indexed_model.tangents[i]),
+        );
     }
 
     mesh.indices = vec![0; indexed_model.indices.len()];
@@ -109,54 +615,542 @@ pub fn load_mesh(filepath: &str) -> Mesh {
 }
 
 pub fn to_indexed_model(obj: OBJModel) -> IndexedModel {
+    return index_faces(&obj, &obj.indices);
+}
+
+// index some of the faces of the obj, vertices that the faces don't use are left out
+// every (v, vt, vn) triple becomes one vertex, in the order they are first used
+pub fn index_faces(obj: &OBJModel, indices: &[OBJIndex]) -> IndexedModel {
     let mut model = IndexedModel::default();
-    let mut index_map = HashMap::new(); // OBJModel.indices -> IndexedModel.indices
-    let mut curr_vertex_index = 0;
-
-    // index the model in parallel
-    let results: Vec<(usize, Option<usize>, Vector4, Vector4, Vector4)> = obj
-        .indices
-        .par_iter()
-        .enumerate()
-        .map(|(i, curr_index)| {
-            let curr_pos = obj.vertices[curr_index.vertex_index];
-            let curr_tex_coord = obj.tex_coords[curr_index.tex_coord_index];
-            let curr_normal = obj.normals[curr_index.normal_index];
-
-            // Check for duplicates O(n^2)
-            let mut prev_index = None;
-
-            for j in 0..i {
-                let old_index = &obj.indices[j];
-
-                if curr_index.vertex_index == old_index.vertex_index
-                    && curr_index.tex_coord_index == old_index.tex_coord_index
-                    && curr_index.normal_index == old_index.normal_index
-                {
-                    prev_index = Some(j);
-                    break;
+    // obj index -> IndexedModel.indices
+    let mut index_map: HashMap<OBJIndex, usize> = HashMap::with_capacity(indices.len());
+
+    for curr_index in indices {
+        let next_vertex_index = model.vertices.len();
+        let vertex_index = *index_map.entry(*curr_index).or_insert(next_vertex_index);
+
+        if vertex_index == next_vertex_index {
+            model.vertices.push(obj.vertices[curr_index.vertex_index]);
+            model
+                .tex_coords
+                .push(obj.tex_coords[curr_index.tex_coord_index]);
+            model.normals.push(obj.normals[curr_index.normal_index]);
+        }
+
+        model.indices.push(vertex_index);
+    }
+
+    return model;
+}
+
+// make the faces use the same position for vertices that are closer than `epsilon` to each other (the first one wins)
+// it closes the cracks between faces that were exported with their own copies of the positions
+//
+// the positions are put into a grid of epsilon sized cells, only the 27 cells around a position have to be checked
+// an epsilon that isn't above zero (or is nan) has no cells, nothing is welded then
+pub fn weld_vertices(obj: &mut OBJModel, epsilon: f32) {
+    if epsilon.is_nan() || epsilon <= 0.0 {
+        return;
+    }
+
+    let cell = |p: Vector4| -> (i64, i64, i64) {
+        return (
+            (p.x / epsilon).floor() as i64,
+            (p.y / epsilon).floor() as i64,
+            (p.z / epsilon).floor() as i64,
+        );
+    };
+
+    let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+    let mut remap = vec![0; obj.vertices.len()];
+
+    for (i, &position) in obj.vertices.iter().enumerate() {
+        let (cx, cy, cz) = cell(position);
+
+        let mut found = None;
+        // far away positions (or a tiny epsilon) saturate the cells at the edges of i64
+        let around = |c: i64| c.saturating_sub(1)..=c.saturating_add(1);
+
+        'search: for x in around(cx) {
+            for y in around(cy) {
+                for z in around(cz) {
+                    let Some(candidates) = grid.get(&(x, y, z)) else {
+                        continue;
+                    };
+                    found = candidates
+                        .iter()
+                        .copied()
+                        .find(|&j| (obj.vertices[j] - position).length() <= epsilon);
+                    if found.is_some() {
+                        break 'search;
+                    }
                 }
             }
+        }
 
-            return (i, prev_index, curr_pos, curr_tex_coord, curr_normal);
-        })
-        .collect();
+        remap[i] = match found {
+            Some(j) => j,
+            None => {
+                grid.entry((cx, cy, cz)).or_default().push(i);
+                i
+            }
+        };
+    }
+
+    for index in obj.indices.iter_mut() {
+        index.vertex_index = remap[index.vertex_index];
+    }
+}
+
+// smooth per-vertex normals: the average of the faces around each vertex, bigger faces have more weight
+pub fn calc_normals(model: &mut IndexedModel) {
+    model.normals = vec![Vector4::ZERO; model.vertices.len()];
+
+    for triangle in model.indices.chunks_exact(3) {
+        let (i0, i1, i2) = (triangle[0], triangle[1], triangle[2]);
+
+        let edge1 = model.vertices[i1] - model.vertices[i0];
+        let edge2 = model.vertices[i2] - model.vertices[i0];
+        let normal = edge1.cross(edge2);
+
+        for i in [i0, i1, i2] {
+            model.normals[i] += normal;
+        }
+    }
+
+    for normal in model.normals.iter_mut() {
+        if normal.length() > 0.0 {
+            *normal = normal.normalized();
+        }
+    }
+}
+
+// per-vertex tangents for normal mapping, they point where u grows on the surface
+// w is the handedness: bitangent = cross(normal, tangent) * w, it's -1 where the uv-map is mirrored
+//
+// the tangents of the faces around a vertex are summed up (weighted by their size) and made perpendicular to the
+// normal (gram-schmidt), this is not mikktspace: normal maps that were baked in mikktspace (blender, substance and
+// most gltf files) are a little off at uv seams and where vertices were split, the tangents of a gltf file are used
+// instead when it has them
+//
+//        bitangent (v)
+//           |
+//           |
+//   normal  o------ tangent (u)
+//
+// v is flipped when the obj is loaded (1 - v) so the bitangent is found from -v, just like in the original uv-space
+// that way normal maps made for OpenGL (green is up) work without flipping their green channel
+pub fn calc_tangents(model: &mut IndexedModel) {
+    let mut tangents = vec![Vector4::ZERO; model.vertices.len()];
+    let mut bitangents = vec![Vector4::ZERO; model.vertices.len()];
 
-    // add results to the model
-    for (i, prev_index, curr_pos, curr_tex_coord, curr_normal) in results {
-        if let Some(value) = prev_index {
-            model.indices.push(*index_map.get(&value).unwrap());
+    for triangle in model.indices.chunks_exact(3) {
+        let (i0, i1, i2) = (triangle[0], triangle[1], triangle[2]);
+
+        let edge1 = model.vertices[i1] - model.vertices[i0];
+        let edge2 = model.vertices[i2] - model.vertices[i0];
+
+        let du1 = model.tex_coords[i1].x - model.tex_coords[i0].x;
+        let dv1 = model.tex_coords[i0].y - model.tex_coords[i1].y;
+        let du2 = model.tex_coords[i2].x - model.tex_coords[i0].x;
+        let dv2 = model.tex_coords[i0].y - model.tex_coords[i2].y;
+
+        // solve: edge = du * tangent + dv * bitangent
+        let determinant = du1 * dv2 - du2 * dv1;
+        if determinant.abs() <= f32::EPSILON {
+            continue; // the uv-map of the triangle is degenerate
+        }
+        let r = 1.0 / determinant;
+
+        // the sum isn't normalized so bigger triangles have more weight
+        let tangent = (edge1 * dv2 - edge2 * dv1) * r;
+        let bitangent = (edge2 * du1 - edge1 * du2) * r;
+
+        for i in [i0, i1, i2] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    model.tangents = vec![Vector4::ZERO; model.vertices.len()];
+
+    for i in 0..model.vertices.len() {
+        let normal = model.normals[i];
+
+        // gram-schmidt: make the tangent perpendicular to the normal
+        let mut tangent = tangents[i] - normal * normal.dot(tangents[i]);
+        tangent.w = 0.0;
+
+        if tangent.length() <= f32::EPSILON {
+            continue;
+        }
+        tangent = tangent.normalized();
+
+        // the bitangent points the other way on mirrored uv-maps
+        tangent.w = if normal.cross(tangent).dot(bitangents[i]) < 0.0 {
+            -1.0
         } else {
-            index_map.insert(i, curr_vertex_index);
+            1.0
+        };
 
-            model.vertices.push(curr_pos);
-            model.tex_coords.push(curr_tex_coord);
-            model.normals.push(curr_normal);
-            model.indices.push(curr_vertex_index);
+        model.tangents[i] = tangent;
+    }
+}
 
-            curr_vertex_index += 1;
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::app::TestDir;
+
+    // a quad facing +z with the uv-map laid out like the obj loader does it (v is flipped)
+    //
+    // 3 ---- 2
+    // |      |
+    // 0 ---- 1
+    fn quad(mirrored: bool) -> IndexedModel {
+        let u = if mirrored { 1.0 } else { 0.0 };
+
+        IndexedModel {
+            vertices: vec![
+                Vector4::new(0.0, 0.0, 0.0, 1.0),
+                Vector4::new(2.0, 0.0, 0.0, 1.0),
+                Vector4::new(2.0, 2.0, 0.0, 1.0),
+                Vector4::new(0.0, 2.0, 0.0, 1.0),
+            ],
+            tex_coords: vec![
+                Vector4::new(u, 1.0, 0.0, 0.0),
+                Vector4::new(1.0 - u, 1.0, 0.0, 0.0),
+                Vector4::new(1.0 - u, 0.0, 0.0, 0.0),
+                Vector4::new(u, 0.0, 0.0, 0.0),
+            ],
+            normals: vec![Vector4::new(0.0, 0.0, 1.0, 0.0); 4],
+            tangents: vec![],
+            indices: vec![0, 1, 2, 0, 2, 3],
         }
     }
 
-    return model;
+    #[test]
+    fn test_load_model_materials() {
+        let directory = TestDir::new("obj");
+
+        std::fs::write(directory.join("parts.mtl"), "newmtl red\nKd 1 0 0\n").unwrap();
+        std::fs::write(
+            directory.join("parts.obj"),
+            "\
+mtllib parts.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1
+usemtl red
+f 1/1/1 3/3/1 4/4/1
+usemtl blue
+f 1/1/1 2/2/1 4/4/1
+usemtl red
+f 2/2/1 3/3/1 4/4/1
+",
+        )
+        .unwrap();
+
+        let model = load_model(directory.join("parts.obj").to_str().unwrap()).unwrap();
+
+        // faces before the first usemtl, then each material once in the order they were first used
+        let names: Vec<_> = model.parts.iter().map(|part| part.name.as_str()).collect();
+        assert_eq!(names, vec!["", "red", "blue"]);
+
+        let triangles: Vec<_> = model
+            .parts
+            .iter()
+            .map(|part| part.mesh.indices.len() / 3)
+            .collect();
+        assert_eq!(triangles, vec![1, 2, 1]);
+
+        // only the vertices that the part uses
+        assert_eq!(model.parts[0].mesh.vertices.len(), 3);
+        assert_eq!(model.parts[1].mesh.vertices.len(), 4);
+
+        assert_eq!(
+            model.parts[1].material.color,
+            Vector4::new(1.0, 0.0, 0.0, 1.0)
+        );
+
+        // blue isn't in the library so it's the default (white)
+        assert_eq!(
+            model.parts[2].material.color,
+            Vector4::new(1.0, 1.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let read = |obj: &str| read_obj(Cursor::new(obj), "test.obj").err().unwrap();
+
+        // the line and column point at the token that couldn't be parsed
+        let err = read("v 0 0 0\nv 1 0x 0\n");
+        assert_eq!((err.line, err.column), (2, 5));
+        assert!(matches!(err.kind, MeshLoadErrorKind::BadNumber(ref token) if token == "0x"));
+        assert_eq!(err.to_string(), "test.obj:2:5: bad number '0x'");
+
+        let err = read("v 0 0\n");
+        assert!(matches!(err.kind, MeshLoadErrorKind::BadNumber(ref token) if token.is_empty()));
+
+        // indices start at 1 and must point at something that's already defined
+        let quad = "v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0 0\nvn 0 0 1\n";
+        let err = read(&format!("{quad}f 1/1/1 2/1/1 4/1/1\n"));
+        assert_eq!((err.line, err.column), (6, 15));
+        assert!(matches!(err.kind, MeshLoadErrorKind::BadIndex(ref token) if token == "4/1/1"));
+
+        let err = read(&format!("{quad}f 1/1/1 0/1/1 3/1/1\n"));
+        assert!(matches!(err.kind, MeshLoadErrorKind::BadIndex(_)));
+
+        // comments, objects and smoothing groups are fine but curves and surfaces can't be drawn
+        let err = read(&format!("# comment\no quad\ns 1\n{quad}curv 0 1 1 2\n"));
+        assert_eq!((err.line, err.column), (9, 1));
+        assert!(matches!(err.kind, MeshLoadErrorKind::UnsupportedDirective(ref d) if d == "curv"));
+
+        // the statements that don't make triangles are skipped
+        let obj = format!("{quad}f 1 2 3\nl 1 2\np 3\nvp 0.5\ncstype bspline\nmg 1\nlod 2\nfoo\n");
+        let model = read_obj(Cursor::new(obj), "test.obj").unwrap();
+        assert_eq!(model.indices.len(), 3);
+
+        // missing files don't panic
+        let err = load_mesh("./missing.obj").err().unwrap();
+        assert!(matches!(err.kind, MeshLoadErrorKind::Io(_)));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn test_face_syntax() {
+        // a quad written in every way a face corner can be written, with negative indices for the last corner
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 1
+vn 0 0 1
+f 1 2 3 4
+f 1/1 2/1 3/2 -1/-1
+f 1//1 2//1 3//1 -1//-1
+f 1/1/1 2/1/1 3/2/1 -1/-1/-1
+";
+        let model = read_obj(Cursor::new(obj), "test.obj").unwrap();
+
+        // every quad becomes 2 triangles
+        assert_eq!(model.indices.len(), 4 * 6);
+        assert!(model.indices.iter().all(|index| index.vertex_index < 4));
+
+        let last = model.indices[3 * 6 + 2];
+        assert_eq!((last.vertex_index, last.tex_coord_index), (2, 1));
+
+        // missing texture coordinates are (0, 0) and missing normals are made from the faces
+        let first = model.indices[0];
+        assert_eq!(model.tex_coords[first.tex_coord_index], Vector4::ZERO);
+        assert_eq!(
+            model.normals[first.normal_index],
+            Vector4::new(0.0, 0.0, 1.0, 0.0)
+        );
+
+        let err = read_obj(Cursor::new("v 0 0 0\nf 1 -2 1\n"), "test.obj").err();
+        assert!(matches!(err.unwrap().kind, MeshLoadErrorKind::BadIndex(_)));
+
+        let err = read_obj(Cursor::new("v 0 0 0\nf 1 1\n"), "test.obj").err();
+        assert!(matches!(err.unwrap().kind, MeshLoadErrorKind::BadIndex(_)));
+    }
+
+    #[test]
+    fn test_smoothing_groups() {
+        // two faces folded along the edge 1-2
+        //
+        //  3
+        //  | \
+        //  1 - 2
+        //  | /
+        //  0
+        let corners = "\
+v 0 0 0
+v 0 1 0
+v 1 1 1
+v 0 2 0
+o left
+s 1
+f 1 3 2
+g right
+s 1
+f 2 3 4
+";
+        let model = read_obj(Cursor::new(corners), "test.obj").unwrap();
+        let names: Vec<_> = model
+            .objects
+            .iter()
+            .map(|o| (o.name.as_str(), o.start))
+            .collect();
+        assert_eq!(names, vec![("left", 0), ("right", 3)]);
+
+        // the shared edge has a single normal that is between the two faces
+        assert_eq!(model.indices[1].normal_index, model.indices[4].normal_index);
+        assert_eq!(model.normals.len(), 4);
+
+        // flat faces don't share anything
+        let flat = corners.replace("s 1", "s off");
+        let model = read_obj(Cursor::new(flat), "test.obj").unwrap();
+        assert_ne!(model.indices[1].normal_index, model.indices[4].normal_index);
+        assert_eq!(model.normals.len(), 2);
+    }
+
+    #[test]
+    fn test_triangulate() {
+        let area = |points: &[Vector4], triangles: &[[usize; 3]]| -> f32 {
+            triangles
+                .iter()
+                .map(|t| {
+                    (points[t[1]] - points[t[0]])
+                        .cross(points[t[2]] - points[t[0]])
+                        .z
+                        * 0.5
+                })
+                .sum()
+        };
+
+        // concave L-shape, a fan from the first corner would cover the notch
+        //
+        // 4 ------- 3
+        // |         |
+        // |    1 -- 2
+        // |    |
+        // 5 -- 0
+        let points = [
+            Vector4::new(1.0, 0.0, 0.0, 1.0),
+            Vector4::new(1.0, 1.0, 0.0, 1.0),
+            Vector4::new(2.0, 1.0, 0.0, 1.0),
+            Vector4::new(2.0, 2.0, 0.0, 1.0),
+            Vector4::new(0.0, 2.0, 0.0, 1.0),
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+        ];
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), 4);
+
+        // the triangles cover the polygon exactly and keep its counter-clockwise winding
+        assert!(triangles.iter().all(|t| area(&points, &[*t]) > 0.0));
+        assert!((area(&points, &triangles) - 3.0).abs() < 0.0001);
+
+        // the same polygon wound the other way (facing -z)
+        let mut reversed = points;
+        reversed.reverse();
+        let triangles = triangulate(&reversed);
+        assert!(triangles.iter().all(|t| area(&reversed, &[*t]) < 0.0));
+        assert!((area(&reversed, &triangles) + 3.0).abs() < 0.0001);
+    }
+
+    // the nested scan that used to do the indexing, the hash map must give the same result
+    fn index_faces_quadratic(obj: &OBJModel) -> IndexedModel {
+        let mut model = IndexedModel::default();
+        let mut index_map = HashMap::new();
+
+        for (i, curr_index) in obj.indices.iter().enumerate() {
+            match obj.indices[..i].iter().position(|old| old == curr_index) {
+                Some(j) => model.indices.push(index_map[&j]),
+                None => {
+                    index_map.insert(i, model.vertices.len());
+                    model.indices.push(model.vertices.len());
+                    model.vertices.push(obj.vertices[curr_index.vertex_index]);
+                    model
+                        .tex_coords
+                        .push(obj.tex_coords[curr_index.tex_coord_index]);
+                    model.normals.push(obj.normals[curr_index.normal_index]);
+                }
+            }
+        }
+
+        model
+    }
+
+    #[test]
+    fn test_index_faces() {
+        for path in ["../../assets/box.obj", "../../assets/turtle.obj"] {
+            let obj = parse_obj(path).unwrap();
+
+            let expected = index_faces_quadratic(&obj);
+            let indexed = to_indexed_model(obj);
+
+            assert_eq!(indexed.indices, expected.indices);
+            assert_eq!(indexed.vertices, expected.vertices);
+            assert_eq!(indexed.tex_coords, expected.tex_coords);
+            assert_eq!(indexed.normals, expected.normals);
+        }
+    }
+
+    #[test]
+    fn test_weld_vertices() {
+        // two triangles that should share an edge, but the positions are a bit off
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 1.0001 0 0
+v 0 0.9999 0
+v 1 1 0
+f 1 2 3
+f 4 6 5
+";
+        let mut model = read_obj(Cursor::new(obj), "test.obj").unwrap();
+        weld_vertices(&mut model, 0.001);
+
+        let vertices: Vec<_> = model.indices.iter().map(|i| i.vertex_index).collect();
+        assert_eq!(vertices, vec![0, 1, 2, 1, 5, 2]);
+
+        // far apart positions are left alone
+        let mut model = read_obj(Cursor::new(obj), "test.obj").unwrap();
+        weld_vertices(&mut model, 0.00001);
+        let vertices: Vec<_> = model.indices.iter().map(|i| i.vertex_index).collect();
+        assert_eq!(vertices, vec![0, 1, 2, 3, 5, 4]);
+
+        // no welding without a positive epsilon
+        for epsilon in [0.0, -1.0, f32::NAN] {
+            let mut model = read_obj(Cursor::new(obj), "test.obj").unwrap();
+            weld_vertices(&mut model, epsilon);
+            let vertices: Vec<_> = model.indices.iter().map(|i| i.vertex_index).collect();
+            assert_eq!(vertices, vec![0, 1, 2, 3, 5, 4]);
+        }
+
+        // huge positions land in the last cells without overflowing
+        let far = "v 1e30 0 0\nv 1e30 0 0\nv -1e30 0 0\nf 1 2 3\n";
+        let mut model = read_obj(Cursor::new(far), "test.obj").unwrap();
+        weld_vertices(&mut model, 1e-30);
+        let vertices: Vec<_> = model.indices.iter().map(|i| i.vertex_index).collect();
+        assert_eq!(vertices, vec![0, 0, 2]);
+    }
+
+    #[test]
+    fn test_calc_tangents() {
+        let mut model = quad(false);
+        calc_tangents(&mut model);
+
+        for tangent in model.tangents.iter() {
+            assert!((tangent.x - 1.0).abs() < 0.0001);
+            assert!(tangent.y.abs() < 0.0001 && tangent.z.abs() < 0.0001);
+            assert_eq!(tangent.w, 1.0);
+        }
+
+        // mirrored uv-map: the tangent flips and so does the handedness, the bitangent still points up
+        let mut model = quad(true);
+        calc_tangents(&mut model);
+
+        for (tangent, normal) in model.tangents.iter().zip(model.normals.iter()) {
+            assert!((tangent.x + 1.0).abs() < 0.0001);
+            assert_eq!(tangent.w, -1.0);
+
+            let bitangent = normal.cross(*tangent) * tangent.w;
+            assert!((bitangent.y - 1.0).abs() < 0.0001);
+        }
+    }
 }
@@ -1,5 +1,46 @@
 pub mod camera;
+pub mod cascades;
+pub mod cube_shadows;
+pub mod gltf_loader;
 pub mod instance;
+pub mod mesh_cache;
 pub mod mesh_loader;
+pub mod morph;
+pub mod mtl_loader;
+pub mod ply_loader;
 pub mod renderer;
+pub mod scene;
+pub mod skeleton;
+pub mod stl_loader;
 pub mod timestep;
+
+// a temporary directory that only one test writes into, the process id keeps test runs at the same time
+// apart (two checkouts, ci), it's removed with everything in it when it's dropped
+#[cfg(test)]
+pub(crate) struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub fn new(test: &str) -> Self {
+        let name = format!("soft-rend-{}-{test}", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,282 @@
+use std::{error::Error, fmt, rc::Rc};
+
+use crate::graphics::mesh::Mesh;
+
+use super::{
+    instance::Instance,
+    mesh_loader::{load_mesh, MeshLoadError},
+};
+
+// vertex (morph) animation: a sequence of keyframes of the same mesh, only the vertices move
+//
+// frames:   0 ----- 1 ----- 2 ----- 3
+// time:     |<----->|                     frame duration
+//
+// once:      0 1 2 3 3 3 ...              stops on the last frame
+// loop:      0 1 2 3 0 1 2 3 ...          the last frame blends back into the first
+// ping-pong: 0 1 2 3 2 1 0 1 ...          plays backwards from the last frame
+//
+// between two frames the positions and normals are blended, everything else (texcoords, colors and the
+// triangles) comes from the first frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playback {
+    Once,
+    Loop,
+    PingPong,
+}
+
+#[derive(Debug)]
+pub enum MorphError {
+    Load(MeshLoadError),
+    NoFrames,
+    // every frame needs the same vertices and triangles as the first one
+    VertexCountMismatch {
+        frame: usize,
+        expected: usize,
+        actual: usize,
+    },
+    IndexMismatch {
+        frame: usize,
+    },
+}
+
+impl fmt::Display for MorphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MorphError::Load(err) => write!(f, "{err}"),
+            MorphError::NoFrames => write!(f, "a morph animation needs at least one frame"),
+            MorphError::VertexCountMismatch {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "frame {frame} has {actual} vertices, the first frame has {expected}"
+            ),
+            MorphError::IndexMismatch { frame } => write!(
+                f,
+                "frame {frame} has different triangles than the first frame"
+            ),
+        }
+    }
+}
+
+impl Error for MorphError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MorphError::Load(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<MeshLoadError> for MorphError {
+    fn from(err: MeshLoadError) -> Self {
+        MorphError::Load(err)
+    }
+}
+
+#[derive(Debug)]
+pub struct MorphAnimation {
+    frames: Vec<Mesh>,
+    pub frame_duration: f32, // seconds from one frame to the next
+    pub playback: Playback,
+    pub time: f32,
+    pub speed: f32, // 1 is normal speed, negative plays backwards
+}
+
+impl MorphAnimation {
+    // the frames are checked against the first one
+    pub fn new(
+        frames: Vec<Mesh>,
+        frame_duration: f32,
+        playback: Playback,
+    ) -> Result<Self, MorphError> {
+        let first = frames.first().ok_or(MorphError::NoFrames)?;
+
+        for (frame, mesh) in frames.iter().enumerate().skip(1) {
+            if mesh.vertices.len() != first.vertices.len() {
+                return Err(MorphError::VertexCountMismatch {
+                    frame,
+                    expected: first.vertices.len(),
+                    actual: mesh.vertices.len(),
+                });
+            }
+            if mesh.indices != first.indices {
+                return Err(MorphError::IndexMismatch { frame });
+            }
+        }
+
+        Ok(Self {
+            frames,
+            frame_duration,
+            playback,
+            time: 0.0,
+            speed: 1.0,
+        })
+    }
+
+    // one obj file per frame, in order
+    pub fn load(
+        paths: &[&str],
+        frame_duration: f32,
+        playback: Playback,
+    ) -> Result<Self, MorphError> {
+        let frames = paths
+            .iter()
+            .map(|path| load_mesh(path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Self::new(frames, frame_duration, playback)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    // seconds until the animation repeats (or stops for `Once`)
+    pub fn duration(&self) -> f32 {
+        let count = self.frames.len() as f32;
+        match self.playback {
+            Playback::Once => (count - 1.0) * self.frame_duration,
+            Playback::Loop => count * self.frame_duration,
+            Playback::PingPong => 2.0 * (count - 1.0) * self.frame_duration,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt * self.speed;
+
+        // keep the time small so it doesn't lose precision, `Once` has to remember that it's finished
+        let duration = self.duration();
+        if duration > 0.0 {
+            self.time = match self.playback {
+                Playback::Once => self.time.clamp(0.0, duration),
+                Playback::Loop | Playback::PingPong => self.time.rem_euclid(duration),
+            };
+        }
+    }
+
+    // the two frames around `time` and how far it is from the first to the second (0 - 1)
+    pub fn frames_at(&self, time: f32) -> (usize, usize, f32) {
+        let count = self.frames.len();
+        if count == 1 || self.frame_duration <= 0.0 {
+            return (0, 0, 0.0);
+        }
+
+        let last = (count - 1) as f32;
+        let t = time / self.frame_duration; // in frames
+
+        match self.playback {
+            Playback::Once => {
+                let t = t.clamp(0.0, last);
+                let from = (t.floor() as usize).min(count - 2);
+                (from, from + 1, t - from as f32)
+            }
+            Playback::Loop => {
+                let t = t.rem_euclid(count as f32);
+                let from = (t.floor() as usize).min(count - 1);
+                (from, (from + 1) % count, t - from as f32)
+            }
+            Playback::PingPong => {
+                let t = t.rem_euclid(2.0 * last);
+                let t = if t > last { 2.0 * last - t } else { t };
+                let from = (t.floor() as usize).min(count - 2);
+                (from, from + 1, t - from as f32)
+            }
+        }
+    }
+
+    // the mesh at the current time
+    pub fn mesh(&self) -> Mesh {
+        let first = &self.frames[0];
+        let mut mesh = Mesh::new(first.vertices.clone(), first.indices.clone());
+        self.blend(&mut mesh);
+        mesh
+    }
+
+    // overwrites the positions and normals of a mesh that was made by `mesh()`
+    pub fn blend(&self, mesh: &mut Mesh) {
+        let (from, to, factor) = self.frames_at(self.time);
+        let (from, to) = (&self.frames[from], &self.frames[to]);
+
+        for ((vertex, a), b) in mesh
+            .vertices
+            .iter_mut()
+            .zip(from.vertices.iter())
+            .zip(to.vertices.iter())
+        {
+            vertex.position = a.position.lerp(b.position, factor);
+
+            let normal = a.normal.lerp(b.normal, factor);
+            vertex.normal = if normal.length() > 0.0 {
+                normal.normalized()
+            } else {
+                a.normal
+            };
+        }
+    }
+
+    // an instance draws (and casts shadows) like any other, it just gets a new mesh every frame
+    pub fn apply(&self, instance: &mut Instance) {
+        // the mesh is reused when nothing else holds on to it
+        match Rc::get_mut(&mut instance.mesh) {
+            Some(mesh) if mesh.vertices.len() == self.frames[0].vertices.len() => self.blend(mesh),
+            _ => instance.mesh = Rc::new(Box::new(self.mesh())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{graphics::vertex::Vertex, math::linear_algebra::vector::Vector4};
+
+    fn frame(x: f32) -> Mesh {
+        let vertex = |y: f32| {
+            Vertex::new(
+                Vector4::new(x, y, 0.0, 1.0),
+                Vector4::ZERO,
+                Vector4::new(0.0, 0.0, 1.0, 0.0),
+            )
+        };
+        Mesh::new(vec![vertex(0.0), vertex(1.0), vertex(2.0)], vec![0, 1, 2])
+    }
+
+    #[test]
+    fn test_morph_animation() {
+        let frames = vec![frame(0.0), frame(1.0), frame(2.0)];
+        let mut animation = MorphAnimation::new(frames, 0.5, Playback::Loop).unwrap();
+
+        assert_eq!(animation.frames_at(0.25), (0, 1, 0.5));
+        assert_eq!(animation.frames_at(1.25), (2, 0, 0.5));
+
+        animation.playback = Playback::PingPong;
+        assert_eq!(animation.frames_at(1.25), (1, 2, 0.5));
+        assert_eq!(animation.frames_at(2.25), (0, 1, 0.5));
+
+        animation.playback = Playback::Once;
+        assert_eq!(animation.frames_at(5.0), (1, 2, 1.0));
+
+        animation.playback = Playback::Loop;
+        animation.update(0.75);
+        assert_eq!(animation.mesh().vertices[1].position.x, 1.5);
+
+        animation.update(1.0); // wraps around to 0.25
+        assert_eq!(animation.time, 0.25);
+        assert_eq!(animation.mesh().vertices[1].position.x, 0.5);
+
+        let mut other = frame(1.0);
+        other.indices = vec![2, 1, 0];
+        let err = MorphAnimation::new(vec![frame(0.0), other], 0.5, Playback::Loop);
+        assert!(matches!(err, Err(MorphError::IndexMismatch { frame: 1 })));
+
+        let mut other = frame(1.0);
+        other.vertices.pop();
+        let err = MorphAnimation::new(vec![frame(0.0), other], 0.5, Playback::Loop);
+        assert!(matches!(
+            err,
+            Err(MorphError::VertexCountMismatch { frame: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    graphics::{
+        bitmap::{Bitmap, Format},
+        material::Material,
+        texture::Texture,
+    },
+    math::Vector4,
+};
+
+// wavefront material library (.mtl), the materials are referenced by name from the obj (`usemtl`)
+//
+// newmtl wood
+// Kd 1.0 0.8 0.6        -> color
+// Ks 0.5 0.5 0.5        -> specular
+// Ns 32.0               -> shininess
+// d 1.0                 -> color.w (alpha)
+// map_Kd wood.png       -> diffuse map
+// map_Bump wood_n.png   -> normal map
+// map_d wood_alpha.png  -> alpha map
+//
+// textures are loaded once and shared between every material that uses them
+#[derive(Default)]
+pub struct MaterialLibrary {
+    pub materials: HashMap<String, Material>,
+    textures: HashMap<PathBuf, Option<Arc<Texture>>>, // none when the file couldn't be loaded
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // add all materials of an .mtl file, missing files only log a warning so the model still draws
+    pub fn load(&mut self, filepath: &Path) {
+        let file = match File::open(filepath) {
+            Ok(file) => file,
+            Err(err) => {
+                log::warn!("can't open material library {}: {err}", filepath.display());
+                return;
+            }
+        };
+
+        // texture paths are relative to the library
+        let directory = filepath.parent().unwrap_or(Path::new(""));
+        self.parse(BufReader::new(file), directory);
+    }
+
+    pub fn parse(&mut self, reader: impl BufRead, directory: &Path) {
+        let mut current: Option<(String, Material)> = None;
+
+        for line in reader.lines().map_while(Result::ok) {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.is_empty() || tokens[0].starts_with('#') {
+                continue;
+            }
+
+            if tokens[0] == "newmtl" {
+                if let Some((name, material)) = current.take() {
+                    self.materials.insert(name, material);
+                }
+                current = Some((tokens[1..].join(" "), Material::default()));
+                continue;
+            }
+
+            // everything else belongs to the current material
+            let Some((_, material)) = current.as_mut() else {
+                continue;
+            };
+
+            match tokens[0] {
+                "Kd" => {
+                    let color = parse_color(&tokens);
+                    material.color = Vector4::new(color.x, color.y, color.z, material.color.w);
+                }
+                "Ks" => material.specular = parse_color(&tokens),
+                "Ke" => material.emissive = parse_color(&tokens),
+                "Ns" => material.shininess = parse_float(&tokens, 1, material.shininess),
+                "d" => material.color.w = parse_float(&tokens, 1, 1.0),
+                "Tr" => material.color.w = 1.0 - parse_float(&tokens, 1, 0.0),
+                "map_Kd" => material.diffuse_map = self.texture(&line, &tokens, directory),
+                "map_Ks" => material.specular_map = self.texture(&line, &tokens, directory),
+                "map_Ke" => material.emissive_map = self.texture(&line, &tokens, directory),
+                "map_Bump" | "map_bump" | "bump" | "norm" => {
+                    material.normal_map = self.texture(&line, &tokens, directory)
+                }
+                "map_d" => {
+                    material.alpha_map = self.texture(&line, &tokens, directory);
+                    // alpha maps are mostly cutouts (leaves, fences), half see-through pixels are dropped
+                    material.alpha_cutoff = 0.5;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((name, material)) = current.take() {
+            self.materials.insert(name, material);
+        }
+    }
+
+    fn texture(&mut self, line: &str, tokens: &[&str], directory: &Path) -> Option<Arc<Texture>> {
+        let path = directory.join(texture_path(line, tokens)?);
+
+        self.textures
+            .entry(path)
+            .or_insert_with_key(|path| match load_texture(path) {
+                Ok(texture) => Some(Arc::new(texture)),
+                Err(err) => {
+                    log::warn!("can't load texture {}: {err}", path.display());
+                    None
+                }
+            })
+            .clone()
+    }
+}
+
+// decode an image file into an rgba bitmap with all of its mip levels
+pub fn load_texture(filepath: &Path) -> Result<Texture, image::ImageError> {
+    let image = image::open(filepath)?.to_rgba8();
+    let bitmap = Bitmap::from_bytes(
+        image.width(),
+        image.height(),
+        Format::Rgba8,
+        image.into_raw(),
+    );
+
+    Ok(Texture::new(bitmap))
+}
+
+// options come before the file name (e.g. `map_Bump -bm 0.5 -o 0 0.5 my wood.png`), the rest of the line is
+// the file (blender doesn't quote paths with spaces)
+fn texture_path<'a>(line: &'a str, tokens: &[&'a str]) -> Option<&'a str> {
+    let mut index = 1;
+
+    while let Some(option) = tokens.get(index) {
+        // how many values the option takes, `-o`, `-s` and `-t` take up to 3 numbers
+        let (min, max) = match *option {
+            "-o" | "-s" | "-t" => (1, 3),
+            "-mm" => (2, 2),
+            "-blendu" | "-blendv" | "-boost" | "-texres" | "-clamp" | "-bm" | "-imfchan"
+            | "-type" | "-cc" => (1, 1),
+            _ => break,
+        };
+
+        index += 1 + min;
+        for _ in min..max {
+            match tokens.get(index) {
+                // the last token is always the file, even when it looks like a number
+                Some(value) if index + 1 < tokens.len() && value.parse::<f32>().is_ok() => {
+                    index += 1
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // the tokens are slices of the line, so the spaces between the parts of the name are kept
+    let start = tokens.get(index)?.as_ptr() as usize - line.as_ptr() as usize;
+    Some(line[start..].trim_end())
+}
+
+fn parse_float(tokens: &[&str], index: usize, default: f32) -> f32 {
+    tokens
+        .get(index)
+        .and_then(|token| token.parse().ok())
+        .unwrap_or(default)
+}
+
+// `Kd r g b` where g and b are optional (the same as r when they're missing)
+fn parse_color(tokens: &[&str]) -> Vector4 {
+    let r = parse_float(tokens, 1, 0.0);
+    let g = parse_float(tokens, 2, r);
+    let b = parse_float(tokens, 3, r);
+
+    Vector4::new(r, g, b, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::app::TestDir;
+
+    #[test]
+    fn test_parse_material_library() {
+        let directory = TestDir::new("mtl");
+
+        // a tiny 2x2 texture next to the library
+        let image = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        image.save(directory.join("red.png")).unwrap();
+        image.save(directory.join("my  red.png")).unwrap();
+
+        let mtl = "\
+# two materials
+newmtl wood
+Kd 1.0 0.5 0.25
+Ks 0.1 0.2 0.3
+Ns 64
+d 0.5
+map_Kd red.png
+map_Bump -bm 0.5 red.png
+map_Ks -o 0.5 0.5 -s 2 -clamp on  my  red.png
+
+newmtl leaves
+Kd 0.2
+map_d red.png
+map_Ks missing.png
+";
+
+        let mut library = MaterialLibrary::new();
+        library.parse(Cursor::new(mtl), &directory);
+        assert_eq!(library.materials.len(), 2);
+
+        let wood = &library.materials["wood"];
+        assert_eq!(wood.color, Vector4::new(1.0, 0.5, 0.25, 0.5));
+        assert_eq!(wood.specular, Vector4::new(0.1, 0.2, 0.3, 0.0));
+        assert_eq!(wood.shininess, 64.0);
+        assert_eq!(wood.diffuse_map.as_ref().unwrap().width(), 2);
+
+        // the same file is only loaded once
+        let diffuse = wood.diffuse_map.as_ref().unwrap();
+        let normal = wood.normal_map.as_ref().unwrap();
+        assert!(Arc::ptr_eq(diffuse, normal));
+
+        // a name with spaces after the options
+        let specular = wood.specular_map.as_ref().unwrap();
+        assert!(!Arc::ptr_eq(diffuse, specular));
+
+        let leaves = &library.materials["leaves"];
+        assert_eq!(leaves.color, Vector4::new(0.2, 0.2, 0.2, 1.0));
+        assert!(leaves.alpha_map.is_some() && leaves.alpha_cutoff > 0.0);
+        assert!(leaves.specular_map.is_none());
+    }
+
+    #[test]
+    fn test_texture_path() {
+        let path = |line: &str| {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            texture_path(line, &tokens).map(str::to_string)
+        };
+
+        assert_eq!(path("map_Kd wood.png").as_deref(), Some("wood.png"));
+        assert_eq!(
+            path("map_Kd -blendu off -mm 0 1 -o 1 -t 0.1 0.2 0.3 old wood.png ").as_deref(),
+            Some("old wood.png")
+        );
+        assert_eq!(path("map_Kd -s 1 1 1 2").as_deref(), Some("2"));
+        assert_eq!(path("map_Kd -bm 0.5"), None);
+    }
+}
@@ -0,0 +1,568 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{graphics::mesh::Mesh, math::linear_algebra::vector::Vector4};
+
+use super::mesh_loader::{
+    calc_normals, to_mesh, triangulate, IndexedModel, LineParser, MeshLoadError, MeshLoadErrorKind,
+};
+
+// polygon file format (.ply), what 3d scanners put out: a header that describes the elements and then their values
+//
+// ply
+// format ascii 1.0                                 <- or binary_little_endian 1.0
+// element vertex 3
+// property float x                                 <- also y z, nx ny nz, s t (or u v) and red green blue alpha
+// property uchar red
+// element face 1
+// property list uchar int vertex_indices           <- polygons, they are triangulated
+// end_header
+// 0 0 0 255
+// ...
+// 3 0 1 2
+//
+// unknown elements and properties are read and skipped, colors are 0 - 255 for integers and 0 - 1 for floats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlyType {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+#[derive(Debug)]
+enum PlyProperty {
+    Scalar(String, PlyType),
+    List(String, PlyType, PlyType), // name, type of the count, type of the items
+}
+
+#[derive(Debug)]
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+pub fn load_ply(filepath: &Path) -> Result<Mesh, MeshLoadError> {
+    let path = filepath.display().to_string();
+    let file = File::open(filepath)
+        .map_err(|err| MeshLoadError::new(&path, 0, 0, MeshLoadErrorKind::Io(err)))?;
+
+    read_ply(BufReader::new(file), &path)
+}
+
+// `path` is only used for the errors
+pub fn read_ply(mut reader: impl BufRead, path: &str) -> Result<Mesh, MeshLoadError> {
+    let io_error = |line: usize| {
+        move |err: io::Error| MeshLoadError::new(path, line, 0, MeshLoadErrorKind::Io(err))
+    };
+
+    // header
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+    let mut line_number = 0;
+
+    loop {
+        let mut text = String::new();
+        line_number += 1;
+        if reader.read_line(&mut text).map_err(io_error(line_number))? == 0 {
+            let err = io::Error::new(io::ErrorKind::UnexpectedEof, "the header doesn't end");
+            return Err(io_error(line_number)(err));
+        }
+
+        let mut parser = LineParser::new(&text, path, line_number);
+        let Some(keyword) = parser.next() else {
+            continue;
+        };
+        match keyword {
+            "ply" | "comment" | "obj_info" => {}
+            "format" => {
+                let name = parser.next().unwrap_or(keyword);
+                format = match name {
+                    "ascii" => Some(PlyFormat::Ascii),
+                    "binary_little_endian" => Some(PlyFormat::BinaryLittleEndian),
+                    _ => return Err(unsupported(&parser, name)),
+                };
+            }
+            "element" => {
+                let name = parser.next().unwrap_or_default().to_string();
+                let count = parser.float()? as usize;
+                elements.push(PlyElement {
+                    name,
+                    count,
+                    properties: vec![],
+                });
+            }
+            "property" => {
+                let read_type = |parser: &mut LineParser| {
+                    let token = parser.next().unwrap_or(keyword);
+                    PlyType::parse(token).ok_or_else(|| unsupported(parser, token))
+                };
+
+                let property = match parser.next() {
+                    Some("list") => {
+                        let count_type = read_type(&mut parser)?;
+                        let item_type = read_type(&mut parser)?;
+                        PlyProperty::List(parser.rest(), count_type, item_type)
+                    }
+                    _ => {
+                        // the type was the first token
+                        let mut parser = LineParser::new(&text, path, line_number);
+                        parser.next();
+                        let scalar_type = read_type(&mut parser)?;
+                        PlyProperty::Scalar(parser.rest(), scalar_type)
+                    }
+                };
+
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err(unsupported(&parser, keyword)),
+                }
+            }
+            "end_header" => break,
+            _ => return Err(unsupported(&parser, keyword)),
+        }
+    }
+
+    let Some(format) = format else {
+        let err = io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the header doesn't have a format",
+        );
+        return Err(io_error(line_number)(err));
+    };
+
+    // body, every element is read in the order of the header
+    let mut values = ValueReader {
+        reader,
+        format,
+        path,
+        line: line_number,
+    };
+
+    let mut model = IndexedModel::default();
+    let mut colors: Vec<Vector4> = vec![];
+    let mut has_normals = false;
+
+    for element in elements.iter() {
+        for _ in 0..element.count {
+            let row = values.row(element)?;
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let get = |names: &[&str]| -> Option<f32> {
+                        element
+                            .properties
+                            .iter()
+                            .zip(row.iter())
+                            .find(|(property, _)| names.contains(&property.name()))
+                            // an empty list is the same as a missing property
+                            .and_then(|(property, value)| {
+                                value
+                                    .first()
+                                    .map(|&value| property.normalized(value, names))
+                            })
+                    };
+
+                    let x = get(&["x"]).unwrap_or(0.0);
+                    let y = get(&["y"]).unwrap_or(0.0);
+                    let z = get(&["z"]).unwrap_or(0.0);
+                    model.vertices.push(Vector4::new(x, y, z, 1.0));
+
+                    let nx = get(&["nx"]);
+                    has_normals |= nx.is_some();
+                    model.normals.push(Vector4::new(
+                        nx.unwrap_or(0.0),
+                        get(&["ny"]).unwrap_or(0.0),
+                        get(&["nz"]).unwrap_or(0.0),
+                        0.0,
+                    ));
+
+                    // v is flipped just like in the obj loader
+                    let u = get(&["s", "u", "texture_u", "texture_s"]).unwrap_or(0.0);
+                    let v = get(&["t", "v", "texture_v", "texture_t"]).unwrap_or(1.0);
+                    model.tex_coords.push(Vector4::new(u, 1.0 - v, 0.0, 0.0));
+
+                    colors.push(Vector4::new(
+                        get(&["red", "diffuse_red"]).unwrap_or(1.0),
+                        get(&["green", "diffuse_green"]).unwrap_or(1.0),
+                        get(&["blue", "diffuse_blue"]).unwrap_or(1.0),
+                        get(&["alpha", "diffuse_alpha"]).unwrap_or(1.0),
+                    ));
+                }
+                "face" => {
+                    let polygon = element
+                        .properties
+                        .iter()
+                        .zip(row.iter())
+                        .find(|(property, _)| {
+                            matches!(property.name(), "vertex_indices" | "vertex_index")
+                        })
+                        .map(|(_, value)| value);
+
+                    let Some(polygon) = polygon else {
+                        continue;
+                    };
+
+                    let mut corners = Vec::with_capacity(polygon.len());
+                    for &index in polygon.iter() {
+                        if index < 0.0 || index as usize >= model.vertices.len() {
+                            let kind = MeshLoadErrorKind::BadIndex(index.to_string());
+                            return Err(MeshLoadError::new(path, values.line, 0, kind));
+                        }
+                        corners.push(index as usize);
+                    }
+
+                    if corners.len() < 3 {
+                        let kind = MeshLoadErrorKind::BadIndex(format!("{corners:?}"));
+                        return Err(MeshLoadError::new(path, values.line, 0, kind));
+                    }
+
+                    let points: Vec<Vector4> = corners.iter().map(|&i| model.vertices[i]).collect();
+                    for triangle in triangulate(&points) {
+                        model.indices.extend(triangle.iter().map(|&i| corners[i]));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if !has_normals {
+        calc_normals(&mut model);
+    }
+
+    let mut mesh = to_mesh(model);
+    for (vertex, color) in mesh.vertices.iter_mut().zip(colors) {
+        vertex.color = color;
+    }
+
+    Ok(mesh)
+}
+
+pub fn save_ply(filepath: &Path, mesh: &Mesh, format: PlyFormat) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filepath)?);
+    write_ply(&mut writer, mesh, format)?;
+    writer.flush()
+}
+
+// positions, normals, texture coordinates, colors (0 - 255) and triangles
+pub fn write_ply(mut writer: impl Write, mesh: &Mesh, format: PlyFormat) -> io::Result<()> {
+    let format_name = match format {
+        PlyFormat::Ascii => "ascii",
+        PlyFormat::BinaryLittleEndian => "binary_little_endian",
+    };
+
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format {format_name} 1.0")?;
+    writeln!(writer, "comment soft-rend")?;
+    writeln!(writer, "element vertex {}", mesh.vertices.len())?;
+    for name in ["x", "y", "z", "nx", "ny", "nz", "s", "t"] {
+        writeln!(writer, "property float {name}")?;
+    }
+    for name in ["red", "green", "blue", "alpha"] {
+        writeln!(writer, "property uchar {name}")?;
+    }
+    writeln!(writer, "element face {}", mesh.indices.len() / 3)?;
+    writeln!(writer, "property list uchar uint vertex_indices")?;
+    writeln!(writer, "end_header")?;
+
+    let channel = |c: f32| (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8;
+
+    for vertex in mesh.vertices.iter() {
+        let (p, n, t, c) = (
+            vertex.position,
+            vertex.normal,
+            vertex.texcoords,
+            vertex.color,
+        );
+        let floats = [p.x, p.y, p.z, n.x, n.y, n.z, t.x, 1.0 - t.y];
+        let bytes = [channel(c.x), channel(c.y), channel(c.z), channel(c.w)];
+
+        match format {
+            PlyFormat::Ascii => {
+                for value in floats {
+                    write!(writer, "{value} ")?;
+                }
+                writeln!(
+                    writer,
+                    "{} {} {} {}",
+                    bytes[0], bytes[1], bytes[2], bytes[3]
+                )?;
+            }
+            PlyFormat::BinaryLittleEndian => {
+                for value in floats {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+                writer.write_all(&bytes)?;
+            }
+        }
+    }
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        match format {
+            PlyFormat::Ascii => {
+                writeln!(writer, "3 {} {} {}", triangle[0], triangle[1], triangle[2])?
+            }
+            PlyFormat::BinaryLittleEndian => {
+                writer.write_all(&[3])?;
+                for &index in triangle {
+                    writer.write_all(&(index as u32).to_le_bytes())?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn unsupported(parser: &LineParser, token: &str) -> MeshLoadError {
+    parser.error(
+        token,
+        MeshLoadErrorKind::UnsupportedDirective(token.to_string()),
+    )
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Option<Self> {
+        let ply_type = match name {
+            "char" | "int8" => PlyType::Char,
+            "uchar" | "uint8" => PlyType::UChar,
+            "short" | "int16" => PlyType::Short,
+            "ushort" | "uint16" => PlyType::UShort,
+            "int" | "int32" => PlyType::Int,
+            "uint" | "uint32" => PlyType::UInt,
+            "float" | "float32" => PlyType::Float,
+            "double" | "float64" => PlyType::Double,
+            _ => return None,
+        };
+        Some(ply_type)
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            PlyType::Char | PlyType::UChar => 1,
+            PlyType::Short | PlyType::UShort => 2,
+            PlyType::Int | PlyType::UInt | PlyType::Float => 4,
+            PlyType::Double => 8,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> f64 {
+        match self {
+            PlyType::Char => bytes[0] as i8 as f64,
+            PlyType::UChar => bytes[0] as f64,
+            PlyType::Short => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            PlyType::UShort => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            PlyType::Int => i32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+            PlyType::UInt => u32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+            PlyType::Float => f32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+            PlyType::Double => f64::from_le_bytes(bytes[..8].try_into().unwrap()),
+        }
+    }
+}
+
+impl PlyProperty {
+    fn name(&self) -> &str {
+        match self {
+            PlyProperty::Scalar(name, _) | PlyProperty::List(name, _, _) => name,
+        }
+    }
+
+    // integer colors are 0 - 255 (or 0 - 65535), everything else is used as it is
+    fn normalized(&self, value: f64, names: &[&str]) -> f32 {
+        let is_color = names.contains(&"red")
+            || names.contains(&"green")
+            || names.contains(&"blue")
+            || names.contains(&"alpha");
+
+        match self {
+            PlyProperty::Scalar(_, PlyType::UChar) if is_color => (value / 255.0) as f32,
+            PlyProperty::Scalar(_, PlyType::UShort) if is_color => (value / 65535.0) as f32,
+            _ => value as f32,
+        }
+    }
+}
+
+// reads the values of the body one element (row) at a time, for both ascii and binary files
+struct ValueReader<'a, R> {
+    reader: R,
+    format: PlyFormat,
+    path: &'a str,
+    line: usize, // line of the row in ascii files, the header's last line in binary files
+}
+
+impl<'a, R: BufRead> ValueReader<'a, R> {
+    // the values of every property, a scalar has one value and a list has its items
+    fn row(&mut self, element: &PlyElement) -> Result<Vec<Vec<f64>>, MeshLoadError> {
+        match self.format {
+            PlyFormat::Ascii => self.ascii_row(element),
+            PlyFormat::BinaryLittleEndian => self.binary_row(element),
+        }
+    }
+
+    fn ascii_row(&mut self, element: &PlyElement) -> Result<Vec<Vec<f64>>, MeshLoadError> {
+        let mut text = String::new();
+        self.line += 1;
+        self.reader.read_line(&mut text).map_err(|err| {
+            MeshLoadError::new(self.path, self.line, 0, MeshLoadErrorKind::Io(err))
+        })?;
+
+        let mut parser = LineParser::new(&text, self.path, self.line);
+        let mut number = || -> Result<f64, MeshLoadError> {
+            match parser.next() {
+                Some(token) => token.parse::<f64>().map_err(|_| {
+                    parser.error(token, MeshLoadErrorKind::BadNumber(token.to_string()))
+                }),
+                None => {
+                    let kind = MeshLoadErrorKind::BadNumber(String::new());
+                    Err(MeshLoadError::new(
+                        self.path,
+                        self.line,
+                        text.trim_end().len() + 1,
+                        kind,
+                    ))
+                }
+            }
+        };
+
+        let mut row = Vec::with_capacity(element.properties.len());
+        for property in element.properties.iter() {
+            match property {
+                PlyProperty::Scalar(..) => row.push(vec![number()?]),
+                PlyProperty::List(..) => {
+                    let count = number()? as usize;
+                    let items = (0..count).map(|_| number()).collect::<Result<_, _>>()?;
+                    row.push(items);
+                }
+            }
+        }
+
+        Ok(row)
+    }
+
+    fn binary_row(&mut self, element: &PlyElement) -> Result<Vec<Vec<f64>>, MeshLoadError> {
+        let mut row = Vec::with_capacity(element.properties.len());
+        for property in element.properties.iter() {
+            match property {
+                PlyProperty::Scalar(_, scalar_type) => row.push(vec![self.binary(*scalar_type)?]),
+                PlyProperty::List(_, count_type, item_type) => {
+                    let count = self.binary(*count_type)? as usize;
+                    let items = (0..count)
+                        .map(|_| self.binary(*item_type))
+                        .collect::<Result<_, _>>()?;
+                    row.push(items);
+                }
+            }
+        }
+
+        Ok(row)
+    }
+
+    fn binary(&mut self, value_type: PlyType) -> Result<f64, MeshLoadError> {
+        let mut bytes = [0; 8];
+        let bytes = &mut bytes[..value_type.size()];
+        self.reader
+            .read_exact(bytes)
+            .map_err(|err| MeshLoadError::new(self.path, 0, 0, MeshLoadErrorKind::Io(err)))?;
+
+        Ok(value_type.decode(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_ply_round_trip() {
+        // a colored quad with an extra property and element that are skipped
+        let ascii = "\
+ply
+format ascii 1.0
+comment made by hand
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+property float confidence
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 255 0 0 0.5
+1 0 0 0 255 0 0.5
+1 1 0 0 0 255 0.5
+0 1 0 255 255 255 0.5
+4 0 1 2 3
+0 1
+";
+        let mesh = read_ply(Cursor::new(ascii), "test.ply").unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.vertices[1].color, Vector4::new(0.0, 1.0, 0.0, 1.0));
+
+        // missing normals are made from the faces
+        assert_eq!(mesh.vertices[0].normal, Vector4::new(0.0, 0.0, 1.0, 0.0));
+
+        for format in [PlyFormat::Ascii, PlyFormat::BinaryLittleEndian] {
+            let mut bytes = vec![];
+            write_ply(&mut bytes, &mesh, format).unwrap();
+
+            let loaded = read_ply(Cursor::new(bytes), "test.ply").unwrap();
+            assert_eq!(loaded.indices, mesh.indices);
+            for (a, b) in loaded.vertices.iter().zip(mesh.vertices.iter()) {
+                assert_eq!(
+                    (a.position, a.normal, a.texcoords, a.color),
+                    (b.position, b.normal, b.texcoords, b.color)
+                );
+            }
+        }
+
+        let err = read_ply(
+            Cursor::new(ascii.replace("4 0 1 2 3", "3 0 1 7")),
+            "test.ply",
+        );
+        let err = err.err().unwrap();
+        assert_eq!(err.line, 22);
+        assert!(matches!(err.kind, MeshLoadErrorKind::BadIndex(_)));
+
+        let err = read_ply(Cursor::new(ascii.replace("1 1 0 0", "1 x 0 0")), "test.ply");
+        let err = err.err().unwrap();
+        assert_eq!((err.line, err.column), (20, 3));
+
+        // a texture coordinate list without any values is skipped
+        let lists = ascii
+            .replace("property float confidence", "property list uchar float u")
+            .replace(" 0.5\n", " 0\n");
+        let mesh = read_ply(Cursor::new(lists), "test.ply").unwrap();
+        assert_eq!(mesh.vertices[2].texcoords.x, 0.0);
+
+        let big_endian = ascii.replace("ascii", "binary_big_endian");
+        let err = read_ply(Cursor::new(big_endian), "test.ply").err().unwrap();
+        assert!(matches!(
+            err.kind,
+            MeshLoadErrorKind::UnsupportedDirective(_)
+        ));
+    }
+}
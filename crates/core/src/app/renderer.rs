@@ -1,18 +1,21 @@
 use std::mem;
 
+use rayon::prelude::*;
+
 use crate::{
     graphics::{
-        bitmap::Bitmap,
+        bitmap::{Bitmap, Format},
         clip::clip_triangle,
         color::Color,
         edge::Edge,
         gradients::{Gradients, Triangle},
-        light::Light,
-        material::Material,
         mesh::Mesh,
-        vertex::Vertex,
+        shader::{Fragment, Shader},
+        tile::Tile,
+        varyings::Varyings,
+        vertex::{ClipVertex, Vertex},
     },
-    math::{Matrix4, Vector4},
+    math::Matrix4,
 };
 
 #[derive(Debug, Default)]
@@ -24,14 +27,39 @@ pub struct Debug {
     depth_miss: bool,
 }
 
+// how many screen rows go into a single tile
+const TILE_HEIGHT: u32 = 16;
+
 #[derive(Debug)]
 pub struct Renderer {
-    pub width: u32,               // width in pixels
-    pub height: u32,              // height in pixels
-    pub screenspace: Matrix4,     // screen-space matrix for rasterizing
-    pub color_buffer: Bitmap<u8>, // the main color buffer (r,g,b,a)
-    pub depth_buffer: Vec<f32>, // the z buffer (1 - 0) -> (far - close)     // @todo: could be an array/slice
-    pub debug: Debug,           // debug variables for displaying extra information
+    pub width: u32,                // width in pixels
+    pub height: u32,               // height in pixels
+    pub screenspace: Matrix4,      // screen-space matrix for rasterizing
+    pub color_buffer: Bitmap<u8>,  // the main color buffer (r,g,b,a)
+    pub depth_buffer: Bitmap<f32>, // the z buffer (1 - 0) -> (far - close), single channel (r32f)
+    pub tile_height: u32, // screen rows per tile, every tile is rasterized on its own thread
+    pub parallel: bool,   // rasterize tiles in parallel or draw triangles one by one
+    pub debug: Debug,     // debug variables for displaying extra information
+}
+
+// a triangle in screen-space, sorted on the y-axis and ready to be scanned into tiles
+#[derive(Debug)]
+pub struct ScreenTriangle {
+    pub min: ClipVertex,
+    pub mid: ClipVertex,
+    pub max: ClipVertex,
+    pub handedness: bool,
+    pub gradients: Gradients,
+}
+
+impl ScreenTriangle {
+    // scan lines that the triangle covers: (start, end) where end is not inclusive
+    pub fn rows(&self) -> (u32, u32) {
+        (
+            self.min.position.y.ceil() as u32,
+            self.max.position.y.ceil() as u32,
+        )
+    }
 }
 
 impl Renderer {
@@ -41,7 +69,9 @@ impl Renderer {
             height,
             screenspace: Matrix4::screenspace(width as f32, height as f32),
             color_buffer: Bitmap::new(width, height),
-            depth_buffer: vec![1.0; (width * height) as usize],
+            depth_buffer: Bitmap::with_format(width, height, Format::R32F),
+            tile_height: TILE_HEIGHT,
+            parallel: true,
             debug: Default::default(),
         };
 
@@ -63,100 +93,35 @@ impl Renderer {
         }
     }
 
-    pub fn draw_mesh(
-        &mut self,
-        mesh: &Mesh,
-        view_projection: &Matrix4,
-        transform: &Matrix4,
-        material: &Material,
-        light: Option<&Light>,
-    ) {
-        let mvp = Matrix4::multiply(view_projection, transform);
-        let identity = &Matrix4::new_identity();
-
-        // # debug: show little white pixel at the top for this object
-        // let pos = Matrix4::multiply_vector(&mvp, transform.translation());
-        // self.color_buffer.set_pixel(pos.x as u32, pos.y as u32, &Color::WHITE);
-
-        // === parallel: get clipped triangles
-        // let triangles: Vec<_> = mesh
-        //     .indices
-        //     .par_chunks_exact(3)
-        //     .map(|chunk| {
-        //         let mut v1 = mesh.vertices[chunk[0]];
-        //         let mut v2 = mesh.vertices[chunk[1]];
-        //         let mut v3 = mesh.vertices[chunk[2]];
-
-        //         // transform shadown-map-coords while the vertices are still in local space
-        //         if let Some(light) = light {
-        //             let light_view_model_projection =
-        //                 Matrix4::multiply(&light.projection, transform);
-        //             v1.shadow_map_coords =
-        //                 Matrix4::multiply_vector(&light_view_model_projection, v1.position);
-        //             v2.shadow_map_coords =
-        //                 Matrix4::multiply_vector(&light_view_model_projection, v2.position);
-        //             v3.shadow_map_coords =
-        //                 Matrix4::multiply_vector(&light_view_model_projection, v3.position);
-        //         }
-
-        //         // transform vertices into mvp
-        //         v1 = v1.transform(&mvp, identity);
-        //         v2 = v2.transform(&mvp, identity);
-        //         v3 = v3.transform(&mvp, identity);
-
-        //         let v1_visible = v1.is_inside_view_frustum();
-        //         let v2_visible = v2.is_inside_view_frustum();
-        //         let v3_visible = v3.is_inside_view_frustum();
-
-        //         // all vertices are visible so draw the triangle as is
-        //         if v1_visible && v2_visible && v3_visible {
-        //             // self.fill_triangle(v1, v2, v3, material, light);
-
-        //             // # debug: draw with green triangles that are not broken
-        //             // let mut fill = Bitmap::new(1, 1);
-        //             // fill.fill(&Color::WHITE);
-        //             // self.fill_triangle(v1, v2, v3, &fill);
-        //             return vec![Triangle::new(v1, v2, v3)];
-        //         }
-
-        //         // one or more (or all) vertices are not visible, we must clip them
-        //         let clipped_triangles = clip_triangle(v1, v2, v3);
-        //         if let Some(clipped_triangles) = clipped_triangles {
-        //             return clipped_triangles;
-        //         }
-
-        //         // empty
-        //         return vec![];
-        //     })
-        //     .filter(|x| x.len() > 0)
-        //     .flatten()
-        //     .collect();
+    // hand over the depth buffer without copying it (e.g. to become a shadow-map)
+    // a recycled bitmap of the same size takes its place, otherwise a new one is allocated
+    // the new depth buffer isn't cleared, it's expected to be cleared before the next draw
+    pub fn take_depth_buffer(&mut self, recycled: Option<Bitmap<f32>>) -> Bitmap<f32> {
+        let replacement = match recycled {
+            Some(bitmap)
+                if bitmap.width == self.width
+                    && bitmap.height == self.height
+                    && bitmap.format == Format::R32F =>
+            {
+                bitmap
+            }
+            _ => Bitmap::with_format(self.width, self.height, Format::R32F),
+        };
 
+        return mem::replace(&mut self.depth_buffer, replacement);
+    }
+
+    // run the shader's vertex stage on every vertex of the mesh, clip and rasterize the triangles
+    pub fn draw_mesh<S: Shader>(&mut self, mesh: &Mesh, shader: &S) {
         // for some reason non parallel is faster, I guess clipping isn't that expensive
         let triangles: Vec<_> = mesh
             .indices
             .chunks_exact(3)
             .map(|chunk| {
-                let mut v1 = mesh.vertices[chunk[0]];
-                let mut v2 = mesh.vertices[chunk[1]];
-                let mut v3 = mesh.vertices[chunk[2]];
-
-                // transform shadown-map-coords while the vertices are still in local space
-                if let Some(light) = light {
-                    let light_view_model_projection =
-                        Matrix4::multiply(&light.projection, transform);
-                    v1.shadow_map_coords =
-                        Matrix4::multiply_vector(&light_view_model_projection, v1.position);
-                    v2.shadow_map_coords =
-                        Matrix4::multiply_vector(&light_view_model_projection, v2.position);
-                    v3.shadow_map_coords =
-                        Matrix4::multiply_vector(&light_view_model_projection, v3.position);
-                }
-
-                // transform vertices into mvp
-                v1 = v1.transform(&mvp, identity);
-                v2 = v2.transform(&mvp, identity);
-                v3 = v3.transform(&mvp, identity);
+                // vertex stage: move the vertices into clip-space
+                let v1 = Self::shade_vertex(&mesh.vertices[chunk[0]], shader);
+                let v2 = Self::shade_vertex(&mesh.vertices[chunk[1]], shader);
+                let v3 = Self::shade_vertex(&mesh.vertices[chunk[2]], shader);
 
                 let v1_visible = v1.is_inside_view_frustum();
                 let v2_visible = v2.is_inside_view_frustum();
@@ -164,12 +129,6 @@ impl Renderer {
 
                 // all vertices are visible so draw the triangle as is
                 if v1_visible && v2_visible && v3_visible {
-                    // self.fill_triangle(v1, v2, v3, material, light);
-
-                    // # debug: draw with green triangles that are not broken
-                    // let mut fill = Bitmap::new(1, 1);
-                    // fill.fill(&Color::WHITE);
-                    // self.fill_triangle(v1, v2, v3, &fill);
                     return vec![Triangle::new(v1, v2, v3)];
                 }
 
@@ -186,23 +145,108 @@ impl Renderer {
             .flatten()
             .collect();
 
-        // @todo: run in parallel, it depends on many things, might need to split it up
-        for triangle in triangles {
-            self.fill_triangle(triangle.min, triangle.mid, triangle.max, material, light);
+        if !self.parallel {
+            for triangle in triangles {
+                self.fill_triangle(triangle.min, triangle.mid, triangle.max, shader);
+            }
+            return;
         }
+
+        // move every triangle into screen-space, each one is independent so this can run in parallel
+        // collect keeps the order of the triangles so that tiles draw them exactly like the serial path
+        let cull_back_faces = shader.cull_back_faces();
+        let triangles: Vec<_> = triangles
+            .into_par_iter()
+            .filter_map(|triangle| {
+                self.setup_triangle(triangle.min, triangle.mid, triangle.max, cull_back_faces)
+            })
+            .collect();
+
+        self.draw_tiles(&triangles, shader);
+    }
+
+    // run the vertex stage and collect the varyings it outputs
+    pub fn shade_vertex<S: Shader>(vertex: &Vertex, shader: &S) -> ClipVertex {
+        let mut varyings = Varyings::new();
+        let position = shader.vertex(vertex, &mut varyings);
+        ClipVertex::new(position, varyings)
+    }
+
+    // split the screen into tiles, bin the triangles into them and rasterize all tiles in parallel
+    pub fn draw_tiles<S: Shader>(&mut self, triangles: &[ScreenTriangle], shader: &S) {
+        let rows = self.tile_height.max(1);
+        let tile_count = self.height.div_ceil(rows) as usize;
+
+        // every tile gets a list of triangles that touch its rows (in the order they were submitted)
+        //
+        // tile 0 |   /\       | [0]
+        // tile 1 |  /0 \  /\  | [0, 1]
+        // tile 2 | /____\/1_\ | [0, 1]
+        // tile 3 |            | []
+        let mut bins: Vec<Vec<usize>> = vec![Vec::new(); tile_count];
+
+        for (index, triangle) in triangles.iter().enumerate() {
+            let (y_start, y_end) = triangle.rows();
+
+            if y_start >= y_end {
+                continue;
+            }
+
+            let first = (y_start / rows) as usize;
+            let last = (((y_end - 1) / rows) as usize).min(tile_count - 1);
+
+            for bin in bins.iter_mut().take(last + 1).skip(first) {
+                bin.push(index);
+            }
+        }
+
+        // each tile owns a slice of the color and depth buffers so no two threads touch the same pixel
+        let width = self.width;
+        let debug = &self.debug;
+
+        self.color_buffer
+            .pixels
+            .par_chunks_mut((width * rows * 4) as usize)
+            .zip(self.depth_buffer.par_chunks_mut((width * rows) as usize))
+            .zip(bins.par_iter())
+            .enumerate()
+            .for_each(|(i, ((color, depth), bin))| {
+                let mut tile = Tile::new(i as u32 * rows, width, color, depth);
+
+                for &index in bin {
+                    Self::scan_triangle(&mut tile, &triangles[index], shader, debug);
+                }
+            });
     }
 
     // given 3 vertices we will fill everything in between with pixels
-    pub fn fill_triangle(
+    pub fn fill_triangle<S: Shader>(
         &mut self,
-        v1: Vertex,
-        v2: Vertex,
-        v3: Vertex,
-        material: &Material,
-        light: Option<&Light>,
+        v1: ClipVertex,
+        v2: ClipVertex,
+        v3: ClipVertex,
+        shader: &S,
     ) {
-        let identity = Matrix4::new_identity();
+        if let Some(triangle) = self.setup_triangle(v1, v2, v3, shader.cull_back_faces()) {
+            // the whole screen is a single tile
+            let mut tile = Tile::new(
+                0,
+                self.width,
+                &mut self.color_buffer,
+                &mut self.depth_buffer,
+            );
+            Self::scan_triangle(&mut tile, &triangle, shader, &self.debug);
+        }
+    }
 
+    // move the vertices into screen-space and sort them, returns none when the triangle is culled
+    pub fn setup_triangle(
+        &self,
+        v1: ClipVertex,
+        v2: ClipVertex,
+        v3: ClipVertex,
+        cull_back_faces: bool,
+    ) -> Option<ScreenTriangle> {
         // transform vertices from world-space to screen-space using matrices.
         // z is used for depth, and w is used for perspective
         // perspective divide puts us into image space
@@ -212,20 +256,15 @@ impl Renderer {
         //     / |
         //   -1 -1
 
-        let mut min = v1
-            .transform(&self.screenspace, &identity)
-            .perspective_divide();
-        let mut mid = v2
-            .transform(&self.screenspace, &identity)
-            .perspective_divide();
-        let mut max = v3
-            .transform(&self.screenspace, &identity)
-            .perspective_divide();
+        let mut min = v1.transform(&self.screenspace).perspective_divide();
+        let mut mid = v2.transform(&self.screenspace).perspective_divide();
+        let mut max = v3.transform(&self.screenspace).perspective_divide();
 
         // back face culling
         // cross product: min->max and min->min will give us the handedness: right > 0 and left < 0
-        if min.triangle_area_times_two(&max, &mid) >= 0.0 {
-            return;
+        // double sided surfaces skip this
+        if cull_back_faces && min.triangle_area_times_two(&max, &mid) >= 0.0 {
+            return None;
         }
 
         // vertices can come in any order so we must sort them, in ideal case they are as the following:
@@ -280,37 +319,33 @@ impl Renderer {
         // self.color_buffer
         //     .set_pixel(max.position.x as u32, max.position.y as u32, &Color::BLUE);
 
-        self.scan_triangle(min, mid, max, handedness, material, light);
-    }
-
-    pub fn scan_triangle(
-        &mut self,
-        min: Vertex,
-        mid: Vertex,
-        max: Vertex,
-        handedness: bool,
-        material: &Material,
-        light: Option<&Light>,
-    ) {
         // construct gradients for the triangle
-        // it contains tex-coords, one-over-z, depth, light-amt for all 3 vertices
-
-        let mut light_dir = Vector4 {
-            x: 0.1,
-            y: 0.6,
-            z: 0.3,
-            w: 1.0,
-        };
+        // it contains one-over-z, depth and the varyings for all 3 vertices
+        let gradients = Gradients::new(Triangle::new(min, mid, max));
 
-        // @todo: get the rotation out of the light matrix
-        // if let Some(light) = light {
-        //     light_dir = light.transform.translation();
-        // }
+        Some(ScreenTriangle {
+            min,
+            mid,
+            max,
+            handedness,
+            gradients,
+        })
+    }
 
-        let gradients = Gradients::new(
-            Triangle::new(min.clone(), mid.clone(), max.clone()),
-            light_dir,
-        );
+    // scan the part of the triangle that is inside of the tile
+    pub fn scan_triangle<S: Shader>(
+        tile: &mut Tile,
+        triangle: &ScreenTriangle,
+        shader: &S,
+        debug: &Debug,
+    ) {
+        let ScreenTriangle {
+            min,
+            mid,
+            max,
+            handedness,
+            gradients,
+        } = triangle;
 
         // # debug: switch textures to see how triangles are drawn
         // make sure to change &bitmap to &debug_tex_1/&debug_tex_1 in scan_edges(...)
@@ -335,11 +370,11 @@ impl Renderer {
         // if handedness is 0 then the top to bottom is the left edge, everything else is a right edge.
 
         // edge that goes from top to bottom, gradients start at the minimum vertex (0)
-        let mut min_to_max = Edge::new(&gradients, &min, &max, 0);
+        let mut min_to_max = Edge::new(gradients, min, max, 0);
         // edge that goes from top to middle, gradients start at the minimum vertex (0)
-        let mut min_to_mid = Edge::new(&gradients, &min, &mid, 0);
+        let mut min_to_mid = Edge::new(gradients, min, mid, 0);
         // edge that goes from middle to bottom, gradients start at the middle vertex (1)
-        let mut mid_to_max = Edge::new(&gradients, &mid, &max, 1);
+        let mut mid_to_max = Edge::new(gradients, mid, max, 1);
 
         // draw edges:
 
@@ -352,13 +387,14 @@ impl Renderer {
         //     .
         //      max
 
-        self.scan_edges(
-            &gradients,
+        Self::scan_edges(
+            tile,
+            gradients,
             &mut min_to_max,
             &mut min_to_mid,
-            handedness,
-            &material,
-            light,
+            *handedness,
+            shader,
+            debug,
         );
 
         // second half of the triangle (after the mid vertex)
@@ -370,24 +406,25 @@ impl Renderer {
         //     .-.
         //      max
 
-        self.scan_edges(
-            &gradients,
+        Self::scan_edges(
+            tile,
+            gradients,
             &mut min_to_max,
             &mut mid_to_max,
-            handedness,
-            &material,
-            light,
+            *handedness,
+            shader,
+            debug,
         );
     }
 
-    pub fn scan_edges(
-        &mut self,
+    pub fn scan_edges<S: Shader>(
+        tile: &mut Tile,
         gradients: &Gradients,
         edge_a: &mut Edge,
         edge_b: &mut Edge,
         handedness: bool,
-        material: &Material,
-        light: Option<&Light>,
+        shader: &S,
+        debug: &Debug,
     ) {
         // all edges must be draw from left to right
         let mut left = edge_a;
@@ -419,6 +456,11 @@ impl Renderer {
         // all scan lines are drawn from left to right
         // so the sorting in the previous block was necessary
         for y in y_start..y_end {
+            // the rest of the triangle is below the tile
+            if y >= tile.y_end {
+                break;
+            }
+
             // // # debug: see how scan lines are drawn
             // if self.debug.scanline_fill {
             //     let v = (y as f32 - y_start as f32) / (y_end as f32 - y_start as f32);
@@ -445,7 +487,10 @@ impl Renderer {
             //     self.color_buffer.set_pixel(x_max, y, &color);
             // }
 
-            self.draw_scan_line(&gradients, &left, &right, y, &material, light);
+            // scan lines above the tile are only stepped through so that the edges stay in sync
+            if y >= tile.y_start {
+                Self::draw_scan_line(tile, gradients, left, right, y, shader, debug);
+            }
 
             // step to the next pixel on both edges
             left.step();
@@ -453,17 +498,15 @@ impl Renderer {
         }
     }
 
-    pub fn draw_scan_line(
-        &mut self,
+    pub fn draw_scan_line<S: Shader>(
+        tile: &mut Tile,
         gradients: &Gradients,
         left: &Edge,
         right: &Edge,
         y: u32,
-        material: &Material,
-        light: Option<&Light>,
+        shader: &S,
+        debug: &Debug,
     ) {
-        // let light: Option<&Light> = None;
-
         // fill convention: if the pixel center is inside the shape it's drawn otherwise it isn't
         let x_min = left.x.ceil() as u32;
         let x_max = right.x.ceil() as u32; // not inclusive so ceil is fine
@@ -471,207 +514,280 @@ impl Renderer {
 
         // define some gradient lerp values for the current scan line
         // make sure to offset them by the x_prestep of the matching gradient step_x
-        let mut px = left.position.value.x + gradients.position.step.x.x * x_prestep;
-        let mut py = left.position.value.y + gradients.position.step.x.y * x_prestep;
-        let mut pz = left.position.value.z + gradients.position.step.x.z * x_prestep;
-
-        let mut tex_coord_x = left.texcoords.value.x + gradients.texcoords.step.x.x * x_prestep;
-        let mut tex_coord_y = left.texcoords.value.y + gradients.texcoords.step.x.y * x_prestep;
-
         let mut one_over_z = left.one_over_z.value + gradients.one_over_z.step.x * x_prestep;
         let mut depth = left.depth.value + gradients.depth.step.x * x_prestep;
-        let mut light_amt = left.light_amp.value + gradients.light_amt.step.x * x_prestep;
-
-        let mut shadow_map_coords_x =
-            left.shadow_map_coords.value.x + gradients.shadow_map_coords.step.x.x * x_prestep;
-        let mut shadow_map_coords_y =
-            left.shadow_map_coords.value.y + gradients.shadow_map_coords.step.x.y * x_prestep;
-        let mut shadow_map_coords_z =
-            left.shadow_map_coords.value.z + gradients.shadow_map_coords.step.x.z * x_prestep;
-
-        // draw min edge
-        // if x_max - x_min > 0 {
-        //     let z = 1.0 / one_over_z;
-        //     let src_x = ((tex_coord_x * z) * (bitmap.width - 1) as f32 + 0.5) as u32;
-        //     let src_y = ((tex_coord_y * z) * (bitmap.height - 1) as f32 + 0.5) as u32;
-
-        //     // copy the pixel from the bitmap
-        //     let texel = bitmap.get_pixel(src_x, src_y);
-        //     self.color_buffer.set_pixel(x_min, y, &texel);
-        // }
+        let mut varyings = left.varyings.value + gradients.varyings.step.x * x_prestep;
 
         for x in x_min..x_max {
             // get the flat index to find the pixel in the depth buffer
-            let index = (x + y * self.width) as usize;
+            let index = tile.index(x, y);
 
             // make sure the pixel is closer to the screen than whatever is currently in the depth buffer
-            if depth < self.depth_buffer[index] {
-                // set the z buffer value
-                self.depth_buffer[index] = depth;
-
-                // we undo perspective texture mapping and get the correct uv from the texture for the current pixel
+            if depth < tile.depth[index] {
+                // we undo perspective texture mapping and get the correct varyings for the current pixel
                 let z = 1.0 / one_over_z;
-                let src_x = ((tex_coord_x * z) * (material.bitmap.width - 1) as f32 + 0.5) as u32;
-                let src_y = ((tex_coord_y * z) * (material.bitmap.height - 1) as f32 + 0.5) as u32;
-
-                // copy the pixel from the bitmap
-                let mut tex_pixel = material.bitmap.get_pixel(src_x, src_y);
-                // let mut tex_pixel = Color::WHITE;
-                // let mut tex_pixel: Color = Vector4::new(px, py, pz, 1.0).into();
-
-                // shadow maping with perspective texture coord correction
-                if let Some(light) = light {
-                    let initial = Vector4::new(
-                        shadow_map_coords_x * z,
-                        shadow_map_coords_y * z,
-                        shadow_map_coords_z * z,
-                        0.0,
-                    );
-
-                    // # debug: see shadow map coords (they're nice and smooth)
-                    // tex_pixel = Color::newf(initial.x, initial.y, initial.z, 1.0);
-
-                    let shadow = Self::calc_shadow_amount(&light.bitmap, initial);
-
-                    if let Some(shadow) = shadow {
-                        // # debug: see the worls through the shadow-map
-                        // tex_pixel = Color::newf(shadow, shadow, shadow, 1.0);
-
-                        if shadow <= 0.5 {
-                            // ~ solution 1: additive
-                            tex_pixel.r = (tex_pixel.r as f32 * 0.6) as u8;
-                            tex_pixel.g = (tex_pixel.g as f32 * 0.6) as u8;
-                            tex_pixel.b = (tex_pixel.b as f32 * 0.6) as u8;
-
-                            // ~ solution 2: fill
-                            // tex_pixel = Color::newf(0.2, 0.2, 0.2, 1.0);
-
-                            // ~ solution 3: dither
-                            // if (x as u32 + y) % 2 == 0 {
-                            //     tex_pixel = Color::BLUE;
-                            // }
-                        }
-                    } else {
-                        // # debug: see where the shadow-map ends
-                        // tex_pixel = Color::BLACK;
-
-                        // tex_pixel.r = (tex_pixel.r as f32 * 0.4) as u8;
-                        tex_pixel.g = (tex_pixel.g as f32 * 0.4) as u8;
-                        // tex_pixel.b = (tex_pixel.b as f32 * 0.4) as u8;
-                    }
-                }
-
-                // # debug: texture coords
-                // tex_pixel = Color::newf(tex_coord_x * z, tex_coord_y * z, 0.0, 1.0);
-
-                // // # debug: draw normals
-                // tex_pixel = Color::newf(normal_x, normal_y, normal_z, 1.0);
 
-                // light it up
-                if material.light {
-                    tex_pixel.r = (tex_pixel.r as f32 * light_amt) as u8;
-                    tex_pixel.g = (tex_pixel.g as f32 * light_amt) as u8;
-                    tex_pixel.b = (tex_pixel.b as f32 * light_amt) as u8;
+                let fragment = Fragment {
+                    x,
+                    y,
+                    depth,
+                    varyings: varyings * z,
+                    one_over_z,
+                    gradients,
+                };
+
+                // fragment stage: the shader decides the color or discards the pixel
+                if let Some(color) = shader.fragment(&fragment) {
+                    // set the z buffer value
+                    tile.depth[index] = depth;
+
+                    // finally set pixel in the color buffer
+                    if shader.writes_color() {
+                        tile.set_pixel(x, y, &color);
+                    }
                 }
-
-                // finally set pixel in the color buffer
-                self.color_buffer.set_pixel(x, y, &tex_pixel);
             } else {
                 // # debug: we can draw a blue pixel when the depth test fails what it means is that
                 // we tried to draw something in a screen position where the z-buffer already has a lower value
-                if self.debug.depth_miss {
+                if debug.depth_miss {
                     if (x as u32 + y) % 2 == 0 {
-                        self.color_buffer.set_pixel(x as u32, y, &Color::BLUE);
+                        tile.set_pixel(x as u32, y, &Color::BLUE);
                     }
                 }
             }
 
             // # debug: draw the depth buffer
-            if self.debug.depth {
-                self.color_buffer.set_pixel(
-                    x as u32,
-                    y,
-                    &Color::newf(depth, depth, 1.0 - depth, 0.5),
-                );
+            if debug.depth {
+                tile.set_pixel(x as u32, y, &Color::newf(depth, depth, 1.0 - depth, 0.5));
             }
 
             // # debug: draw dithered fill over the shape
-            if self.debug.solid {
+            if debug.solid {
                 if (x as u32) % 4 == 0 && (y as u32) % 4 == 0 {
-                    self.color_buffer.set_pixel(x as u32, y, &Color::GREEN);
+                    tile.set_pixel(x as u32, y, &Color::GREEN);
                 }
             }
 
             // step all gradient values for this scan line
-            px += gradients.position.step.x.x;
-            py += gradients.position.step.x.y;
-            pz += gradients.position.step.x.z;
-
-            tex_coord_x += gradients.texcoords.step.x.x;
-            tex_coord_y += gradients.texcoords.step.x.y;
-
             one_over_z += gradients.one_over_z.step.x;
             depth += gradients.depth.step.x;
-            light_amt += gradients.light_amt.step.x;
-
-            shadow_map_coords_x += gradients.shadow_map_coords.step.x.x;
-            shadow_map_coords_y += gradients.shadow_map_coords.step.x.y;
-            shadow_map_coords_z += gradients.shadow_map_coords.step.x.z;
+            varyings += gradients.varyings.step.x;
         }
 
         // # debug: draw wireframe
         // @todo: fix the pixel bleed on opposite edge
-        if self.debug.wireframe {
+        if debug.wireframe {
             let color = Color::newf(1.0, 1.0, 1.0, 0.01);
-            self.color_buffer.set_pixel(x_min, y, &color);
-            self.color_buffer.set_pixel(x_max, y, &color);
+            tile.set_pixel(x_min, y, &color);
+            tile.set_pixel(x_max, y, &color);
         }
     }
+}
 
-    fn calc_shadow_amount(
-        shadow_map: &Bitmap<f32>,
-        initial_shadow_map_coords: Vector4,
-    ) -> Option<f32> {
-        let x = initial_shadow_map_coords.x;
-        let y = initial_shadow_map_coords.y;
-        let z = initial_shadow_map_coords.z;
-
-        // -1.0 to +1.0 on x and y in the shadow_map
-        let normal_x = x * 0.5 + 0.5;
-        let normal_y = -y * 0.5 + 0.5;
-        // let normal_z = z * 0.5 + 0.5;
-
-        // cut if coords are out of bounds
-        // if normal_x < -1.0
-        //     || normal_x > 1.0
-        //     || normal_y < -1.0
-        //     || normal_y > 1.0
-        //     || normal_z < -1.0
-        //     || normal_z > 1.0
-        // {
-        //     return None;
-        // }
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        graphics::{
+            material::Material,
+            shader::{DepthShader, StandardShader},
+            texture::Texture,
+        },
+        math::Vector4,
+    };
+
+    // a bunch of overlapping triangles at different depths, some of them poke out of the view
+    fn test_mesh() -> Mesh {
+        let mut mesh = Mesh::default();
+
+        for i in 0..12 {
+            let x = (i % 4) as f32 - 1.5;
+            let y = (i / 4) as f32 - 1.0;
+            let z = (i % 3) as f32 * 0.5;
+            let size = 0.8 + (i % 5) as f32 * 0.4;
+
+            let start = mesh.vertices.len();
+            mesh.vertices.push(Vertex::new(
+                Vector4::new(x - size, y - size, z, 1.0),
+                Vector4::new(0.0, 1.0, 0.0, 0.0),
+                Vector4::FORWARD,
+            ));
+            mesh.vertices.push(Vertex::new(
+                Vector4::new(x + size, y - size, z, 1.0),
+                Vector4::new(1.0, 1.0, 0.0, 0.0),
+                Vector4::FORWARD,
+            ));
+            mesh.vertices.push(Vertex::new(
+                Vector4::new(x, y + size, z, 1.0),
+                Vector4::new(0.5, 0.0, 0.0, 0.0),
+                Vector4::FORWARD,
+            ));
+            mesh.indices.extend([start, start + 1, start + 2]);
+        }
 
-        // stretch across to fit the shadow_map texture
-        let src_x = (normal_x * (shadow_map.width as f32 - 1.0) + 0.5) as u32;
-        let src_y = (normal_y * (shadow_map.height as f32 - 1.0) + 0.5) as u32;
+        mesh
+    }
 
-        if src_x <= 0
-            || src_x >= shadow_map.width - 1
-            || src_y <= 0
-            || src_y >= shadow_map.height - 1
-        {
-            return None;
+    fn test_material() -> Material {
+        let mut bitmap = Bitmap::new(8, 8);
+        for x in 0..bitmap.width {
+            for y in 0..bitmap.height {
+                bitmap.set_pixel(
+                    x,
+                    y,
+                    &Color::new((x * 30) as u8, (y * 30) as u8, 0x80, 0xFF),
+                );
+            }
+        }
+        Material::new(Arc::new(Texture::new(bitmap)))
+    }
+
+    fn test_view_projection() -> Matrix4 {
+        let projection = Matrix4::perspective(90.0, 97.0 / 61.0, 0.1, 100.0);
+        let mut view = Matrix4::new_identity();
+        view.look_at(
+            Vector4::new(0.0, 0.0, 3.0, 1.0),
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+            Vector4::UP,
+        );
+        Matrix4::multiply(&projection, &view)
+    }
+
+    fn render(material: &Material, parallel: bool) -> Renderer {
+        let mut renderer = Renderer::new(97, 61);
+        renderer.tile_height = 8;
+        renderer.parallel = parallel;
+
+        let shader = StandardShader::new(
+            &test_view_projection(),
+            Vector4::new(0.0, 0.0, 3.0, 1.0),
+            &Matrix4::new_identity(),
+            material,
+            &[],
+        );
+        renderer.draw_mesh(&test_mesh(), &shader);
+
+        renderer
+    }
+
+    #[test]
+    fn test_tiles_match_serial() {
+        let material = test_material();
+        let serial = render(&material, false);
+        let tiled = render(&material, true);
+
+        assert!(serial.depth_buffer.iter().any(|depth| *depth < 1.0));
+        assert_eq!(serial.depth_buffer.pixels, tiled.depth_buffer.pixels);
+        assert_eq!(serial.color_buffer.pixels, tiled.color_buffer.pixels);
+    }
+
+    #[test]
+    fn test_opaque() {
+        let solid = render(&test_material(), false);
+
+        // an opaque material ignores its alpha, otherwise it's blended with the cleared buffer
+        let mut material = test_material();
+        material.color.w = 0.5;
+        material.opaque = true;
+        assert_eq!(
+            render(&material, false).color_buffer.pixels,
+            solid.color_buffer.pixels
+        );
+
+        material.opaque = false;
+        assert_ne!(
+            render(&material, false).color_buffer.pixels,
+            solid.color_buffer.pixels
+        );
+    }
+
+    #[test]
+    fn test_depth_only() {
+        let mut material = test_material();
+        material.alpha_cutoff = 0.5;
+        let standard = render(&material, true);
+
+        let mut renderer = Renderer::new(97, 61);
+        renderer.tile_height = 8;
+        let shader = DepthShader::new(&test_view_projection(), &Matrix4::new_identity(), &material);
+        renderer.draw_mesh(&test_mesh(), &shader);
+
+        // the same depth without touching the colors
+        assert_eq!(renderer.depth_buffer.pixels, standard.depth_buffer.pixels);
+        assert!(renderer.color_buffer.pixels.iter().all(|byte| *byte == 0));
+
+        // cut out pixels are discarded like in the standard shader
+        material.color.w = 0.25;
+        let mut renderer = Renderer::new(97, 61);
+        let shader = DepthShader::new(&test_view_projection(), &Matrix4::new_identity(), &material);
+        renderer.draw_mesh(&test_mesh(), &shader);
+        assert!(renderer.depth_buffer.iter().all(|depth| *depth == 1.0));
+    }
+
+    #[test]
+    fn test_double_sided() {
+        // look at the test mesh from behind
+        let eye = Vector4::new(0.0, 0.0, -3.0, 1.0);
+        let mut view = Matrix4::new_identity();
+        view.look_at(eye, Vector4::new(0.0, 0.0, 0.0, 1.0), Vector4::UP);
+        let view_projection =
+            Matrix4::multiply(&Matrix4::perspective(90.0, 1.0, 0.1, 100.0), &view);
+
+        let mut material = test_material();
+        let mut drawn = vec![];
+
+        for double_sided in [false, true] {
+            material.double_sided = double_sided;
+            let shader = StandardShader::new(
+                &view_projection,
+                eye,
+                &Matrix4::new_identity(),
+                &material,
+                &[],
+            );
+
+            let mut renderer = Renderer::new(32, 32);
+            renderer.draw_mesh(&test_mesh(), &shader);
+            drawn.push(renderer.depth_buffer.iter().any(|depth| *depth < 1.0));
+        }
+
+        // back faces are culled unless the material is double sided
+        assert_eq!(drawn, vec![false, true]);
+    }
+
+    // discards every pixel of the mesh
+    struct DiscardShader {
+        mvp: Matrix4,
+    }
+
+    impl Shader for DiscardShader {
+        fn vertex(&self, vertex: &Vertex, _varyings: &mut Varyings) -> Vector4 {
+            Matrix4::multiply_vector(&self.mvp, vertex.position)
         }
 
-        return Some(Self::sample_shadow_map(shadow_map, src_x, src_y, z));
+        fn fragment(&self, _fragment: &Fragment) -> Option<Color> {
+            None
+        }
     }
 
-    fn sample_shadow_map(shadow_map: &Bitmap<f32>, x: u32, y: u32, compare: f32) -> f32 {
-        return if shadow_map.get_pixel(x, y).0 < compare - 0.01 {
-            0.0
-        } else {
-            1.0
+    #[test]
+    fn test_fragment_discard() {
+        let mut renderer = Renderer::new(32, 32);
+        let mut view = Matrix4::new_identity();
+        view.look_at(
+            Vector4::new(0.0, 0.0, 3.0, 1.0),
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+            Vector4::UP,
+        );
+        let shader = DiscardShader {
+            mvp: Matrix4::multiply(&Matrix4::perspective(90.0, 1.0, 0.1, 100.0), &view),
         };
+
+        renderer.draw_mesh(&test_mesh(), &shader);
+
+        assert!(renderer.depth_buffer.iter().all(|depth| *depth == 1.0));
+        assert!(renderer.color_buffer.pixels.iter().all(|byte| *byte == 0));
     }
 }
@@ -0,0 +1,386 @@
+use crate::graphics::light::Light;
+use crate::math::{
+    linear_algebra::{matrix::Matrix4, vector::Vector4},
+    transform::Transform,
+};
+
+use super::{camera::Camera, instance::Instance, renderer::Renderer};
+
+// a tree of nodes, every node is placed relative to its parent and takes its children along when it moves
+//
+//   plane                  (flies around)
+//    ├── propeller left    (spins, stays on the wing)
+//    └── propeller right
+//   house
+//    └── lantern           (a light that stays by the door)
+//
+// world = parent.world * local
+//
+// the things that are drawn (instances), the lights and the cameras stay in flat lists like before, a node
+// points into them and `update` moves them to the world matrix of their node, the renderer and the
+// shadow-maps never see the tree
+//
+// instances and lights that don't belong to a node stay where they are
+// lights and cameras look down the -z axis of their node
+#[derive(Debug)]
+pub struct Node {
+    pub name: String,
+    pub instance: Option<usize>, // index into `Scene.instances`
+    pub light: Option<usize>,    // index into `Scene.lights`
+    pub camera: Option<usize>,   // index into `Scene.cameras`
+    local: Transform,
+    world: Matrix4,        // parent.world * local
+    parent: Option<usize>, // index into the nodes of the scene
+    children: Vec<usize>,
+    dirty: bool, // the local transform changed since the last update
+}
+
+impl Node {
+    pub fn local(&self) -> &Transform {
+        &self.local
+    }
+
+    // as of the last `Scene::update`
+    pub fn world(&self) -> &Matrix4 {
+        &self.world
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[usize] {
+        &self.children
+    }
+}
+
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Node>,
+    pub instances: Vec<Instance>,
+    pub lights: Vec<Light>,
+    pub cameras: Vec<Camera>,
+    moved: Vec<usize>, // the instances that the last update moved
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // an empty node (a pivot or a group), `parent` is the node it's attached to
+    pub fn add_node(&mut self, name: &str, parent: Option<usize>, local: Transform) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            instance: None,
+            light: None,
+            camera: None,
+            world: local.matrix().clone(),
+            local,
+            parent: None,
+            children: Vec::new(),
+            dirty: true,
+        });
+        self.set_parent(index, parent);
+        index
+    }
+
+    // the instance is drawn with the world matrix of the node, its own transform isn't used anymore
+    pub fn add_instance(
+        &mut self,
+        name: &str,
+        parent: Option<usize>,
+        local: Transform,
+        instance: Instance,
+    ) -> usize {
+        let node = self.add_node(name, parent, local);
+        self.nodes[node].instance = Some(self.instances.len());
+        self.instances.push(instance);
+        node
+    }
+
+    // the position and direction of the light are replaced by the ones of the node
+    pub fn add_light(
+        &mut self,
+        name: &str,
+        parent: Option<usize>,
+        local: Transform,
+        light: Light,
+    ) -> usize {
+        let node = self.add_node(name, parent, local);
+        self.nodes[node].light = Some(self.lights.len());
+        self.lights.push(light);
+        node
+    }
+
+    pub fn add_camera(
+        &mut self,
+        name: &str,
+        parent: Option<usize>,
+        local: Transform,
+        camera: Camera,
+    ) -> usize {
+        let node = self.add_node(name, parent, local);
+        self.nodes[node].camera = Some(self.cameras.len());
+        self.cameras.push(camera);
+        node
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn node(&self, node: usize) -> &Node {
+        &self.nodes[node]
+    }
+
+    // the first node with the name
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    // the node and everything attached to it moves on the next update
+    pub fn transform_mut(&mut self, node: usize) -> &mut Transform {
+        let node = &mut self.nodes[node];
+        node.dirty = true;
+        &mut node.local
+    }
+
+    pub fn instance_mut(&mut self, node: usize) -> Option<&mut Instance> {
+        self.nodes[node]
+            .instance
+            .map(|instance| &mut self.instances[instance])
+    }
+
+    pub fn light_mut(&mut self, node: usize) -> Option<&mut Light> {
+        self.nodes[node].light.map(|light| &mut self.lights[light])
+    }
+
+    pub fn camera_mut(&mut self, node: usize) -> Option<&mut Camera> {
+        self.nodes[node]
+            .camera
+            .map(|camera| &mut self.cameras[camera])
+    }
+
+    // attaches the node to another one (or to the world with `None`), the local transform is kept so the
+    // node jumps to the same place relative to its new parent
+    pub fn set_parent(&mut self, node: usize, parent: Option<usize>) {
+        // a node can't be attached to itself or to one of its children
+        let mut ancestor = parent;
+        while let Some(index) = ancestor {
+            assert!(index != node, "node {node} can't be its own ancestor");
+            ancestor = self.nodes[index].parent;
+        }
+
+        if let Some(old) = self.nodes[node].parent {
+            self.nodes[old].children.retain(|&child| child != node);
+        }
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(node);
+        }
+
+        self.nodes[node].parent = parent;
+        self.nodes[node].dirty = true;
+    }
+
+    // depth-first from the roots, a parent is visited before its children (with its depth in the tree)
+    pub fn traverse(&self, mut visit: impl FnMut(usize, &Node, usize)) {
+        let mut stack: Vec<(usize, usize)> = self.roots().rev().map(|root| (root, 0)).collect();
+
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            visit(index, node, depth);
+            stack.extend(node.children.iter().rev().map(|&child| (child, depth + 1)));
+        }
+    }
+
+    // finds the world matrices of the nodes that moved (or whose parents moved) and places what's attached
+    // to them, nothing else is touched
+    pub fn update(&mut self) {
+        self.moved.clear();
+
+        // (node, did its parent move)
+        let mut stack: Vec<(usize, bool)> = self.roots().rev().map(|root| (root, false)).collect();
+
+        while let Some((index, parent_moved)) = stack.pop() {
+            let moved = parent_moved || self.nodes[index].dirty;
+
+            if moved {
+                // multiplied as matrices, a turned child of a stretched parent gets skewed and the parts
+                // of a transform can't hold that
+                let local = self.nodes[index].local.matrix();
+                let world = match self.nodes[index].parent {
+                    Some(parent) => Matrix4::multiply(&self.nodes[parent].world, local),
+                    None => local.clone(),
+                };
+                self.place(index, &world);
+
+                let node = &mut self.nodes[index];
+                node.world = world;
+                node.dirty = false;
+            }
+
+            let node = &self.nodes[index];
+            stack.extend(node.children.iter().rev().map(|&child| (child, moved)));
+        }
+    }
+
+    // indices into `instances`, as of the last `update`
+    pub fn moved(&self) -> &[usize] {
+        &self.moved
+    }
+
+    // every instance with the lights of the scene, call `update` first
+    pub fn draw(&self, renderer: &mut Renderer, view_projection: &Matrix4, eye: Vector4) {
+        for instance in self.instances.iter() {
+            instance.draw(renderer, view_projection, eye, &self.lights);
+        }
+    }
+
+    fn roots(&self) -> impl DoubleEndedIterator<Item = usize> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.parent.is_none())
+            .map(|(index, _)| index)
+    }
+
+    // moves what's attached to a node to its world matrix
+    fn place(&mut self, index: usize, world: &Matrix4) {
+        let node = &self.nodes[index];
+        let forward =
+            Matrix4::multiply_vector(world, Vector4::new(0.0, 0.0, -1.0, 0.0)).normalized();
+
+        if let Some(instance) = node.instance {
+            self.instances[instance].world = Some(world.clone());
+            self.moved.push(instance);
+        }
+        if let Some(light) = node.light {
+            let light = &mut self.lights[light];
+            light.position = world.translation();
+            light.direction = forward;
+        }
+        if let Some(camera) = node.camera {
+            // a camera can't be skewed, it's only moved to the node and turned the way its -z axis points
+            let up = Matrix4::multiply_vector(world, Vector4::UP);
+            let transform = &mut self.cameras[camera].transform;
+            transform.set_translation(world.translation());
+            transform.look_at(world.translation() + forward, up);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{
+        graphics::{material::Material, mesh::Mesh},
+        math::Quaternion,
+    };
+
+    fn assert_near(a: Vector4, b: Vector4) {
+        assert!((a - b).length() < 0.0001, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn test_scene_graph() {
+        let mut scene = Scene::new();
+
+        // a plane turned to face -x with a propeller on its nose (+z) and a light under its wing
+        let mut local = Transform::from_translation(Vector4::new(10.0, 5.0, 0.0, 1.0));
+        local.set_rotation(Quaternion::from_euler(Vector4::new(0.0, -90.0, 0.0, 0.0)));
+        let plane = scene.add_node("plane", None, local);
+
+        let instance = Instance::new(
+            Rc::new(Box::new(Mesh::new(vec![], vec![]))),
+            Rc::new(Material::default()),
+        );
+        let propeller = scene.add_instance(
+            "propeller",
+            Some(plane),
+            Transform::from_translation(Vector4::new(0.0, 0.0, 2.0, 1.0)),
+            instance,
+        );
+        let light = scene.add_light(
+            "light",
+            Some(plane),
+            Transform::from_translation(Vector4::new(1.0, 0.0, 0.0, 1.0)),
+            Light::point(Vector4::ZERO, 5.0),
+        );
+
+        let mut order = vec![];
+        scene.traverse(|index, _, depth| order.push((index, depth)));
+        assert_eq!(order, vec![(plane, 0), (propeller, 1), (light, 1)]);
+
+        scene.update();
+        assert_near(
+            scene.instances[0].matrix().translation(),
+            Vector4::new(8.0, 5.0, 0.0, 1.0),
+        );
+        assert_near(scene.lights[0].position, Vector4::new(10.0, 5.0, 1.0, 1.0));
+
+        // the children follow their parent
+        scene
+            .transform_mut(plane)
+            .translate(Vector4::new(0.0, 1.0, 0.0, 0.0));
+        scene.update();
+        assert_near(
+            scene.node(propeller).world().translation(),
+            Vector4::new(8.0, 6.0, 0.0, 1.0),
+        );
+        assert_near(scene.lights[0].position, Vector4::new(10.0, 6.0, 1.0, 1.0));
+
+        assert_eq!(scene.moved(), [0]);
+
+        // nodes that didn't move are left alone
+        scene.lights[0].position = Vector4::ZERO;
+        scene.update();
+        assert_eq!(scene.lights[0].position, Vector4::ZERO);
+        assert!(scene.moved().is_empty());
+
+        // detached, the local transform is now relative to the world
+        scene.set_parent(propeller, None);
+        scene.update();
+        assert!(scene.node(plane).children() == [light]);
+        assert_near(
+            scene.instances[0].matrix().translation(),
+            Vector4::new(0.0, 0.0, 2.0, 1.0),
+        );
+        assert_eq!(scene.find("light"), Some(light));
+    }
+
+    #[test]
+    fn test_scene_graph_skew() {
+        let mut scene = Scene::new();
+
+        // a stretched parent with a turned child, the child ends up skewed
+        let mut local = Transform::from_translation(Vector4::new(1.0, 2.0, 3.0, 1.0));
+        local.set_scale(Vector4::new(3.0, 1.0, 1.0, 0.0));
+        let parent = scene.add_node("parent", None, local.clone());
+
+        let mut child_local = Transform::from_translation(Vector4::new(1.0, 0.0, 0.0, 1.0));
+        child_local.set_rotation(Quaternion::from_euler(Vector4::new(0.0, 0.0, 45.0, 0.0)));
+        let instance = Instance::new(
+            Rc::new(Box::new(Mesh::new(vec![], vec![]))),
+            Rc::new(Material::default()),
+        );
+        scene.add_instance("child", Some(parent), child_local.clone(), instance);
+        scene.update();
+
+        // the same as multiplying the matrices, whatever the point
+        let expected = Matrix4::multiply(local.matrix(), child_local.matrix());
+        for point in [
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+            Vector4::new(1.0, 1.0, 0.0, 1.0),
+            Vector4::new(-2.0, 0.5, 4.0, 1.0),
+        ] {
+            assert_near(
+                Matrix4::multiply_vector(scene.instances[0].matrix(), point),
+                Matrix4::multiply_vector(&expected, point),
+            );
+        }
+    }
+}
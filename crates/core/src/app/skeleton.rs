@@ -0,0 +1,433 @@
+use std::rc::Rc;
+
+use crate::{
+    graphics::mesh::Mesh,
+    math::{
+        linear_algebra::{matrix::Matrix4, quaternion::Quaternion, vector::Vector4},
+        transform::Transform,
+    },
+};
+
+use super::{instance::Instance, morph::Playback};
+
+// skeletal animation with linear blend skinning
+//
+//         root                  every joint has a transform relative to its parent (the pose),
+//        /    \                 the world matrix of a joint is parent.world * offset * local
+//     spine   hip
+//       |       \               a vertex is moved by up to 4 joints:
+//     head      knee              p' = sum(weight[i] * world[i] * inverse_bind[i] * p)
+//
+// the inverse bind matrix takes a vertex from the mesh into the space of the joint (where it was when the mesh
+// was bound to the skeleton), the world matrix takes it back out to where the joint is now
+//
+// the mesh is skinned on the cpu into a new mesh, the renderer (and the shadows) then draw it like any other
+#[derive(Debug)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>, // index into `Skeleton.joints`
+    pub inverse_bind: Matrix4,
+    pub rest: Transform, // the pose of the joint when no clip moves it
+    // what's between the parent joint and this one (nodes that aren't joints), for a joint without a parent it's
+    // everything above it, usually the identity
+    pub offset: Matrix4,
+}
+
+#[derive(Debug)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    pub root: Matrix4, // moves the whole skeleton (the joints that don't have a parent)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,   // holds a keyframe until the next one
+    Linear, // rotations are blended along the shortest path
+}
+
+// keyframes of one value, the times are in seconds and go up
+#[derive(Debug, Clone)]
+pub struct Track<T> {
+    pub times: Vec<f32>,
+    pub values: Vec<T>,
+    pub interpolation: Interpolation,
+}
+
+// the tracks that move a joint, a missing track leaves that part of the pose alone
+#[derive(Debug, Clone)]
+pub struct JointTrack {
+    pub joint: usize, // index into `Skeleton.joints`
+    pub translation: Option<Track<Vector4>>,
+    pub rotation: Option<Track<Quaternion>>,
+    pub scale: Option<Track<Vector4>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32, // seconds
+    pub tracks: Vec<JointTrack>,
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>) -> Self {
+        Self {
+            joints,
+            root: Matrix4::new_identity(),
+        }
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest.clone()).collect()
+    }
+
+    // the world matrix of every joint, the parents don't have to come before their children
+    pub fn world_matrices(&self, pose: &[Transform]) -> Vec<Matrix4> {
+        let mut world: Vec<Option<Matrix4>> = vec![None; self.joints.len()];
+
+        for index in 0..self.joints.len() {
+            // walk up to the first joint that's done (or the root) and come back down
+            let mut chain = vec![index];
+            while let Some(parent) = self.joints[*chain.last().unwrap()].parent {
+                if world[parent].is_some() || chain.contains(&parent) {
+                    break;
+                }
+                chain.push(parent);
+            }
+
+            for &joint in chain.iter().rev() {
+                if world[joint].is_some() {
+                    continue;
+                }
+
+                let parent = match self.joints[joint].parent {
+                    Some(parent) => world[parent].as_ref().unwrap_or(&self.root),
+                    None => &self.root,
+                };
+                let parent = Matrix4::multiply(parent, &self.joints[joint].offset);
+                world[joint] = Some(Matrix4::multiply(&parent, pose[joint].matrix()));
+            }
+        }
+
+        world.into_iter().map(|matrix| matrix.unwrap()).collect()
+    }
+
+    // world * inverse bind, what `skin_mesh` needs
+    pub fn skinning_matrices(&self, pose: &[Transform]) -> Vec<Matrix4> {
+        self.world_matrices(pose)
+            .iter()
+            .zip(self.joints.iter())
+            .map(|(world, joint)| Matrix4::multiply(world, &joint.inverse_bind))
+            .collect()
+    }
+}
+
+impl<T: Copy> Track<T> {
+    // the value at `time`, before the first keyframe it's the first and after the last it's the last
+    pub fn sample(&self, time: f32, lerp: impl Fn(T, T, f32) -> T) -> Option<T> {
+        let first = *self.values.first()?;
+        let next = self.times.partition_point(|&t| t <= time);
+
+        if next == 0 {
+            return Some(first);
+        }
+        if next >= self.times.len() {
+            return self.values.last().copied();
+        }
+
+        let (from, to) = (self.times[next - 1], self.times[next]);
+        let value = match self.interpolation {
+            Interpolation::Step => self.values[next - 1],
+            Interpolation::Linear => {
+                let factor = if to > from {
+                    (time - from) / (to - from)
+                } else {
+                    0.0
+                };
+                lerp(self.values[next - 1], self.values[next], factor)
+            }
+        };
+
+        Some(value)
+    }
+}
+
+impl AnimationClip {
+    // moves the joints of `pose` that have tracks, the others keep their values
+    pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+        let lerp = |a: Vector4, b: Vector4, factor: f32| a.lerp(b, factor);
+
+        for track in self.tracks.iter() {
+            let Some(joint) = pose.get_mut(track.joint) else {
+                continue;
+            };
+
+            if let Some(translation) = track.translation.as_ref() {
+                if let Some(translation) = translation.sample(time, lerp) {
+                    joint.set_translation(translation);
+                }
+            }
+            if let Some(rotation) = track.rotation.as_ref() {
+                if let Some(rotation) = rotation.sample(time, |a, b, factor| a.slerp(b, factor)) {
+                    joint.set_rotation(rotation);
+                }
+            }
+            if let Some(scale) = track.scale.as_ref() {
+                if let Some(scale) = scale.sample(time, lerp) {
+                    joint.set_scale(scale);
+                }
+            }
+        }
+    }
+}
+
+// moves the vertices of `bind` (the mesh the way it was bound to the skeleton) into `mesh`
+//
+// `mesh` needs the same vertices as `bind`, vertices without weights stay where they are
+// the weights don't have to add up to 1 (quantized weights, joints that don't exist), they're scaled so they do
+pub fn skin_mesh(bind: &Mesh, matrices: &[Matrix4], mesh: &mut Mesh) {
+    for (vertex, source) in mesh.vertices.iter_mut().zip(bind.vertices.iter()) {
+        let weights = [
+            source.weights.x,
+            source.weights.y,
+            source.weights.z,
+            source.weights.w,
+        ];
+
+        // the joint matrices are blended and then used like a model matrix
+        let mut skin = Matrix4::new();
+        let mut total = 0.0;
+        for (&joint, weight) in source.joints.iter().zip(weights) {
+            let Some(matrix) = matrices.get(joint as usize) else {
+                continue;
+            };
+            if weight == 0.0 {
+                continue;
+            }
+
+            for col in 0..4 {
+                for row in 0..4 {
+                    skin[col][row] += matrix[col][row] * weight;
+                }
+            }
+            total += weight;
+        }
+
+        if total == 0.0 {
+            *vertex = *source;
+            continue;
+        }
+
+        // otherwise w ends up being the total and the perspective divide scales the vertex
+        for col in 0..4 {
+            for row in 0..4 {
+                skin[col][row] /= total;
+            }
+        }
+
+        // the normals are only right for joints without a non-uniform scale (which is almost always)
+        // blending turned matrices shortens the directions, so they're made unit length again
+        *vertex = source.transform(&skin, &skin);
+        if vertex.normal.length() > 0.0 {
+            vertex.normal = vertex.normal.normalized();
+        }
+        let handedness = vertex.tangent.w;
+        vertex.tangent.w = 0.0;
+        if vertex.tangent.length() > 0.0 {
+            vertex.tangent = vertex.tangent.normalized();
+        }
+        vertex.tangent.w = handedness;
+    }
+}
+
+// plays a clip on a skinned mesh, like `MorphAnimation` it writes the result into an instance
+#[derive(Debug)]
+pub struct SkinnedAnimation {
+    pub bind: Rc<Box<Mesh>>, // the mesh the way it was bound to the skeleton
+    pub skeleton: Rc<Skeleton>,
+    pub clip: Option<Rc<AnimationClip>>, // no clip shows the rest pose
+    pub playback: Playback,
+    pub time: f32,
+    pub speed: f32, // 1 is normal speed, negative plays backwards
+}
+
+impl SkinnedAnimation {
+    pub fn new(bind: Rc<Box<Mesh>>, skeleton: Rc<Skeleton>) -> Self {
+        Self {
+            bind,
+            skeleton,
+            clip: None,
+            playback: Playback::Loop,
+            time: 0.0,
+            speed: 1.0,
+        }
+    }
+
+    // starts a clip from the beginning
+    pub fn play(&mut self, clip: Rc<AnimationClip>, playback: Playback) {
+        self.clip = Some(clip);
+        self.playback = playback;
+        self.time = 0.0;
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt * self.speed;
+
+        let duration = self.clip.as_ref().map_or(0.0, |clip| clip.duration);
+        if duration > 0.0 {
+            self.time = match self.playback {
+                Playback::Once => self.time.clamp(0.0, duration),
+                Playback::Loop => self.time.rem_euclid(duration),
+                Playback::PingPong => self.time.rem_euclid(2.0 * duration),
+            };
+        }
+    }
+
+    // the pose at the current time, ping-pong plays the clip backwards every other time
+    pub fn pose(&self) -> Vec<Transform> {
+        let mut pose = self.skeleton.rest_pose();
+
+        if let Some(clip) = self.clip.as_ref() {
+            let time = match self.playback {
+                Playback::PingPong if self.time > clip.duration => 2.0 * clip.duration - self.time,
+                _ => self.time,
+            };
+            clip.sample(time, &mut pose);
+        }
+
+        pose
+    }
+
+    // the skinned mesh at the current time
+    pub fn mesh(&self) -> Mesh {
+        let mut mesh = Mesh::new(self.bind.vertices.clone(), self.bind.indices.clone());
+        self.skin(&mut mesh);
+        mesh
+    }
+
+    pub fn skin(&self, mesh: &mut Mesh) {
+        let matrices = self.skeleton.skinning_matrices(&self.pose());
+        skin_mesh(&self.bind, &matrices, mesh);
+    }
+
+    // the instance gets the skinned mesh, the mesh is reused when nothing else holds on to it
+    pub fn apply(&self, instance: &mut Instance) {
+        match Rc::get_mut(&mut instance.mesh) {
+            Some(mesh) if mesh.vertices.len() == self.bind.vertices.len() => self.skin(mesh),
+            _ => instance.mesh = Rc::new(Box::new(self.mesh())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::vertex::Vertex;
+
+    fn assert_near(a: Vector4, b: Vector4) {
+        assert!((a - b).length() < 0.0001, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn test_skinning() {
+        // an arm along +x: the shoulder at the origin and the elbow 1 to the right
+        let elbow_rest = Transform::from_translation(Vector4::new(1.0, 0.0, 0.0, 1.0));
+        let mut elbow_inverse_bind = Matrix4::new_identity();
+        elbow_inverse_bind.translate(-1.0, 0.0, 0.0);
+
+        let skeleton = Rc::new(Skeleton::new(vec![
+            Joint {
+                name: "shoulder".to_string(),
+                parent: None,
+                inverse_bind: Matrix4::new_identity(),
+                rest: Transform::default(),
+                offset: Matrix4::new_identity(),
+            },
+            Joint {
+                name: "elbow".to_string(),
+                parent: Some(0),
+                inverse_bind: elbow_inverse_bind,
+                rest: elbow_rest,
+                offset: Matrix4::new_identity(),
+            },
+        ]));
+
+        // the hand only follows the elbow, the middle of the forearm follows both
+        let vertex = |x: f32, joints: [u16; 4], weights: Vector4| {
+            Vertex::new(Vector4::new(x, 0.0, 0.0, 1.0), Vector4::ZERO, Vector4::UP)
+                .with_tangent(Vector4::new(1.0, 0.0, 0.0, -1.0))
+                .with_skin(joints, weights)
+        };
+        let bind = Rc::new(Box::new(Mesh::new(
+            vec![
+                vertex(2.0, [1, 0, 0, 0], Vector4::new(1.0, 0.0, 0.0, 0.0)),
+                vertex(1.5, [0, 1, 0, 0], Vector4::new(0.5, 0.5, 0.0, 0.0)),
+                vertex(3.0, [0; 4], Vector4::ZERO),
+                // quantized weights that don't add up to 1 and a joint that doesn't exist
+                vertex(1.5, [0, 1, 0, 0], Vector4::new(0.498, 0.498, 0.0, 0.0)),
+                vertex(2.0, [9, 1, 0, 0], Vector4::new(0.5, 0.5, 0.0, 0.0)),
+            ],
+            vec![0, 1, 2, 3, 4, 2],
+        )));
+
+        // the rest pose is the bind pose
+        let mut animation = SkinnedAnimation::new(Rc::clone(&bind), Rc::clone(&skeleton));
+        let mesh = animation.mesh();
+        for (a, b) in mesh.vertices.iter().zip(bind.vertices.iter()) {
+            assert_near(a.position, b.position);
+        }
+
+        // bend the elbow 90 degrees around z over 1 second
+        let half_turn = std::f32::consts::FRAC_1_SQRT_2;
+        let clip = Rc::new(AnimationClip {
+            name: "bend".to_string(),
+            duration: 1.0,
+            tracks: vec![JointTrack {
+                joint: 1,
+                translation: None,
+                rotation: Some(Track {
+                    times: vec![0.0, 1.0],
+                    values: vec![
+                        Quaternion::new(0.0, 0.0, 0.0, 1.0),
+                        Quaternion::new(0.0, 0.0, half_turn, half_turn),
+                    ],
+                    interpolation: Interpolation::Linear,
+                }),
+                scale: None,
+            }],
+        });
+        animation.play(clip, Playback::Once);
+        animation.update(2.0);
+        assert_eq!(animation.time, 1.0);
+
+        let mut instance = Instance::new(Rc::clone(&bind), Rc::default());
+        animation.apply(&mut instance);
+        assert!(!Rc::ptr_eq(&instance.mesh, &bind));
+
+        let vertices = &instance.mesh.vertices;
+        assert_near(vertices[0].position, Vector4::new(1.0, 1.0, 0.0, 1.0));
+        assert_near(vertices[0].normal, Vector4::new(-1.0, 0.0, 0.0, 0.0));
+        assert_near(vertices[1].position, Vector4::new(1.25, 0.25, 0.0, 1.0));
+        assert_near(vertices[2].position, Vector4::new(3.0, 0.0, 0.0, 1.0));
+        assert_near(vertices[3].position, vertices[1].position);
+        assert_near(vertices[4].position, vertices[0].position);
+
+        // the blended tangent is unit length again and keeps its handedness
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert_near(
+            vertices[1].tangent,
+            Vector4::new(diagonal, diagonal, 0.0, -1.0),
+        );
+
+        // halfway is 45 degrees
+        let pose = {
+            animation.time = 0.5;
+            animation.pose()
+        };
+        let hand = Matrix4::multiply_vector(
+            &skeleton.world_matrices(&pose)[1],
+            Vector4::new(1.0, 0.0, 0.0, 1.0),
+        );
+        assert_near(hand, Vector4::new(1.0 + half_turn, half_turn, 0.0, 1.0));
+    }
+}
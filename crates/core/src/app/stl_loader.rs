@@ -0,0 +1,248 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{graphics::mesh::Mesh, math::linear_algebra::vector::Vector4};
+
+use super::mesh_loader::{to_mesh, IndexedModel, LineParser, MeshLoadError, MeshLoadErrorKind};
+
+// stereolithography (.stl), loose triangles with a normal each, the format that cad programs and slicers use
+//
+// binary:                          ascii:
+//   header       80 bytes            solid name
+//   count        u32                   facet normal nx ny nz
+//   triangles    count x                 outer loop
+//     normal     3 x f32                   vertex x y z
+//     vertices   9 x f32                   vertex x y z
+//     attribute  u16                       vertex x y z
+//                                        endloop
+//                                      endfacet
+//                                    endsolid name
+//
+// there's nothing else (no texture coordinates or colors) and the triangles don't share their vertices
+// which makes every triangle flat shaded
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+pub fn load_stl(filepath: &Path) -> Result<Mesh, MeshLoadError> {
+    let path = filepath.display().to_string();
+    let bytes = std::fs::read(filepath)
+        .map_err(|err| MeshLoadError::new(&path, 0, 0, MeshLoadErrorKind::Io(err)))?;
+
+    read_stl(&bytes, &path)
+}
+
+// `path` is only used for the errors
+pub fn read_stl(bytes: &[u8], path: &str) -> Result<Mesh, MeshLoadError> {
+    // ascii files start with "solid", but so do the headers of some binary files, their size gives them away
+    let binary_size = bytes
+        .get(HEADER_SIZE..HEADER_SIZE + 4)
+        .map(|count| HEADER_SIZE + 4 + u32_at(count, 0) as usize * TRIANGLE_SIZE);
+
+    let triangles = if bytes.starts_with(b"solid") && binary_size != Some(bytes.len()) {
+        read_ascii(bytes, path)?
+    } else {
+        read_binary(bytes, path)?
+    };
+
+    let mut model = IndexedModel::default();
+    for (normal, corners) in triangles {
+        // some exporters leave the normals out (zero), the winding still knows where the front is
+        let normal = if normal.length() > 0.0 {
+            normal.normalized()
+        } else {
+            face_normal(corners)
+        };
+
+        for position in corners {
+            model.indices.push(model.vertices.len());
+            model.vertices.push(position);
+            model.tex_coords.push(Vector4::ZERO);
+            model.normals.push(normal);
+        }
+    }
+
+    Ok(to_mesh(model))
+}
+
+pub fn save_stl(filepath: &Path, mesh: &Mesh) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filepath)?);
+    write_stl(&mut writer, mesh)?;
+    writer.flush()
+}
+
+// binary stl, the normal of every triangle is found from its winding
+pub fn write_stl(mut writer: impl Write, mesh: &Mesh) -> io::Result<()> {
+    let mut header = [0; HEADER_SIZE];
+    let name = b"soft-rend";
+    header[..name.len()].copy_from_slice(name);
+    writer.write_all(&header)?;
+
+    let count = u32::try_from(mesh.indices.len() / 3)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many triangles"))?;
+    writer.write_all(&count.to_le_bytes())?;
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let corners = [
+            mesh.vertices[triangle[0]].position,
+            mesh.vertices[triangle[1]].position,
+            mesh.vertices[triangle[2]].position,
+        ];
+
+        let normal = face_normal(corners);
+        for v in [normal, corners[0], corners[1], corners[2]] {
+            for value in [v.x, v.y, v.z] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        writer.write_all(&0u16.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn read_binary(bytes: &[u8], path: &str) -> Result<Vec<(Vector4, [Vector4; 3])>, MeshLoadError> {
+    let truncated = || {
+        let err = io::Error::new(io::ErrorKind::UnexpectedEof, "the file ends too early");
+        MeshLoadError::new(path, 0, 0, MeshLoadErrorKind::Io(err))
+    };
+
+    let count = bytes
+        .get(HEADER_SIZE..HEADER_SIZE + 4)
+        .ok_or_else(truncated)?;
+    let count = u32_at(count, 0) as usize;
+
+    let body = &bytes[HEADER_SIZE + 4..];
+    if body.len() < count * TRIANGLE_SIZE {
+        return Err(truncated());
+    }
+
+    let triangles = body
+        .chunks_exact(TRIANGLE_SIZE)
+        .take(count)
+        .map(|triangle| {
+            let vector = |i: usize| {
+                let f = |j: usize| f32::from_bits(u32_at(triangle, (i * 3 + j) * 4));
+                Vector4::new(f(0), f(1), f(2), 1.0)
+            };
+
+            let mut normal = vector(0);
+            normal.w = 0.0;
+            (normal, [vector(1), vector(2), vector(3)])
+        })
+        .collect();
+
+    Ok(triangles)
+}
+
+fn read_ascii(bytes: &[u8], path: &str) -> Result<Vec<(Vector4, [Vector4; 3])>, MeshLoadError> {
+    let text = String::from_utf8_lossy(bytes);
+
+    let mut triangles = vec![];
+    let mut normal = Vector4::ZERO;
+    let mut corners = vec![];
+
+    for (line_index, line) in text.lines().enumerate() {
+        let mut parser = LineParser::new(line, path, line_index + 1);
+
+        let Some(keyword) = parser.next() else {
+            continue;
+        };
+
+        match keyword {
+            "facet" => {
+                parser.next(); // normal
+                normal = Vector4::new(parser.float()?, parser.float()?, parser.float()?, 0.0);
+            }
+            "vertex" => {
+                if corners.len() == 3 {
+                    let kind = MeshLoadErrorKind::BadIndex(line.trim().to_string());
+                    return Err(parser.error(keyword, kind));
+                }
+                corners.push(Vector4::new(
+                    parser.float()?,
+                    parser.float()?,
+                    parser.float()?,
+                    1.0,
+                ));
+            }
+            "endfacet" => {
+                // a facet with less than 3 vertices
+                let corners: [Vector4; 3] =
+                    std::mem::take(&mut corners).try_into().map_err(|_| {
+                        let kind = MeshLoadErrorKind::BadIndex(line.trim().to_string());
+                        parser.error(keyword, kind)
+                    })?;
+                triangles.push((normal, corners));
+            }
+            "solid" | "outer" | "endloop" | "endsolid" => {}
+            _ => {
+                let kind = MeshLoadErrorKind::UnsupportedDirective(keyword.to_string());
+                return Err(parser.error(keyword, kind));
+            }
+        }
+    }
+
+    Ok(triangles)
+}
+
+fn face_normal(corners: [Vector4; 3]) -> Vector4 {
+    let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+    if normal.length() > 0.0 {
+        normal.normalized()
+    } else {
+        normal
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stl_round_trip() {
+        let ascii = "\
+solid triangle
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid triangle
+";
+        let mesh = read_stl(ascii.as_bytes(), "test.stl").unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+
+        // the missing normal is found from the winding
+        assert_eq!(mesh.vertices[0].normal, Vector4::new(0.0, 0.0, 1.0, 0.0));
+
+        let mut bytes = vec![];
+        write_stl(&mut bytes, &mesh).unwrap();
+        assert_eq!(bytes.len(), 84 + 50);
+
+        let loaded = read_stl(&bytes, "test.stl").unwrap();
+        for (a, b) in loaded.vertices.iter().zip(mesh.vertices.iter()) {
+            assert_eq!((a.position, a.normal), (b.position, b.normal));
+        }
+
+        let err = read_stl(&bytes[..100], "test.stl").err().unwrap();
+        assert!(matches!(err.kind, MeshLoadErrorKind::Io(_)));
+
+        // a binary file whose header starts with "solid" isn't mistaken for ascii
+        bytes[..5].copy_from_slice(b"solid");
+        assert_eq!(read_stl(&bytes, "test.stl").unwrap().indices.len(), 3);
+
+        let err = read_stl(b"solid\nfacet normal 0 0 x\n", "test.stl")
+            .err()
+            .unwrap();
+        assert_eq!((err.line, err.column), (2, 18));
+    }
+}
@@ -6,32 +6,96 @@ use crate::math::lerp;
 
 use super::color::Color;
 
-/// Bitmap with format: RGBA
+// how the channels of a pixel are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    R8,      // single u8 channel (masks, gray-scale)
+    Rgba8,   // four u8 channels (colors)
+    R32F,    // single f32 channel (depth)
+    Rgba32F, // four f32 channels
+}
+
+impl Format {
+    pub fn channels(&self) -> usize {
+        match self {
+            Format::R8 | Format::R32F => 1,
+            Format::Rgba8 | Format::Rgba32F => 4,
+        }
+    }
+}
+
+// type of a single channel, it decides which formats a bitmap can have
+pub trait Channel: Default + Clone {
+    // one channel per pixel
+    const SINGLE: Format;
+    // four channels per pixel
+    const RGBA: Format;
+}
+
+impl Channel for u8 {
+    const SINGLE: Format = Format::R8;
+    const RGBA: Format = Format::Rgba8;
+}
+
+impl Channel for f32 {
+    const SINGLE: Format = Format::R32F;
+    const RGBA: Format = Format::Rgba32F;
+}
+
+/// Bitmap with a pixel format: RGBA by default
 #[derive(Debug, Clone)]
 pub struct Bitmap<T> {
     pub width: u32,
     pub height: u32,
+    pub format: Format,
     pub pixels: Vec<T>,
 }
 
-// Bitmap is a list of pixels in RGBA format
-impl<T: Default + Clone> Bitmap<T> {
+// Bitmap is a list of pixels, every pixel has `format.channels()` values
+impl<T: Channel> Bitmap<T> {
     pub fn new(width: u32, height: u32) -> Self {
+        Self::with_format(width, height, T::RGBA)
+    }
+
+    pub fn with_format(width: u32, height: u32, format: Format) -> Self {
+        assert!(
+            format == T::SINGLE || format == T::RGBA,
+            "format {format:?} doesn't fit the channel type"
+        );
+
         Self {
             width,
             height,
-            pixels: vec![T::default(); (width * height * 4) as usize],
+            format,
+            pixels: vec![T::default(); (width * height) as usize * format.channels()],
         }
     }
 
-    pub fn from_bytes(width: u32, height: u32, pixels: Vec<T>) -> Self {
+    // `pixels` must have `format.channels()` values for every pixel, other layouts (like rgb) have to be
+    // converted first
+    pub fn from_bytes(width: u32, height: u32, format: Format, pixels: Vec<T>) -> Self {
+        assert!(
+            format == T::SINGLE || format == T::RGBA,
+            "format {format:?} doesn't fit the channel type"
+        );
+        assert_eq!(
+            pixels.len(),
+            (width * height) as usize * format.channels(),
+            "{width}x{height} {format:?} pixels don't fit"
+        );
+
         Self {
             width,
             height,
+            format,
             pixels,
         }
     }
 
+    pub fn channels(&self) -> usize {
+        self.format.channels()
+    }
+
     // todo: remove this complexity (was a fun exersize)
     // pub fn copy_to_rgb(&self) -> Vec<u8> {
     //     let total_pixels = (self.width * self.height) as usize;
@@ -59,33 +123,37 @@ impl Bitmap<u8> {
     }
 
     pub fn set_pixel(&mut self, x: u32, y: u32, color: &Color) {
-        let index = ((x as usize + y as usize * self.width as usize) * 4) as usize;
+        let channels = self.channels();
+        let index = (x as usize + y as usize * self.width as usize) * channels;
 
-        if index < 0 || index >= (self.width * self.height * 4) as usize {
+        if index < 0 || index >= self.pixels.len() {
             return;
         }
 
-        let prev_color = Color::new(
-            self.pixels[index + 0],
-            self.pixels[index + 1],
-            self.pixels[index + 2],
-            0xFF,
-        );
+        // single channel bitmaps only keep the red channel
+        if channels == 1 {
+            let blend = (color.a as f32) / 255.0;
+            self.pixels[index] = lerp(self.pixels[index] as f32, color.r as f32, blend) as u8;
+            return;
+        }
 
-        let blend = (color.a as f32) / 255.0;
-        self.pixels[index + 0] = lerp(prev_color.r as f32, color.r as f32, blend) as u8;
-        self.pixels[index + 1] = lerp(prev_color.g as f32, color.g as f32, blend) as u8;
-        self.pixels[index + 2] = lerp(prev_color.b as f32, color.b as f32, blend) as u8;
-        self.pixels[index + 3] = 0xFF;
+        blend_pixel(&mut self.pixels[index..index + 4], color);
     }
 
     pub fn get_pixel(&self, x: u32, y: u32) -> Color {
-        let index = ((x + y * self.width) * 4) as usize;
+        let channels = self.channels();
+        let index = (x + y * self.width) as usize * channels;
 
-        if index < 0 || index >= (self.width * self.height * 4) as usize {
+        if index < 0 || index >= self.pixels.len() {
             return Color::BLACK;
         }
 
+        // single channel bitmaps are gray-scale
+        if channels == 1 {
+            let value = self.pixels[index];
+            return Color::new(value, value, value, 0xFF);
+        }
+
         Color::new(
             self.pixels[index + 0],
             self.pixels[index + 1],
@@ -97,12 +165,19 @@ impl Bitmap<u8> {
 
 impl Bitmap<f32> {
     pub fn get_pixel(&self, x: u32, y: u32) -> (f32, f32, f32, f32) {
-        let index = ((x + y * self.width) * 4) as usize;
+        let channels = self.channels();
+        let index = (x + y * self.width) as usize * channels;
 
-        if index < 0 || index >= (self.width * self.height * 4) as usize {
+        if index < 0 || index >= self.pixels.len() {
             return (0.0, 0.0, 0.0, 0.0);
         }
 
+        // single channel bitmaps are gray-scale
+        if channels == 1 {
+            let value = self.pixels[index];
+            return (value, value, value, 1.0);
+        }
+
         (
             self.pixels[index + 0],
             self.pixels[index + 1],
@@ -110,6 +185,21 @@ impl Bitmap<f32> {
             self.pixels[index + 3],
         )
     }
+
+    // the first channel of a pixel, it's all there is in a depth bitmap
+    #[inline(always)]
+    pub fn get_value(&self, x: u32, y: u32) -> f32 {
+        self.pixels[(x + y * self.width) as usize * self.channels()]
+    }
+}
+
+// blends the color on top of an RGBA pixel using the color's alpha, the result is always opaque
+pub fn blend_pixel(pixel: &mut [u8], color: &Color) {
+    let blend = (color.a as f32) / 255.0;
+    pixel[0] = lerp(pixel[0] as f32, color.r as f32, blend) as u8;
+    pixel[1] = lerp(pixel[1] as f32, color.g as f32, blend) as u8;
+    pixel[2] = lerp(pixel[2] as f32, color.b as f32, blend) as u8;
+    pixel[3] = 0xFF;
 }
 
 impl<T> Deref for Bitmap<T> {
@@ -170,4 +260,41 @@ mod tests {
         assert_eq!(bitmap.pixels[10], 0x00);
         assert_eq!(bitmap.pixels[11], 0xFF);
     }
+
+    #[test]
+    fn test_bitmap_formats() {
+        let rgba: Bitmap<u8> = Bitmap::new(3, 2);
+        assert_eq!(rgba.format, Format::Rgba8);
+        assert_eq!(rgba.pixels.len(), 3 * 2 * 4);
+
+        let mut mask: Bitmap<u8> = Bitmap::with_format(3, 2, Format::R8);
+        assert_eq!(mask.pixels.len(), 3 * 2);
+        mask.set_pixel(2, 1, &Color::RED);
+        assert_eq!(mask.pixels[5], 0xFF);
+        assert_eq!(mask.get_pixel(2, 1).g, 0xFF);
+
+        let mut depth: Bitmap<f32> = Bitmap::with_format(3, 2, Format::R32F);
+        assert_eq!(depth.pixels.len(), 3 * 2);
+        depth.pixels[4] = 0.5;
+        assert_eq!(depth.get_value(1, 1), 0.5);
+        assert_eq!(depth.get_pixel(1, 1), (0.5, 0.5, 0.5, 1.0));
+
+        let colors: Bitmap<f32> =
+            Bitmap::from_bytes(1, 1, Format::Rgba32F, vec![0.1, 0.2, 0.3, 0.4]);
+        assert_eq!(colors.format, Format::Rgba32F);
+        assert_eq!(colors.get_value(0, 0), 0.1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bitmap_wrong_format() {
+        let _: Bitmap<u8> = Bitmap::with_format(1, 1, Format::R32F);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bitmap_wrong_size() {
+        // rgb bytes aren't rgba
+        let _: Bitmap<u8> = Bitmap::from_bytes(2, 1, Format::Rgba8, vec![0; 6]);
+    }
 }
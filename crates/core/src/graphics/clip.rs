@@ -1,10 +1,10 @@
-use super::{gradients::Triangle, vertex::Vertex};
+use super::{gradients::Triangle, vertex::ClipVertex};
 
 // clip vertices and return the vertices that are visible
 // 1. can be the same as the input
 // 2. can be the none when they are completely out of view
 // 3. can be new vertices due to clipping on all axis
-pub fn clip_triangle(v1: Vertex, v2: Vertex, v3: Vertex) -> Option<Vec<Triangle>> {
+pub fn clip_triangle(v1: ClipVertex, v2: ClipVertex, v3: ClipVertex) -> Option<Vec<Triangle>> {
     // # 3d homogenous clipping
     // https://fabiensanglard.net/polygon_codec/
     //
@@ -73,7 +73,7 @@ pub fn clip_triangle(v1: Vertex, v2: Vertex, v3: Vertex) -> Option<Vec<Triangle>
 }
 
 // clips for one particular axis
-fn clip_polygon_axis(vertices: &mut Vec<Vertex>, component: usize) -> bool {
+fn clip_polygon_axis(vertices: &mut Vec<ClipVertex>, component: usize) -> bool {
     let mut new_vertices = Vec::new();
 
     // clip on specific component on the +w
@@ -107,10 +107,10 @@ fn clip_polygon_axis(vertices: &mut Vec<Vertex>, component: usize) -> bool {
 
 // clips on components: x,y,z
 fn clip_polygon_component(
-    vertices: &Vec<Vertex>,   // vertices to clip
-    component_index: usize,   // which component to clip on (x:0,y:1,z:2)
-    factor: f32,              // -w or +w
-    result: &mut Vec<Vertex>, // resulting clipped vertices
+    vertices: &Vec<ClipVertex>,   // vertices to clip
+    component_index: usize,       // which component to clip on (x:0,y:1,z:2)
+    factor: f32,                  // -w or +w
+    result: &mut Vec<ClipVertex>, // resulting clipped vertices
 ) {
     // start with the very last vertex in the list
     // compare loop checks (prev-curr) v3-v1, v1-v2, v2->v3
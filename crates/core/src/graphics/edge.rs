@@ -1,10 +1,9 @@
 use std::ops::{Add, AddAssign, Mul};
 
-use crate::math::linear_algebra::vector::Vector4;
-
 use super::{
     gradients::{Gradient, Gradients},
-    vertex::Vertex,
+    varyings::Varyings,
+    vertex::ClipVertex,
 };
 
 // move along the edge y and some x and increase steppable values as you go along
@@ -16,17 +15,19 @@ pub struct Edge {
     pub y_end: u32,                           // y-end of the edge
     pub x: f32,                               // current x on the edge 
     pub x_step: f32,                          // how much to step on the x-axis every time we step down on y-axis
-    pub position: Stepable<Vector4>,          // position
-    pub texcoords: Stepable<Vector4>,         // texture-coordinate start and step values
     pub one_over_z: Stepable<f32>,            // one-over-z start and step values
     pub depth: Stepable<f32>,                 // depth start and step values
-    pub light_amp: Stepable<f32>,             // light-amp start and step values
-    pub shadow_map_coords: Stepable<Vector4>, // interpolated shadow-map-coordinates
+    pub varyings: Stepable<Varyings>,         // varyings (divided by z) start and step values
 }
 
 impl Edge {
     // `start_index` is also the `min_y_vertex`/`top_to_bot`
-    pub fn new(gradients: &Gradients, start: &Vertex, end: &Vertex, start_index: usize) -> Self {
+    pub fn new(
+        gradients: &Gradients,
+        start: &ClipVertex,
+        end: &ClipVertex,
+        start_index: usize,
+    ) -> Self {
         // apply our fill convention to the start and end y screen positions
         let y_start = start.position.y.ceil() as u32;
         let y_end = end.position.y.ceil() as u32;
@@ -70,17 +71,11 @@ impl Edge {
 
         // construct steps with gradients with initial values
         #[rustfmt::skip]
-        let position = Stepable::new(&gradients.position, start_index, x_prestep, y_prestep, x_step);
-        #[rustfmt::skip]
-        let texcoords = Stepable::new(&gradients.texcoords, start_index, x_prestep, y_prestep, x_step);
-        #[rustfmt::skip]
         let one_over_z = Stepable::new(&gradients.one_over_z, start_index, x_prestep, y_prestep, x_step);
         #[rustfmt::skip]
         let depth = Stepable::new(&gradients.depth, start_index, x_prestep, y_prestep, x_step);
         #[rustfmt::skip]
-        let light_amp = Stepable::new(&gradients.light_amt, start_index, x_prestep, y_prestep, x_step);
-        #[rustfmt::skip]
-        let shadow_map_coords = Stepable::new(&gradients.shadow_map_coords, start_index, x_prestep, y_prestep, x_step);
+        let varyings = Stepable::new(&gradients.varyings, start_index, x_prestep, y_prestep, x_step);
 
         // and finally return the newly constructed edge
         return Self {
@@ -88,12 +83,9 @@ impl Edge {
             x_step,
             y_start: (y_start as u32),
             y_end: (y_end as u32),
-            position,
-            texcoords,
             one_over_z,
             depth,
-            light_amp,
-            shadow_map_coords,
+            varyings,
         };
     }
 
@@ -103,12 +95,9 @@ impl Edge {
         self.x += self.x_step;
 
         // move forward all gradients
-        self.position.step();
-        self.texcoords.step();
         self.one_over_z.step();
         self.depth.step();
-        self.light_amp.step();
-        self.shadow_map_coords.step();
+        self.varyings.step();
     }
 }
 
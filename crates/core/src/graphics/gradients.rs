@@ -1,17 +1,12 @@
 use std::ops::{Mul, Sub};
 
-use crate::math::{clamp, linear_algebra::vector::Vector4};
-
-use super::vertex::Vertex;
+use super::{varyings::Varyings, vertex::ClipVertex};
 
 #[derive(Debug)]
 pub struct Gradients {
-    pub position: Gradient<Vector4>,
-    pub texcoords: Gradient<Vector4>,
     pub one_over_z: Gradient<f32>,
     pub depth: Gradient<f32>,
-    pub light_amt: Gradient<f32>,
-    pub shadow_map_coords: Gradient<Vector4>,
+    pub varyings: Gradient<Varyings>,
 }
 
 // 0 .
@@ -43,31 +38,19 @@ pub struct Step<T> {
 /// vertices are aligned on the y-axis, min, mid, and max.
 #[derive(Debug, Default, Clone)]
 pub struct Triangle {
-    pub min: Vertex,
-    pub mid: Vertex,
-    pub max: Vertex,
+    pub min: ClipVertex,
+    pub mid: ClipVertex,
+    pub max: ClipVertex,
 }
 
 impl Gradients {
-    pub fn new(triangle: Triangle, light_dir: Vector4) -> Self {
-        let mut position = Gradient::default();
-        position.value[0] = triangle.min.normal;
-        position.value[1] = triangle.mid.normal;
-        position.value[2] = triangle.max.normal;
-
+    pub fn new(triangle: Triangle) -> Self {
         // depth: interpolate between the z-axis of each vertex of the triangle
         let mut depth = Gradient::default();
         depth.value[0] = triangle.min.position.z;
         depth.value[1] = triangle.mid.position.z;
         depth.value[2] = triangle.max.position.z;
 
-        // light amount: interpolate between light directions
-        // initial light amount is calculated by taking the dot product of each vertex normal with the light direction
-        let mut light_amt = Gradient::default();
-        light_amt.value[0] = clamp(triangle.min.normal.dot(light_dir), 0.0, 1.0) * 0.75 + 0.25; // +ambient light
-        light_amt.value[1] = clamp(triangle.mid.normal.dot(light_dir), 0.0, 1.0) * 0.75 + 0.25; // +ambient light
-        light_amt.value[2] = clamp(triangle.max.normal.dot(light_dir), 0.0, 1.0) * 0.75 + 0.25; // +ambient light
-
         // one over z: to get perspective correct values (texture-mapping)
         // one over z is a linear function which makes our gradient formula work as opposed to the inverse which is not linear
         // note that `w` is the perspective z-value while `z` is the occlution z-value
@@ -76,25 +59,17 @@ impl Gradients {
         one_over_z.value[1] = 1.0 / triangle.mid.position.w;
         one_over_z.value[2] = 1.0 / triangle.max.position.w;
 
-        // texture coordinates: perspective divide
+        // varyings: perspective divide
         // https://youtu.be/_elt1LVUsdY?t=758
         // not everything can be linearly interpolated across the face of a triangle
-        // since we can't interpolate texture coordinates directly
+        // since we can't interpolate varyings (like texture coordinates) directly
         // we can move them through the same transformation as the x and y (divide by z)
-        // and no, we don't really care what tex-coord divided by z is but it can help us to get the actual z
-        // the renderer does the transformation and gets the true tex-coords
-        let mut texcoords = Gradient::default();
-        texcoords.value[0] = triangle.min.texcoords * one_over_z.value[0]; // the same as: / triangle.min.position.w
-        texcoords.value[1] = triangle.mid.texcoords * one_over_z.value[1]; // the same as: / triangle.mid.position.w
-        texcoords.value[2] = triangle.max.texcoords * one_over_z.value[2]; // the same as: / triangle.max.position.w
-
-        // shadow map texture coordinates will be interpolated across each vertex
-        // we calculate it by transforming each vertex
-        // using the same mvp matrix that the light used to create the shadow-map
-        let mut shadow_map_coords = Gradient::default();
-        shadow_map_coords.value[0] = triangle.min.shadow_map_coords * one_over_z.value[0];
-        shadow_map_coords.value[1] = triangle.mid.shadow_map_coords * one_over_z.value[1];
-        shadow_map_coords.value[2] = triangle.max.shadow_map_coords * one_over_z.value[2];
+        // and no, we don't really care what a varying divided by z is but it can help us to get the actual z
+        // the renderer does the transformation and gets the true varyings
+        let mut varyings = Gradient::default();
+        varyings.value[0] = triangle.min.varyings * one_over_z.value[0]; // the same as: / triangle.min.position.w
+        varyings.value[1] = triangle.mid.varyings * one_over_z.value[1]; // the same as: / triangle.mid.position.w
+        varyings.value[2] = triangle.max.varyings * one_over_z.value[2]; // the same as: / triangle.max.position.w
 
         // triangle gradient interpolation formula to find the extra points
         // https://youtu.be/AysDWKF3CBs
@@ -121,20 +96,14 @@ impl Gradients {
         // ∂y is the inverse
         let one_over_dy = -one_over_dx;
 
-        position.calc_steps(&triangle, one_over_dx, one_over_dy);
-        texcoords.calc_steps(&triangle, one_over_dx, one_over_dy);
         one_over_z.calc_steps(&triangle, one_over_dx, one_over_dy);
         depth.calc_steps(&triangle, one_over_dx, one_over_dy);
-        light_amt.calc_steps(&triangle, one_over_dx, one_over_dy);
-        shadow_map_coords.calc_steps(&triangle, one_over_dx, one_over_dy);
+        varyings.calc_steps(&triangle, one_over_dx, one_over_dy);
 
         Self {
-            position,
-            texcoords,
             one_over_z,
             depth,
-            light_amt,
-            shadow_map_coords,
+            varyings,
         }
     }
 }
@@ -180,7 +149,7 @@ where
 }
 
 impl Triangle {
-    pub fn new(min: Vertex, mid: Vertex, max: Vertex) -> Self {
+    pub fn new(min: ClipVertex, mid: ClipVertex, max: ClipVertex) -> Self {
         return Self { min, mid, max };
     }
 }
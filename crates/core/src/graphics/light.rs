@@ -1,19 +1,631 @@
-use crate::math::linear_algebra::matrix::Matrix4;
+use crate::math::{
+    clamp, lerp,
+    linear_algebra::{matrix::Matrix4, vector::Vector4},
+    PI,
+};
 
 use super::bitmap::Bitmap;
 
+// how the light reaches a surface
+//
+//  directional     point          spot
+//   \ \ \ \       \ | /           |
+//    \ \ \ \     -- o --         / \
+//     \ \ \ \      / | \        /   \
+//                              inner/outer cone
+#[derive(Debug, Clone, Copy)]
+pub enum LightKind {
+    // infinitely far away (the sun), only the direction matters
+    Directional,
+    // shines everywhere and fades out at the radius
+    Point { radius: f32 },
+    // full light within the inner angle and fades out until the outer angle (degrees)
+    Spot { radius: f32, inner: f32, outer: f32 },
+}
+
+// how many shadow-map texels are compared to find out how much of the light gets through
+// more texels make softer edges (percentage-closer filtering)
+//
+//  hard    bilinear    poisson     grid
+//   .        . .       .  .  .     . . .
+//            . .        .  . .     . . .
+//                      . .  .      . . .
+#[derive(Debug, Clone, Copy)]
+pub enum ShadowFilter {
+    // a single texel, shadows are either on or off
+    Hard,
+    // the 4 closest texels blended by how close they are
+    Bilinear,
+    // 16 texels spread in a disk with a radius (in texels)
+    Poisson { radius: f32 },
+    // a square of size x size texels
+    Grid { size: u32 },
+}
+
+// points in a unit disk that are spread out evenly, but not in a pattern that the eye could pick up
+#[rustfmt::skip]
+const POISSON_DISK: [(f32, f32); 16] = [
+    (-0.942016, -0.399062), ( 0.945586, -0.768907),
+    (-0.094184, -0.929389), ( 0.344959,  0.293878),
+    (-0.915886,  0.457714), (-0.815442, -0.879125),
+    (-0.382775,  0.276768), ( 0.974844,  0.756484),
+    ( 0.443233, -0.975116), ( 0.537430, -0.473734),
+    (-0.264969, -0.418930), ( 0.791975,  0.190902),
+    (-0.241888,  0.997065), (-0.814100,  0.914376),
+    ( 0.199841,  0.786414), ( 0.143832, -0.141008),
+];
+
+#[rustfmt::skip]
 pub struct Light {
-    pub projection: Matrix4,
-    pub transform: Matrix4,
-    pub bitmap: Bitmap<f32>, // todo: use 1d format!
+    pub kind: LightKind,
+    pub position: Vector4,                      // world-space, not used by directional lights
+    pub direction: Vector4,                     // world-space, not used by point lights
+    pub color: Vector4,                         // rgb color of the light
+    pub intensity: f32,                         // how strong the diffuse and specular light is
+    pub ambient: f32,                           // light that reaches every surface, even the ones in shadow
+    pub shadow_maps: Vec<ShadowMap>,            // cascades from the closest to the furthest, empty means no shadows
+    pub cube_shadow_map: Option<CubeShadowMap>, // omnidirectional shadows of point lights
+    pub shadow_filter: ShadowFilter,            // how soft the edges of the shadows are
+    pub depth_bias: f32,                        // depth offset that stops surfaces from shadowing themselves (acne)
+    pub slope_bias: f32,                        // extra depth offset for surfaces at a steep angle to the light
+    pub normal_offset: f32,                     // world-space distance the position is pushed along the normal
+}
+
+// six perspective shadow-maps around a point light, one for each side of a cube
+//
+//        +y
+//   -x   +z   +x   -z
+//        -y
+pub struct CubeShadowMap {
+    pub position: Vector4,     // world-space position of the light
+    pub near: f32,             // near plane of every face
+    pub far: f32,              // far plane of every face, usually the radius of the light
+    pub faces: Vec<ShadowMap>, // +x, -x, +y, -y, +z, -z
+    pub casters: Vec<usize>, // the instances that were close enough to cast a shadow when it was drawn
+}
+
+// depth of the scene as seen from the light
+pub struct ShadowMap {
+    pub projection: Matrix4, // view-projection of the shadow-map
+    pub bitmap: Bitmap<f32>, // single channel (r32f) depth
 }
 
 impl Light {
-    pub fn new(projection: Matrix4, transform: Matrix4, depth: Bitmap<f32>) -> Self {
+    fn new(kind: LightKind, position: Vector4, direction: Vector4, ambient: f32) -> Self {
+        return Self {
+            kind,
+            position,
+            direction,
+            color: Vector4::new(1.0, 1.0, 1.0, 0.0),
+            intensity: 1.0,
+            ambient,
+            shadow_maps: Vec::new(),
+            cube_shadow_map: None,
+            shadow_filter: ShadowFilter::Hard,
+            depth_bias: 0.01,
+            slope_bias: 0.0,
+            normal_offset: 0.0,
+        };
+    }
+
+    pub fn directional(direction: Vector4) -> Self {
+        return Self::new(
+            LightKind::Directional,
+            Vector4::ZERO,
+            direction.normalized(),
+            0.25,
+        );
+    }
+
+    pub fn point(position: Vector4, radius: f32) -> Self {
+        return Self::new(LightKind::Point { radius }, position, Vector4::ZERO, 0.0);
+    }
+
+    pub fn spot(
+        position: Vector4,
+        direction: Vector4,
+        radius: f32,
+        inner: f32,
+        outer: f32,
+    ) -> Self {
+        return Self::new(
+            LightKind::Spot {
+                radius,
+                inner,
+                outer,
+            },
+            position,
+            direction.normalized(),
+            0.0,
+        );
+    }
+
+    pub fn with_color(mut self, color: Vector4, intensity: f32) -> Self {
+        self.color = color;
+        self.intensity = intensity;
+        return self;
+    }
+
+    // direction from the surface towards the light and how much of the light is left when it gets there
+    pub fn incoming(&self, world_position: Vector4) -> (Vector4, f32) {
+        match self.kind {
+            LightKind::Directional => (-self.direction, 1.0),
+            LightKind::Point { radius } => {
+                let (to_light, distance) = self.towards(world_position);
+                (to_light, Self::attenuation(distance, radius))
+            }
+            LightKind::Spot {
+                radius,
+                inner,
+                outer,
+            } => {
+                let (to_light, distance) = self.towards(world_position);
+
+                // compare the angle between the light direction and the surface with the cone angles
+                let cos_angle = (-to_light).dot(self.direction);
+                let cos_inner = (inner * PI / 180.0).cos();
+                let cos_outer = (outer * PI / 180.0).cos();
+                let cone = clamp(
+                    (cos_angle - cos_outer) / (cos_inner - cos_outer).max(0.0001),
+                    0.0,
+                    1.0,
+                );
+
+                (to_light, Self::attenuation(distance, radius) * cone)
+            }
+        }
+    }
+
+    fn towards(&self, world_position: Vector4) -> (Vector4, f32) {
+        let mut offset = self.position - world_position;
+        offset.w = 0.0;
+
+        let distance = offset.length();
+        if distance <= 0.0 {
+            return (Vector4::ZERO, 0.0);
+        }
+
+        return (offset / distance, distance);
+    }
+
+    // smooth falloff that reaches exactly 0 at the radius
+    //
+    // 1 |--.
+    //   |    `.
+    //   |      `.
+    // 0 |---------`--
+    //   0       radius
+    fn attenuation(distance: f32, radius: f32) -> f32 {
+        let falloff = clamp(1.0 - (distance / radius).powi(2), 0.0, 1.0);
+        return falloff * falloff;
+    }
+
+    // 1 when the light can see the position, 0 when it's in shadow and anything in between on soft edges
+    // `normal` must be normalized
+    pub fn visibility(&self, world_position: Vector4, normal: Vector4) -> f32 {
+        if self.shadow_maps.is_empty() && self.cube_shadow_map.is_none() {
+            return 1.0;
+        }
+
+        let (to_light, _) = self.incoming(world_position);
+        let n_dot_l = clamp(normal.dot(to_light), 0.0, 1.0);
+
+        // normal-offset: move the position away from the surface, the more it faces away from the light the further
+        //
+        //   offset
+        //     ^
+        //     |
+        // ----x---- surface
+        let offset_position = world_position + normal * (self.normal_offset * (1.0 - n_dot_l));
+
+        // slope-scaled bias: at steep angles a single texel covers a bigger range of depths
+        // tan(angle) between the normal and the light, capped so that grazing angles don't explode
+        let slope = (1.0 - n_dot_l * n_dot_l).sqrt() / n_dot_l.max(0.1);
+        let bias = self.depth_bias + self.slope_bias * slope;
+
+        if let Some(cube_shadow_map) = &self.cube_shadow_map {
+            return cube_shadow_map.calc_shadow_amount(offset_position, bias, self.shadow_filter);
+        }
+
+        // pick the first cascade that covers the position, they're sorted from the most detailed one
+        for shadow_map in self.shadow_maps.iter() {
+            // move the position into the light's clip-space and compare depths
+            let shadow_map_coords =
+                Matrix4::multiply_vector(&shadow_map.projection, offset_position);
+
+            if let Some(amount) =
+                shadow_map.calc_shadow_amount(shadow_map_coords, bias, self.shadow_filter)
+            {
+                return amount;
+            }
+        }
+
+        // outside of every shadow-map
+        return 1.0;
+    }
+}
+
+impl CubeShadowMap {
+    pub fn new(position: Vector4, near: f32, far: f32, faces: Vec<ShadowMap>) -> Self {
+        assert_eq!(faces.len(), 6, "a cube shadow-map needs 6 faces");
+        return Self {
+            position,
+            near,
+            far,
+            faces,
+            casters: Vec::new(),
+        };
+    }
+
+    // direction the face is looking in and its up vector
+    pub fn face_axes(face: usize) -> (Vector4, Vector4) {
+        return match face {
+            0 => (Vector4::new(1.0, 0.0, 0.0, 0.0), Vector4::UP),
+            1 => (Vector4::new(-1.0, 0.0, 0.0, 0.0), Vector4::UP),
+            2 => (Vector4::UP, Vector4::FORWARD),
+            3 => (-Vector4::UP, Vector4::FORWARD),
+            4 => (Vector4::FORWARD, Vector4::UP),
+            5 => (-Vector4::FORWARD, Vector4::UP),
+            _ => panic!("cube has no face: ({})", face),
+        };
+    }
+
+    // 90 degree perspective view that covers one face of the cube
+    pub fn face_view_projection(position: Vector4, near: f32, far: f32, face: usize) -> Matrix4 {
+        let (direction, up) = Self::face_axes(face);
+
+        let mut view = Matrix4::new_identity();
+        view.look_at(position, position + direction, up);
+
+        let projection = Matrix4::perspective(90.0, 1.0, near, far);
+        return Matrix4::multiply(&projection, &view);
+    }
+
+    // the face is picked by the longest axis of the direction
+    //
+    //   \ +y /
+    //    \  /
+    // -x  \/  +x
+    //     /\
+    //    /  \
+    //   / -y \
+    pub fn face(direction: Vector4) -> usize {
+        let (x, y, z) = (direction.x.abs(), direction.y.abs(), direction.z.abs());
+
+        return if x >= y && x >= z {
+            if direction.x >= 0.0 {
+                0
+            } else {
+                1
+            }
+        } else if y >= z {
+            if direction.y >= 0.0 {
+                2
+            } else {
+                3
+            }
+        } else if direction.z >= 0.0 {
+            4
+        } else {
+            5
+        };
+    }
+
+    // how much light reaches a world-space position
+    // the depth of the faces isn't linear so the bias is in world units here (distance along the face axis)
+    pub fn calc_shadow_amount(
+        &self,
+        world_position: Vector4,
+        bias: f32,
+        filter: ShadowFilter,
+    ) -> f32 {
+        let mut direction = world_position - self.position;
+        direction.w = 0.0;
+
+        let face = Self::face(direction);
+        let shadow_map = &self.faces[face];
+
+        // distance along the axis of the face is the z that the perspective projection divides by
+        let (axis, _) = Self::face_axes(face);
+        let distance = direction.dot(axis);
+        if distance <= self.near || distance >= self.far {
+            return 1.0;
+        }
+
+        let mut position = world_position;
+        position.w = 1.0;
+        let coords = Matrix4::multiply_vector(&shadow_map.projection, position);
+        let x = coords.x / coords.w;
+        let y = coords.y / coords.w;
+
+        // move the biased distance into the same (non linear) depth as the depth buffer
+        //
+        // z_clip = z * far / (far - near) - far * near / (far - near)
+        // depth  = z_clip / z
+        let z = (distance - bias).max(self.near);
+        let range = self.far - self.near;
+        let compare = (z * self.far / range - self.far * self.near / range) / z;
+
+        // faces touch each other so the position is clamped to the edge instead of dropped
+        let bitmap = &shadow_map.bitmap;
+        let tex_x = clamp(x * 0.5 + 0.5, 0.0, 1.0) * (bitmap.width as f32 - 1.0);
+        let tex_y = clamp(-y * 0.5 + 0.5, 0.0, 1.0) * (bitmap.height as f32 - 1.0);
+
+        return shadow_map.filter(tex_x, tex_y, compare, filter);
+    }
+}
+
+impl ShadowMap {
+    pub fn new(projection: Matrix4, depth: Bitmap<f32>) -> Self {
         return Self {
             projection,
-            transform,
             bitmap: depth,
         };
     }
+
+    // look up the shadow-map with a position in the light's clip-space
+    // returns none when the position is outside of the shadow-map, otherwise how much light gets through (0 to 1)
+    pub fn calc_shadow_amount(
+        &self,
+        initial_shadow_map_coords: Vector4,
+        bias: f32,
+        filter: ShadowFilter,
+    ) -> Option<f32> {
+        let shadow_map = &self.bitmap;
+
+        let x = initial_shadow_map_coords.x;
+        let y = initial_shadow_map_coords.y;
+        let z = initial_shadow_map_coords.z;
+
+        // -1.0 to +1.0 on x and y in the shadow_map
+        let normal_x = x * 0.5 + 0.5;
+        let normal_y = -y * 0.5 + 0.5;
+
+        // the first and the last row and column are still inside, the filters clamp to the edge
+        if !(0.0..=1.0).contains(&normal_x) || !(0.0..=1.0).contains(&normal_y) {
+            return None;
+        }
+
+        // stretch across to fit the shadow_map texture
+        let tex_x = normal_x * (shadow_map.width as f32 - 1.0);
+        let tex_y = normal_y * (shadow_map.height as f32 - 1.0);
+
+        return Some(self.filter(tex_x, tex_y, z - bias, filter));
+    }
+
+    // percentage-closer filtering: compare a bunch of texels around a position (in texels) and average them
+    pub fn filter(&self, tex_x: f32, tex_y: f32, compare: f32, filter: ShadowFilter) -> f32 {
+        return match filter {
+            ShadowFilter::Hard => self.sample_shadow_map_nearest(tex_x, tex_y, compare),
+            ShadowFilter::Bilinear => {
+                // blend the 4 texels around the position
+                //
+                // a --- b
+                // |  x  |
+                // c --- d
+                let x0 = tex_x.floor();
+                let y0 = tex_y.floor();
+                let (ix, iy) = (x0 as i32, y0 as i32);
+
+                let a = self.sample_shadow_map(ix, iy, compare);
+                let b = self.sample_shadow_map(ix + 1, iy, compare);
+                let c = self.sample_shadow_map(ix, iy + 1, compare);
+                let d = self.sample_shadow_map(ix + 1, iy + 1, compare);
+
+                lerp(lerp(a, b, tex_x - x0), lerp(c, d, tex_x - x0), tex_y - y0)
+            }
+            ShadowFilter::Poisson { radius } => {
+                let total: f32 = POISSON_DISK
+                    .iter()
+                    .map(|(dx, dy)| {
+                        self.sample_shadow_map_nearest(
+                            tex_x + dx * radius,
+                            tex_y + dy * radius,
+                            compare,
+                        )
+                    })
+                    .sum();
+
+                total / POISSON_DISK.len() as f32
+            }
+            ShadowFilter::Grid { size } => {
+                let size = size.max(1);
+                let half = (size - 1) as f32 * 0.5;
+                let mut total = 0.0;
+
+                for i in 0..size {
+                    for j in 0..size {
+                        let offset_x = i as f32 - half;
+                        let offset_y = j as f32 - half;
+                        total += self.sample_shadow_map_nearest(
+                            tex_x + offset_x,
+                            tex_y + offset_y,
+                            compare,
+                        );
+                    }
+                }
+
+                total / (size * size) as f32
+            }
+        };
+    }
+
+    // 0 when the stored depth is in front of `compare` and 1 otherwise
+    // texels outside of the bitmap are clamped to the edge
+    fn sample_shadow_map(&self, x: i32, y: i32, compare: f32) -> f32 {
+        let shadow_map = &self.bitmap;
+        let x = x.clamp(0, shadow_map.width as i32 - 1) as u32;
+        let y = y.clamp(0, shadow_map.height as i32 - 1) as u32;
+
+        return if shadow_map.get_value(x, y) < compare {
+            0.0
+        } else {
+            1.0
+        };
+    }
+
+    fn sample_shadow_map_nearest(&self, tex_x: f32, tex_y: f32, compare: f32) -> f32 {
+        return self.sample_shadow_map(
+            (tex_x + 0.5).floor() as i32,
+            (tex_y + 0.5).floor() as i32,
+            compare,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::bitmap::Format;
+
+    #[test]
+    fn test_point_light_attenuation() {
+        let light = Light::point(Vector4::new(0.0, 2.0, 0.0, 1.0), 4.0);
+
+        // right under the light
+        let (to_light, near) = light.incoming(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        assert!((to_light - Vector4::UP).length() < 0.0001);
+        assert!((near - 0.5625).abs() < 0.0001); // (1 - (2/4)^2)^2
+
+        // further away is darker and outside of the radius there is no light at all
+        let (_, far) = light.incoming(Vector4::new(3.0, 0.0, 0.0, 1.0));
+        assert!(far < near && far > 0.0);
+
+        let (_, outside) = light.incoming(Vector4::new(5.0, 0.0, 0.0, 1.0));
+        assert_eq!(outside, 0.0);
+    }
+
+    #[test]
+    fn test_spot_light_cone() {
+        let light = Light::spot(
+            Vector4::new(0.0, 1.0, 0.0, 1.0),
+            -Vector4::UP,
+            100.0,
+            20.0,
+            40.0,
+        );
+
+        // inside of the inner cone
+        let (_, center) = light.incoming(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        // between the inner and the outer cone (30 degrees)
+        let (_, edge) = light.incoming(Vector4::new((30.0 * PI / 180.0).tan(), 0.0, 0.0, 1.0));
+        // outside of the outer cone (45 degrees)
+        let (_, outside) = light.incoming(Vector4::new(1.0, 0.0, 0.0, 1.0));
+
+        assert!(center > 0.99);
+        assert!(edge > 0.0 && edge < center);
+        assert_eq!(outside, 0.0);
+    }
+
+    // the left half of the shadow-map is blocked by something close to the light
+    fn test_shadow_map() -> ShadowMap {
+        let mut bitmap: Bitmap<f32> = Bitmap::with_format(16, 16, Format::R32F);
+        for y in 0..16 {
+            for x in 0..16 {
+                let depth = if x < 8 { 0.2 } else { 1.0 };
+                let index = (x + y * 16) as usize;
+                bitmap.pixels[index] = depth;
+            }
+        }
+        ShadowMap::new(Matrix4::new_identity(), bitmap)
+    }
+
+    // clip-space position of a texel center
+    fn texel(x: f32, y: f32) -> Vector4 {
+        Vector4::new(x / 15.0 * 2.0 - 1.0, -(y / 15.0 * 2.0 - 1.0), 0.5, 1.0)
+    }
+
+    #[test]
+    fn test_shadow_filters() {
+        let shadow_map = test_shadow_map();
+        let hard = ShadowFilter::Hard;
+
+        assert_eq!(
+            shadow_map.calc_shadow_amount(texel(3.0, 8.0), 0.01, hard),
+            Some(0.0)
+        );
+        assert_eq!(
+            shadow_map.calc_shadow_amount(texel(12.0, 8.0), 0.01, hard),
+            Some(1.0)
+        );
+
+        // the edges of the shadow-map cast shadows too, only what's outside of it is left to someone else
+        assert_eq!(
+            shadow_map.calc_shadow_amount(texel(0.0, 8.0), 0.01, hard),
+            Some(0.0)
+        );
+        assert_eq!(
+            shadow_map.calc_shadow_amount(texel(15.0, 15.0), 0.01, ShadowFilter::Grid { size: 4 }),
+            Some(1.0)
+        );
+        assert_eq!(
+            shadow_map.calc_shadow_amount(texel(-1.0, 8.0), 0.01, hard),
+            None
+        );
+
+        // half way between the last blocked and the first free texel
+        let bilinear = shadow_map.calc_shadow_amount(texel(7.5, 8.0), 0.01, ShadowFilter::Bilinear);
+        assert!((bilinear.unwrap() - 0.5).abs() < 0.0001);
+
+        // a 4x4 grid on the edge sees 2 blocked and 2 free columns
+        let grid =
+            shadow_map.calc_shadow_amount(texel(7.5, 8.0), 0.01, ShadowFilter::Grid { size: 4 });
+        assert!((grid.unwrap() - 0.5).abs() < 0.0001);
+
+        // the poisson disk is soft on the edge and hard far away from it
+        let poisson = ShadowFilter::Poisson { radius: 1.5 };
+        let edge = shadow_map
+            .calc_shadow_amount(texel(7.5, 8.0), 0.01, poisson)
+            .unwrap();
+        assert!(edge > 0.0 && edge < 1.0);
+        assert_eq!(
+            shadow_map.calc_shadow_amount(texel(3.0, 8.0), 0.01, poisson),
+            Some(0.0)
+        );
+
+        // a big enough bias pushes everything out of the shadow
+        assert_eq!(
+            shadow_map.calc_shadow_amount(texel(3.0, 8.0), 0.5, hard),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn test_cube_shadow_map() {
+        let position = Vector4::new(1.0, 2.0, 3.0, 1.0);
+        let (near, far) = (0.1, 10.0);
+
+        // there is a wall at a distance of 2 on every side of the light
+        let wall = (2.0 * far / (far - near) - far * near / (far - near)) / 2.0;
+        let faces = (0..6)
+            .map(|face| {
+                let projection = CubeShadowMap::face_view_projection(position, near, far, face);
+                ShadowMap::new(
+                    projection,
+                    Bitmap::from_bytes(8, 8, Format::R32F, vec![wall; 64]),
+                )
+            })
+            .collect();
+        let cube_shadow_map = CubeShadowMap::new(position, near, far, faces);
+
+        let hard = ShadowFilter::Hard;
+        for (direction, face) in [
+            (Vector4::new(1.0, 0.2, -0.3, 0.0), 0),
+            (Vector4::new(-0.1, -1.0, 0.5, 0.0), 3),
+            (Vector4::new(0.4, 0.3, -1.0, 0.0), 5),
+        ] {
+            assert_eq!(CubeShadowMap::face(direction), face);
+
+            // in front of the wall
+            let lit = position + direction * 1.5;
+            assert_eq!(cube_shadow_map.calc_shadow_amount(lit, 0.05, hard), 1.0);
+
+            // behind the wall
+            let shadowed = position + direction * 4.0;
+            assert_eq!(
+                cube_shadow_map.calc_shadow_amount(shadowed, 0.05, hard),
+                0.0
+            );
+        }
+    }
 }
@@ -1,14 +1,62 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
-use super::bitmap::Bitmap;
+use crate::math::Vector4;
 
+use super::{sampler::Sampler, texture::Texture};
+
+// how a surface looks, shared between instances (and between the tiles that are rasterized in parallel)
+//
+// every texture slot is optional and multiplies its matching color:
+//   color    * diffuse_map  -> albedo
+//   specular * specular_map -> highlight
+//   emissive * emissive_map -> glow (not affected by lights or shadows)
+//   color.w  * alpha_map.r  -> alpha (blended with what's behind it unless the material is opaque)
+#[derive(Debug)]
 pub struct Material {
-    pub light: bool,
-    pub bitmap: Rc<Box<Bitmap<u8>>>,
+    pub color: Vector4,     // base color tint (rgba 0 - 1)
+    pub specular: Vector4,  // rgb color of the specular highlight
+    pub emissive: Vector4,  // rgb color that the surface gives off by itself
+    pub shininess: f32,     // higher values make smaller and sharper highlights
+    pub alpha_cutoff: f32,  // pixels with less alpha are discarded (0 keeps all of them)
+    pub opaque: bool,       // the pixels that are kept aren't blended, whatever their alpha
+    pub double_sided: bool, // draw the back faces too, they are lit as if they were facing the camera
+    pub unlit: bool,        // skip the lighting, only the shadows darken the surface
+    pub sampler: Sampler,   // how every texture is filtered and wrapped
+
+    pub diffuse_map: Option<Arc<Texture>>,
+    pub specular_map: Option<Arc<Texture>>,
+    pub emissive_map: Option<Arc<Texture>>,
+    pub normal_map: Option<Arc<Texture>>, // tangent-space normals (OpenGL style, green is up)
+    pub alpha_map: Option<Arc<Texture>>,  // only the red channel is used
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            specular: Vector4::new(0.5, 0.5, 0.5, 0.0),
+            emissive: Vector4::ZERO,
+            shininess: 32.0,
+            alpha_cutoff: 0.0,
+            opaque: false,
+            double_sided: false,
+            unlit: false,
+            sampler: Sampler::default(),
+            diffuse_map: None,
+            specular_map: None,
+            emissive_map: None,
+            normal_map: None,
+            alpha_map: None,
+        }
+    }
 }
 
 impl Material {
-    pub fn new(light: bool, bitmap: Rc<Box<Bitmap<u8>>>) -> Self {
-        Self { light, bitmap }
+    // lit material with a diffuse texture
+    pub fn new(diffuse_map: Arc<Texture>) -> Self {
+        Self {
+            diffuse_map: Some(diffuse_map),
+            ..Default::default()
+        }
     }
 }
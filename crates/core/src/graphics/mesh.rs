@@ -10,4 +10,15 @@ impl Mesh {
     pub fn new(vertices: Vec<Vertex>, indices: Vec<usize>) -> Self {
         Self { vertices, indices }
     }
+
+    // distance from the origin of the mesh to its furthest vertex, a sphere this big holds the whole mesh
+    pub fn radius(&self) -> f32 {
+        self.vertices
+            .iter()
+            .map(|vertex| {
+                let p = vertex.position;
+                (p.x * p.x + p.y * p.y + p.z * p.z).sqrt()
+            })
+            .fold(0.0, f32::max)
+    }
 }
@@ -6,5 +6,10 @@ pub mod gradients;
 pub mod light;
 pub mod material;
 pub mod mesh;
+pub mod sampler;
 pub mod scan_buffer;
+pub mod shader;
+pub mod texture;
+pub mod tile;
+pub mod varyings;
 pub mod vertex;
@@ -0,0 +1,257 @@
+use crate::math::{lerp, Vector4};
+
+use super::{bitmap::Bitmap, color::Color, shader::Fragment, texture::Texture};
+
+// how a texture is sampled between (and across) its texels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,   // the closest texel of the full size bitmap, blocky up close and shimmers far away
+    Bilinear,  // blend the 4 closest texels of the best fitting mip level
+    Trilinear, // blend bilinear samples of the 2 closest mip levels
+}
+
+// what happens to texture coordinates outside of 0 - 1
+//
+// repeat:          | abc | abc | abc |
+// mirrored-repeat: | cba | abc | cba |
+// clamp-to-edge:   | aaa | abc | ccc |
+// clamp-to-border: | ### | abc | ### |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+// how a texture is read: the filter and the wrap mode of each axis (like a sampler object in OpenGL)
+// it's small and copied around freely, the same texture can be read with different samplers
+#[derive(Debug, Clone, Copy)]
+pub struct Sampler {
+    pub filter: TextureFilter,
+    pub wrap_u: Wrap,
+    pub wrap_v: Wrap,
+    pub border: Vector4, // rgba (0 - 1) used outside of the texture when clamping to the border
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::Trilinear,
+            wrap_u: Wrap::Repeat,
+            wrap_v: Wrap::Repeat,
+            border: Vector4::new(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+}
+
+impl Sampler {
+    pub fn new(filter: TextureFilter, wrap: Wrap) -> Self {
+        Self {
+            filter,
+            wrap_u: wrap,
+            wrap_v: wrap,
+            ..Default::default()
+        }
+    }
+
+    // color of the texture at the texture coordinates, `lod` is the mip level (see `Texture::lod`)
+    pub fn sample(&self, texture: &Texture, u: f32, v: f32, lod: f32) -> Color {
+        let last = texture.levels.len() - 1;
+
+        let texel = match self.filter {
+            TextureFilter::Nearest => self.sample_nearest(&texture.levels[0], u, v),
+            TextureFilter::Bilinear => {
+                let level = (lod.round() as usize).min(last);
+                self.sample_bilinear(&texture.levels[level], u, v)
+            }
+            TextureFilter::Trilinear => {
+                let level = (lod.floor() as usize).min(last);
+                let next = (level + 1).min(last);
+                let t = lod - level as f32;
+
+                let a = self.sample_bilinear(&texture.levels[level], u, v);
+                let b = self.sample_bilinear(&texture.levels[next], u, v);
+                a * (1.0 - t) + b * t
+            }
+        };
+
+        Color::new(
+            (texel.x + 0.5) as u8,
+            (texel.y + 0.5) as u8,
+            (texel.z + 0.5) as u8,
+            (texel.w + 0.5) as u8,
+        )
+    }
+
+    // sample a texture with the texture coordinates that are in the varyings of a fragment (lanes: u, v)
+    // the mip level is picked from how fast the texture coordinates change on the screen
+    pub fn sample_fragment(&self, texture: &Texture, fragment: &Fragment, lane: usize) -> Color {
+        let texcoords = fragment.varyings.get2(lane);
+
+        let lod = match self.filter {
+            TextureFilter::Nearest => 0.0,
+            _ => {
+                let ddx = Vector4::new(fragment.ddx(lane), fragment.ddx(lane + 1), 0.0, 0.0);
+                let ddy = Vector4::new(fragment.ddy(lane), fragment.ddy(lane + 1), 0.0, 0.0);
+                texture.lod(ddx, ddy)
+            }
+        };
+
+        self.sample(texture, texcoords.x, texcoords.y, lod)
+    }
+
+    // move a texel coordinate inside of the bitmap, none when it lands on the border
+    pub fn wrap(mode: Wrap, i: i32, size: u32) -> Option<u32> {
+        let size = size as i32;
+
+        match mode {
+            Wrap::Repeat => Some(i.rem_euclid(size) as u32),
+            Wrap::MirroredRepeat => {
+                // every other repetition is flipped
+                let m = i.rem_euclid(size * 2);
+                Some(if m < size { m } else { size * 2 - 1 - m } as u32)
+            }
+            Wrap::ClampToEdge => Some(i.clamp(0, size - 1) as u32),
+            Wrap::ClampToBorder => {
+                if i < 0 || i >= size {
+                    None
+                } else {
+                    Some(i as u32)
+                }
+            }
+        }
+    }
+
+    // texel as floats (0 - 255) so that they can be blended without rounding on every step
+    fn texel(&self, bitmap: &Bitmap<u8>, x: i32, y: i32) -> Vector4 {
+        let x = Self::wrap(self.wrap_u, x, bitmap.width);
+        let y = Self::wrap(self.wrap_v, y, bitmap.height);
+
+        match (x, y) {
+            (Some(x), Some(y)) => {
+                let color = bitmap.get_pixel(x, y);
+                Vector4::new(
+                    color.r as f32,
+                    color.g as f32,
+                    color.b as f32,
+                    color.a as f32,
+                )
+            }
+            _ => self.border * 255.0,
+        }
+    }
+
+    fn sample_nearest(&self, bitmap: &Bitmap<u8>, u: f32, v: f32) -> Vector4 {
+        let x = (u * bitmap.width as f32).floor() as i32;
+        let y = (v * bitmap.height as f32).floor() as i32;
+
+        self.texel(bitmap, x, y)
+    }
+
+    // texel centers are at half texels, blend the 4 centers around the coordinates
+    //
+    // a ---- b
+    // |  .   |
+    // c ---- d
+    fn sample_bilinear(&self, bitmap: &Bitmap<u8>, u: f32, v: f32) -> Vector4 {
+        let x = u * bitmap.width as f32 - 0.5;
+        let y = v * bitmap.height as f32 - 0.5;
+
+        let ix = x.floor();
+        let iy = y.floor();
+        let fx = x - ix;
+        let fy = y - iy;
+        let (ix, iy) = (ix as i32, iy as i32);
+
+        let a = self.texel(bitmap, ix, iy);
+        let b = self.texel(bitmap, ix + 1, iy);
+        let c = self.texel(bitmap, ix, iy + 1);
+        let d = self.texel(bitmap, ix + 1, iy + 1);
+
+        let top = a * (1.0 - fx) + b * fx;
+        let bottom = c * (1.0 - fx) + d * fx;
+
+        Vector4::new(
+            lerp(top.x, bottom.x, fy),
+            lerp(top.y, bottom.y, fy),
+            lerp(top.z, bottom.z, fy),
+            lerp(top.w, bottom.w, fy),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::texture::checker_board;
+
+    #[test]
+    fn test_texture_filters() {
+        let texture = Texture::new(checker_board(8));
+        let nearest = Sampler::new(TextureFilter::Nearest, Wrap::Repeat);
+        let bilinear = Sampler::new(TextureFilter::Bilinear, Wrap::Repeat);
+        let trilinear = Sampler::new(TextureFilter::Trilinear, Wrap::Repeat);
+
+        // one texel per pixel: the full size bitmap
+        let one = Vector4::new(1.0 / 8.0, 0.0, 0.0, 0.0);
+        assert_eq!(texture.lod(one, one), 0.0);
+
+        // 4 texels per pixel: two levels down
+        let four = Vector4::new(0.0, 4.0 / 8.0, 0.0, 0.0);
+        assert!((texture.lod(one, four) - 2.0).abs() < 0.0001);
+
+        // nearest and bilinear at the center of a texel match
+        let (u, v) = (0.5 / 8.0, 0.5 / 8.0);
+        let a = nearest.sample(&texture, u, v, 0.0);
+        let b = bilinear.sample(&texture, u, v, 0.0);
+        assert_eq!((a.r, b.r), (255, 255));
+
+        // between two texels bilinear blends them
+        let between = bilinear.sample(&texture, 1.0 / 8.0, 0.5 / 8.0, 0.0);
+        assert_eq!(between.r, 128);
+
+        // far away the checker-board becomes gray instead of shimmering
+        let far = trilinear.sample(&texture, u, v, 2.0);
+        assert_eq!(far.r, 128);
+
+        // half way between level 0 and 1
+        let half = trilinear.sample(&texture, u, v, 0.5);
+        assert!(half.r > 128 && half.r < 255);
+    }
+
+    #[test]
+    fn test_wrap_modes() {
+        // repeat: | abc | abc |
+        assert_eq!(Sampler::wrap(Wrap::Repeat, 4, 3), Some(1));
+        assert_eq!(Sampler::wrap(Wrap::Repeat, -1, 3), Some(2));
+
+        // mirrored-repeat: | cba | abc | cba |
+        assert_eq!(Sampler::wrap(Wrap::MirroredRepeat, 3, 3), Some(2));
+        assert_eq!(Sampler::wrap(Wrap::MirroredRepeat, 5, 3), Some(0));
+        assert_eq!(Sampler::wrap(Wrap::MirroredRepeat, -1, 3), Some(0));
+        assert_eq!(Sampler::wrap(Wrap::MirroredRepeat, 6, 3), Some(0));
+
+        // clamp-to-edge: | aaa | abc | ccc |
+        assert_eq!(Sampler::wrap(Wrap::ClampToEdge, -5, 3), Some(0));
+        assert_eq!(Sampler::wrap(Wrap::ClampToEdge, 7, 3), Some(2));
+
+        // clamp-to-border: | ### | abc | ### |
+        assert_eq!(Sampler::wrap(Wrap::ClampToBorder, 3, 3), None);
+        assert_eq!(Sampler::wrap(Wrap::ClampToBorder, 2, 3), Some(2));
+
+        // coordinates outside of 0 - 1 don't turn black or leak into the next row
+        let texture = Texture::new(checker_board(8));
+        let mut sampler = Sampler::new(TextureFilter::Nearest, Wrap::Repeat);
+        let (u, v) = (1.0 + 0.5 / 8.0, -1.0 + 0.5 / 8.0);
+        assert_eq!(sampler.sample(&texture, u, v, 0.0).r, 255);
+
+        sampler.wrap_u = Wrap::MirroredRepeat;
+        assert_eq!(sampler.sample(&texture, u, v, 0.0).r, 0);
+
+        sampler.wrap_u = Wrap::ClampToBorder;
+        sampler.border = Vector4::new(0.0, 1.0, 0.0, 1.0);
+        let border = sampler.sample(&texture, u, v, 0.0);
+        assert_eq!((border.r, border.g), (0, 255));
+    }
+}
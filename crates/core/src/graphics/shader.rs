@@ -0,0 +1,468 @@
+use std::sync::Arc;
+
+use crate::math::{Matrix4, Vector4};
+
+use super::{
+    color::Color, gradients::Gradients, light::Light, material::Material, texture::Texture,
+    varyings::Varyings, vertex::Vertex,
+};
+
+// programmable stages of the renderer (like OpenGL)
+//
+// mesh vertex -> [vertex] -> clip-space vertex -> clip -> rasterize -> [fragment] -> pixel
+//
+// shaders are shared between all tiles that are rasterized in parallel so they must be `Sync`
+pub trait Shader: Sync {
+    // move a mesh vertex into clip-space and return its position
+    // anything pushed into `varyings` gets interpolated across the triangle and handed to the fragment stage
+    fn vertex(&self, vertex: &Vertex, varyings: &mut Varyings) -> Vector4;
+
+    // color a single pixel that passed the depth test, returning none discards the pixel
+    // discarded pixels don't write into the color or the depth buffer
+    fn fragment(&self, fragment: &Fragment) -> Option<Color>;
+
+    // triangles that face away from the camera are skipped, shaders for double sided surfaces keep them
+    fn cull_back_faces(&self) -> bool {
+        true
+    }
+
+    // passes that only need the depth (like shadow-maps) leave the color buffer alone
+    fn writes_color(&self) -> bool {
+        true
+    }
+}
+
+// a pixel that is about to be drawn with all varyings interpolated (perspective correct)
+#[derive(Debug)]
+pub struct Fragment<'a> {
+    pub x: u32,                   // screen-space x
+    pub y: u32,                   // screen-space y
+    pub depth: f32,               // value that will be written into the depth buffer
+    pub varyings: Varyings,       // whatever the vertex stage pushed
+    pub one_over_z: f32,          // perspective of the pixel, needed for the derivatives
+    pub gradients: &'a Gradients, // how the varyings (divided by z) change across the triangle
+}
+
+impl<'a> Fragment<'a> {
+    // how much a varying changes when moving one pixel to the right (like ddx in hlsl)
+    //
+    // the gradients step the varying divided by z, so the quotient rule undoes the perspective
+    // v = V / Q  ->  ∂v/∂x = (∂V/∂x - v * ∂Q/∂x) / Q
+    pub fn ddx(&self, lane: usize) -> f32 {
+        let step_varying = self.gradients.varyings.step.x.get(lane);
+        let step_one_over_z = self.gradients.one_over_z.step.x;
+        (step_varying - self.varyings.get(lane) * step_one_over_z) / self.one_over_z
+    }
+
+    // how much a varying changes when moving one pixel down (like ddy in hlsl)
+    pub fn ddy(&self, lane: usize) -> f32 {
+        let step_varying = self.gradients.varyings.step.y.get(lane);
+        let step_one_over_z = self.gradients.one_over_z.step.y;
+        (step_varying - self.varyings.get(lane) * step_one_over_z) / self.one_over_z
+    }
+}
+
+// the default shader: textured with per-pixel (blinn-phong) lighting and shadow mapping
+//
+// varyings: [u, v, nx, ny, nz, wx, wy, wz, tx, ty, tz, tw, r, g, b, a]
+//            \__/  \________/  \________/  \____________/  \________/
+//         texcoords  normal   world-position   tangent      color
+pub struct StandardShader<'a> {
+    pub mvp: Matrix4,           // model-view-projection
+    pub model: Matrix4,         // model transform, moves vertices into world-space
+    pub normal_matrix: Matrix4, // inverse-transpose of the model, keeps normals perpendicular after scaling
+    pub eye: Vector4,           // camera position in world-space, used for the specular highlight
+    pub material: &'a Material,
+    pub lights: &'a [Light], // every light adds up, an empty list draws the texture as is
+}
+
+// where each varying of the standard shader starts
+const TEXCOORDS: usize = 0;
+const NORMAL: usize = 2;
+const WORLD_POSITION: usize = 5;
+const TANGENT: usize = 8;
+const COLOR: usize = 12;
+
+// how many lanes the standard shader takes, a shader built on it pushes its own varyings after them
+pub const STANDARD_VARYINGS: usize = 16;
+
+impl<'a> StandardShader<'a> {
+    pub fn new(
+        view_projection: &Matrix4,
+        eye: Vector4,
+        transform: &Matrix4,
+        material: &'a Material,
+        lights: &'a [Light],
+    ) -> Self {
+        let mut normal_matrix = transform.clone();
+        if normal_matrix.invert() {
+            normal_matrix.transpose();
+        } else {
+            normal_matrix = transform.clone();
+        }
+
+        Self {
+            mvp: Matrix4::multiply(view_projection, transform),
+            model: transform.clone(),
+            normal_matrix,
+            eye,
+            material,
+            lights,
+        }
+    }
+
+    // blinn-phong: ambient + diffuse + specular
+    //
+    //   light    normal   eye
+    //       \      |  half  /
+    //        \     |  /    /
+    //         \    | /    /
+    //          \   |/   /
+    // ----------- pixel -----------
+    //
+    // the specular highlight is the strongest when the half vector (between light and eye) lines up with the normal
+    // `visibility` is 0 when the pixel is in shadow and 1 when the light can see it
+    // returns the rgb that a single light adds to the pixel
+    pub fn blinn_phong(
+        &self,
+        light: &Light,
+        albedo: Vector4,
+        specular_color: Vector4,
+        normal: Vector4,
+        world_position: Vector4,
+        visibility: f32,
+    ) -> Vector4 {
+        let normal = normalize_or_zero(normal);
+        let (to_light, attenuation) = light.incoming(world_position);
+        let to_eye = normalize_or_zero(self.eye - world_position);
+        let half = normalize_or_zero(to_light + to_eye);
+
+        let diffuse = normal.dot(to_light).max(0.0);
+        let specular = if diffuse > 0.0 {
+            normal.dot(half).max(0.0).powf(self.material.shininess)
+        } else {
+            0.0
+        };
+
+        let strength = light.intensity * attenuation * visibility;
+        let mut result = Vector4::ZERO;
+
+        // each color channel is lit on its own
+        result.x = albedo.x * (light.ambient + light.color.x * diffuse * strength)
+            + specular_color.x * light.color.x * specular * strength;
+        result.y = albedo.y * (light.ambient + light.color.y * diffuse * strength)
+            + specular_color.y * light.color.y * specular * strength;
+        result.z = albedo.z * (light.ambient + light.color.z * diffuse * strength)
+            + specular_color.z * light.color.z * specular * strength;
+
+        result
+    }
+
+    // bend the normal with the material's normal map (tangent-space)
+    //
+    //   tangent-space     world-space
+    //   r -> tangent      x y z
+    //   g -> bitangent    x y z
+    //   b -> normal       x y z
+    //
+    // vertices without a tangent keep the interpolated normal
+    pub fn normal_map(&self, fragment: &Fragment, normal: Vector4) -> Vector4 {
+        let normal_map = match &self.material.normal_map {
+            Some(normal_map) => normal_map,
+            None => return normal,
+        };
+
+        let tangent = fragment.varyings.get4(TANGENT);
+        let handedness = if tangent.w < 0.0 { -1.0 } else { 1.0 };
+
+        // interpolation bends the tangent away from the normal, gram-schmidt makes them perpendicular again
+        let mut tangent = tangent - normal * normal.dot(tangent);
+        tangent.w = 0.0;
+        if tangent.length() <= f32::EPSILON {
+            return normal;
+        }
+        let tangent = tangent.normalized();
+        let bitangent = normal.cross(tangent) * handedness;
+
+        // colors (0 - 1) to directions (-1 - 1)
+        let texel = self
+            .material
+            .sampler
+            .sample_fragment(normal_map, fragment, TEXCOORDS);
+        let x = texel.r as f32 / 255.0 * 2.0 - 1.0;
+        let y = texel.g as f32 / 255.0 * 2.0 - 1.0;
+        let z = texel.b as f32 / 255.0 * 2.0 - 1.0;
+
+        normalize_or_zero(tangent * x + bitangent * y + normal * z)
+    }
+}
+
+impl<'a> Shader for StandardShader<'a> {
+    fn vertex(&self, vertex: &Vertex, varyings: &mut Varyings) -> Vector4 {
+        // normals are directions so the translation must not affect them
+        let mut normal = vertex.normal;
+        normal.w = 0.0;
+
+        // lanes must be pushed in the same order as the constants above
+        debug_assert_eq!(varyings.len, TEXCOORDS);
+        varyings.push2(vertex.texcoords);
+        debug_assert_eq!(varyings.len, NORMAL);
+        varyings.push3(Matrix4::multiply_vector(&self.normal_matrix, normal));
+        debug_assert_eq!(varyings.len, WORLD_POSITION);
+        varyings.push3(Matrix4::multiply_vector(&self.model, vertex.position));
+
+        // tangents lie on the surface so they move with the model (unlike the normals)
+        let mut tangent = vertex.tangent;
+        tangent.w = 0.0;
+        tangent = Matrix4::multiply_vector(&self.model, tangent);
+        tangent.w = vertex.tangent.w;
+        debug_assert_eq!(varyings.len, TANGENT);
+        varyings.push4(tangent);
+        debug_assert_eq!(varyings.len, COLOR);
+        varyings.push4(vertex.color);
+        debug_assert_eq!(varyings.len, STANDARD_VARYINGS);
+
+        // transform vertex into mvp
+        Matrix4::multiply_vector(&self.mvp, vertex.position)
+    }
+
+    fn fragment(&self, fragment: &Fragment) -> Option<Color> {
+        let material = self.material;
+
+        let mut world_position = fragment.varyings.get3(WORLD_POSITION);
+        world_position.w = 1.0;
+
+        // albedo: the base color tinted by the vertex color and the diffuse map
+        let mut albedo = multiply(material.color, fragment.varyings.get4(COLOR));
+        if let Some(diffuse) = self.sample(&material.diffuse_map, fragment) {
+            albedo = multiply(albedo, diffuse);
+        }
+
+        // alpha: see-through pixels are cut out
+        if let Some(alpha) = self.sample(&material.alpha_map, fragment) {
+            albedo.w *= alpha.x;
+        }
+        if albedo.w < material.alpha_cutoff {
+            return None;
+        }
+        if material.opaque {
+            albedo.w = 1.0;
+        }
+
+        let mut emissive = material.emissive;
+        if let Some(glow) = self.sample(&material.emissive_map, fragment) {
+            emissive = multiply(emissive, glow);
+        }
+
+        if self.lights.is_empty() {
+            return Some(to_color(albedo + emissive));
+        }
+
+        let mut normal = normalize_or_zero(fragment.varyings.get3(NORMAL));
+
+        // the back of a double sided surface faces the camera, so its normal is flipped
+        if material.double_sided && normal.dot(self.eye - world_position) < 0.0 {
+            normal = -normal;
+        }
+        let surface_normal = self.normal_map(fragment, normal);
+
+        let mut specular = material.specular;
+        if let Some(highlight) = self.sample(&material.specular_map, fragment) {
+            specular = multiply(specular, highlight);
+        }
+
+        // # debug: texture coords
+        // let texcoords = fragment.varyings.get2(TEXCOORDS);
+        // return Some(Color::newf(texcoords.x, texcoords.y, 0.0, 1.0));
+
+        // # debug: draw normals
+        // return Some(Color::newf(normal.x, normal.y, normal.z, 1.0));
+
+        let mut lit = Vector4::ZERO;
+        let mut min_visibility: f32 = 1.0;
+
+        for light in self.lights {
+            // shadow maping: lights with a shadow-map can be blocked by other geometry
+            let visibility = light.visibility(world_position, normal);
+            min_visibility = min_visibility.min(visibility);
+
+            // # debug: see the worls through the shadow-map
+            // return Some(Color::newf(visibility, visibility, visibility, 1.0));
+
+            // light it up
+            if !material.unlit {
+                lit += self.blinn_phong(
+                    light,
+                    albedo,
+                    specular,
+                    surface_normal,
+                    world_position,
+                    visibility,
+                );
+            }
+        }
+
+        // unlit materials only get darker in the shadow (down to 60%)
+        if material.unlit {
+            lit = albedo * (0.6 + 0.4 * min_visibility);
+        }
+
+        lit += emissive;
+        lit.w = albedo.w;
+        Some(to_color(lit))
+    }
+
+    fn cull_back_faces(&self) -> bool {
+        !self.material.double_sided
+    }
+}
+
+impl<'a> StandardShader<'a> {
+    // a texture slot of the material (rgba 0 - 1), none when the slot is empty
+    fn sample(&self, slot: &Option<Arc<Texture>>, fragment: &Fragment) -> Option<Vector4> {
+        let texture = slot.as_ref()?;
+        let texel = self
+            .material
+            .sampler
+            .sample_fragment(texture, fragment, TEXCOORDS);
+
+        Some(Vector4::new(
+            texel.r as f32 / 255.0,
+            texel.g as f32 / 255.0,
+            texel.b as f32 / 255.0,
+            texel.a as f32 / 255.0,
+        ))
+    }
+}
+
+// draws only the depth, for shadow-maps
+//
+// varyings: [u, v, a] when the material cuts out pixels, none otherwise
+//            \__/  |
+//       texcoords  alpha of the vertex color
+pub struct DepthShader<'a> {
+    pub mvp: Matrix4, // model-view-projection
+    pub material: &'a Material,
+}
+
+impl<'a> DepthShader<'a> {
+    pub fn new(view_projection: &Matrix4, transform: &Matrix4, material: &'a Material) -> Self {
+        Self {
+            mvp: Matrix4::multiply(view_projection, transform),
+            material,
+        }
+    }
+
+    // the alpha of a pixel like the standard shader finds it (without the lighting)
+    fn alpha(&self, fragment: &Fragment) -> f32 {
+        let material = self.material;
+        let mut alpha = material.color.w * fragment.varyings.get(2);
+
+        if let Some(diffuse) = &material.diffuse_map {
+            alpha *= material.sampler.sample_fragment(diffuse, fragment, 0).a as f32 / 255.0;
+        }
+        if let Some(alpha_map) = &material.alpha_map {
+            alpha *= material.sampler.sample_fragment(alpha_map, fragment, 0).r as f32 / 255.0;
+        }
+
+        alpha
+    }
+}
+
+impl<'a> Shader for DepthShader<'a> {
+    fn vertex(&self, vertex: &Vertex, varyings: &mut Varyings) -> Vector4 {
+        // the texcoords are only needed to cut out pixels
+        if self.material.alpha_cutoff > 0.0 {
+            varyings.push2(vertex.texcoords);
+            varyings.push(vertex.color.w);
+        }
+
+        Matrix4::multiply_vector(&self.mvp, vertex.position)
+    }
+
+    fn fragment(&self, fragment: &Fragment) -> Option<Color> {
+        // cut out pixels don't cast shadows
+        if self.material.alpha_cutoff > 0.0 && self.alpha(fragment) < self.material.alpha_cutoff {
+            return None;
+        }
+
+        Some(Color::BLACK)
+    }
+
+    fn cull_back_faces(&self) -> bool {
+        !self.material.double_sided
+    }
+
+    fn writes_color(&self) -> bool {
+        false
+    }
+}
+
+// component-wise multiplication of two colors
+fn multiply(a: Vector4, b: Vector4) -> Vector4 {
+    Vector4::new(a.x * b.x, a.y * b.y, a.z * b.z, a.w * b.w)
+}
+
+// rgba (0 - 1) to a color, channels above 1.0 saturate
+fn to_color(v: Vector4) -> Color {
+    let channel = |c: f32| (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8;
+    Color::new(channel(v.x), channel(v.y), channel(v.z), channel(v.w))
+}
+
+// meshes without normals and degenerate half vectors have no length, they just stay zero
+fn normalize_or_zero(v: Vector4) -> Vector4 {
+    let len = v.length();
+    if len > 0.0 {
+        v / len
+    } else {
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::bitmap::Bitmap;
+
+    fn test_light() -> Light {
+        // the light shines straight down
+        let mut light = Light::directional(-Vector4::UP);
+        light.ambient = 0.1;
+        light
+    }
+
+    #[test]
+    fn test_blinn_phong() {
+        let material = Material::new(Arc::new(Texture::new(Bitmap::new(1, 1))));
+        let lights = [test_light()];
+        let light = &lights[0];
+        let eye = Vector4::new(0.0, 5.0, 0.0, 1.0);
+        let shader = StandardShader::new(
+            &Matrix4::new_identity(),
+            eye,
+            &Matrix4::new_identity(),
+            &material,
+            &lights,
+        );
+
+        let albedo = Vector4::new(1.0, 0.5, 0.25, 1.0);
+        let origin = Vector4::new(0.0, 0.0, 0.0, 1.0);
+
+        // facing the light and the eye: full diffuse and the brightest highlight
+        let lit = shader.blinn_phong(light, albedo, material.specular, Vector4::UP, origin, 1.0);
+        assert!((lit.y - (0.5 * 1.1 + 0.5)).abs() < 0.0001);
+
+        // facing away from the light: only the ambient light is left
+        let unlit = shader.blinn_phong(light, albedo, material.specular, -Vector4::UP, origin, 1.0);
+        assert!((unlit.y - 0.5 * 0.1).abs() < 0.0001);
+
+        // in shadow: the same as facing away
+        let shadow = shader.blinn_phong(light, albedo, material.specular, Vector4::UP, origin, 0.0);
+        assert!((shadow.x - unlit.x).abs() < 0.0001);
+
+        // a tilted normal is darker and the highlight fades quickly
+        let tilted = Vector4::new(0.5, 1.0, 0.0, 0.0);
+        let side = shader.blinn_phong(light, albedo, material.specular, tilted, origin, 1.0);
+        assert!(side.y < lit.y && side.y > unlit.y);
+    }
+}
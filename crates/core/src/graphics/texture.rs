@@ -0,0 +1,132 @@
+use crate::math::Vector4;
+
+use super::bitmap::Bitmap;
+
+// a bitmap together with its mip chain, every level is half the size of the previous one
+//
+// 0 ---------------- 1 -------- 2 ---- 3 -- 4 - ...
+//   |              |   |      |   |  |   ||
+//   |              |   |      |   ----
+//   |              |   --------
+//   |              |
+//   ----------------
+#[derive(Debug, Clone)]
+pub struct Texture {
+    pub levels: Vec<Bitmap<u8>>, // level 0 is the original bitmap, the last level is 1x1
+}
+
+impl Texture {
+    pub fn new(bitmap: Bitmap<u8>) -> Self {
+        let mut levels = vec![bitmap];
+
+        while let Some(level) = levels.last() {
+            if level.width == 1 && level.height == 1 {
+                break;
+            }
+            let next = Self::downsample(level);
+            levels.push(next);
+        }
+
+        Self { levels }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.levels[0].height
+    }
+
+    // half the size of the bitmap where each texel is the average of 4 texels (box filter)
+    // odd sizes repeat the last row/column
+    fn downsample(bitmap: &Bitmap<u8>) -> Bitmap<u8> {
+        let width = (bitmap.width / 2).max(1);
+        let height = (bitmap.height / 2).max(1);
+        let channels = bitmap.channels();
+        let mut result = Bitmap::with_format(width, height, bitmap.format);
+
+        for y in 0..height {
+            for x in 0..width {
+                let x0 = (x * 2).min(bitmap.width - 1);
+                let x1 = (x * 2 + 1).min(bitmap.width - 1);
+                let y0 = (y * 2).min(bitmap.height - 1);
+                let y1 = (y * 2 + 1).min(bitmap.height - 1);
+
+                for c in 0..channels {
+                    let sum: u32 = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)]
+                        .iter()
+                        .map(|(sx, sy)| {
+                            bitmap.pixels[(sx + sy * bitmap.width) as usize * channels + c] as u32
+                        })
+                        .sum();
+
+                    // round to the closest value
+                    result.pixels[(x + y * width) as usize * channels + c] = ((sum + 2) / 4) as u8;
+                }
+            }
+        }
+
+        result
+    }
+
+    // mip level that fits the size of a pixel on the texture
+    // the derivatives are how much the texture coordinates change between neighbouring pixels on the screen
+    //
+    // lod = log2(texels covered by a pixel)
+    pub fn lod(&self, ddx: Vector4, ddy: Vector4) -> f32 {
+        let width = self.width() as f32;
+        let height = self.height() as f32;
+
+        let dx = ((ddx.x * width).powi(2) + (ddx.y * height).powi(2)).sqrt();
+        let dy = ((ddy.x * width).powi(2) + (ddy.y * height).powi(2)).sqrt();
+        let rho = dx.max(dy);
+
+        if rho <= 0.0 || !rho.is_finite() {
+            return 0.0;
+        }
+
+        rho.log2().clamp(0.0, (self.levels.len() - 1) as f32)
+    }
+}
+
+// black and white checker-board, every texel is a square (for the texture and the sampler tests)
+#[cfg(test)]
+pub(crate) fn checker_board(size: u32) -> Bitmap<u8> {
+    use crate::graphics::color::Color;
+
+    let mut bitmap = Bitmap::new(size, size);
+    for y in 0..size {
+        for x in 0..size {
+            let color = if (x + y) % 2 == 0 {
+                Color::WHITE
+            } else {
+                Color::BLACK
+            };
+            bitmap.set_pixel(x, y, &color);
+        }
+    }
+    bitmap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mip_chain() {
+        let texture = Texture::new(checker_board(8));
+
+        let sizes: Vec<_> = texture.levels.iter().map(|l| (l.width, l.height)).collect();
+        assert_eq!(sizes, vec![(8, 8), (4, 4), (2, 2), (1, 1)]);
+
+        // the checker-board averages out to gray
+        let gray = texture.levels[1].get_pixel(1, 2);
+        assert_eq!((gray.r, gray.a), (128, 255));
+
+        // odd sizes still end with a single texel
+        let texture = Texture::new(Bitmap::new(5, 2));
+        let sizes: Vec<_> = texture.levels.iter().map(|l| (l.width, l.height)).collect();
+        assert_eq!(sizes, vec![(5, 2), (2, 1), (1, 1)]);
+    }
+}
@@ -0,0 +1,53 @@
+use super::{bitmap::blend_pixel, color::Color};
+
+// a horizontal strip of the screen that owns its rows of the color and depth buffers
+// tiles never overlap so they can be rasterized on different threads at the same time
+//
+// 0 ----------------------- tile 0
+//   |                     |
+// 1 ----------------------- tile 1
+//   |        /\           |
+// 2 -------/----\---------- tile 2
+//   |    /________\       |
+// 3 ----------------------- tile 3
+#[derive(Debug)]
+pub struct Tile<'a> {
+    pub y_start: u32,         // first screen row of the tile
+    pub y_end: u32,           // last screen row of the tile (not inclusive)
+    pub width: u32,           // width of the screen in pixels
+    pub color: &'a mut [u8],  // rows of the color buffer (r,g,b,a)
+    pub depth: &'a mut [f32], // rows of the depth buffer
+}
+
+impl<'a> Tile<'a> {
+    pub fn new(y_start: u32, width: u32, color: &'a mut [u8], depth: &'a mut [f32]) -> Self {
+        let rows = depth.len() as u32 / width;
+
+        Self {
+            y_start,
+            y_end: y_start + rows,
+            width,
+            color,
+            depth,
+        }
+    }
+
+    // flat index of a screen pixel inside of the tile, `y` must be within the tile rows
+    #[inline(always)]
+    pub fn index(&self, x: u32, y: u32) -> usize {
+        (x + (y - self.y_start) * self.width) as usize
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x < self.width && y >= self.y_start && y < self.y_end
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: &Color) {
+        if !self.contains(x, y) {
+            return;
+        }
+
+        let index = self.index(x, y) * 4;
+        blend_pixel(&mut self.color[index..index + 4], color);
+    }
+}
@@ -0,0 +1,209 @@
+use std::ops::{Add, AddAssign, Mul, Sub};
+
+use crate::math::linear_algebra::vector::Vector4;
+
+// how many f32 lanes a vertex can hand over to the fragment stage
+// the standard shader takes the first 16, the rest is left for shaders that add their own
+pub const MAX_VARYINGS: usize = 24;
+
+// values that the vertex stage outputs and that get interpolated across the triangle (like OpenGL)
+// it's up to the shader to decide what each lane means, the rasterizer only interpolates them
+//
+// lanes: [u, v, nx, ny, nz, ...]
+//         \__/  \________/
+//       texcoords  normal
+#[derive(Debug, Clone, Copy)]
+pub struct Varyings {
+    pub lanes: [f32; MAX_VARYINGS],
+    pub len: usize, // how many lanes are in use
+}
+
+impl Default for Varyings {
+    fn default() -> Self {
+        Self {
+            lanes: [0.0; MAX_VARYINGS],
+            len: 0,
+        }
+    }
+}
+
+impl Varyings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // add a value to the next free lane
+    pub fn push(&mut self, value: f32) {
+        assert!(
+            self.len < MAX_VARYINGS,
+            "out of varying lanes: {MAX_VARYINGS}"
+        );
+        self.lanes[self.len] = value;
+        self.len += 1;
+    }
+
+    pub fn push2(&mut self, v: Vector4) {
+        self.push(v.x);
+        self.push(v.y);
+    }
+
+    pub fn push3(&mut self, v: Vector4) {
+        self.push2(v);
+        self.push(v.z);
+    }
+
+    pub fn push4(&mut self, v: Vector4) {
+        self.push3(v);
+        self.push(v.w);
+    }
+
+    pub fn get(&self, lane: usize) -> f32 {
+        self.lanes[lane]
+    }
+
+    pub fn get2(&self, lane: usize) -> Vector4 {
+        Vector4::new(self.lanes[lane], self.lanes[lane + 1], 0.0, 0.0)
+    }
+
+    pub fn get3(&self, lane: usize) -> Vector4 {
+        Vector4::new(
+            self.lanes[lane],
+            self.lanes[lane + 1],
+            self.lanes[lane + 2],
+            0.0,
+        )
+    }
+
+    pub fn get4(&self, lane: usize) -> Vector4 {
+        Vector4::new(
+            self.lanes[lane],
+            self.lanes[lane + 1],
+            self.lanes[lane + 2],
+            self.lanes[lane + 3],
+        )
+    }
+
+    pub fn lerp(&self, other: &Varyings, factor: f32) -> Self {
+        *self * (1.0 - factor) + *other * factor
+    }
+}
+
+impl Add for Varyings {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for Varyings {
+    fn add_assign(&mut self, rhs: Self) {
+        self.len = self.len.max(rhs.len);
+        for (lane, value) in self.lanes.iter_mut().zip(rhs.lanes).take(self.len) {
+            *lane += value;
+        }
+    }
+}
+
+impl Sub for Varyings {
+    type Output = Self;
+
+    fn sub(mut self, rhs: Self) -> Self::Output {
+        self.len = self.len.max(rhs.len);
+        for (lane, value) in self.lanes.iter_mut().zip(rhs.lanes).take(self.len) {
+            *lane -= value;
+        }
+        self
+    }
+}
+
+impl Mul<f32> for Varyings {
+    type Output = Self;
+
+    fn mul(mut self, rhs: f32) -> Self::Output {
+        for lane in self.lanes.iter_mut().take(self.len) {
+            *lane *= rhs;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varyings(values: &[f32]) -> Varyings {
+        let mut varyings = Varyings::new();
+        for &value in values {
+            varyings.push(value);
+        }
+        varyings
+    }
+
+    #[test]
+    fn test_push_get() {
+        let mut varyings = Varyings::new();
+        varyings.push(1.0);
+        varyings.push2(Vector4::new(2.0, 3.0, 9.0, 9.0));
+        varyings.push3(Vector4::new(4.0, 5.0, 6.0, 9.0));
+        varyings.push4(Vector4::new(7.0, 8.0, 9.0, 10.0));
+
+        // only the lanes that were asked for are pushed
+        assert_eq!(varyings.len, 10);
+        assert_eq!(varyings.get(0), 1.0);
+        assert_eq!(varyings.get2(1), Vector4::new(2.0, 3.0, 0.0, 0.0));
+        assert_eq!(varyings.get3(3), Vector4::new(4.0, 5.0, 6.0, 0.0));
+        assert_eq!(varyings.get4(6), Vector4::new(7.0, 8.0, 9.0, 10.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_push_full() {
+        varyings(&[0.0; MAX_VARYINGS + 1]);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = varyings(&[1.0, 2.0, 3.0]);
+        let b = varyings(&[4.0, 6.0]);
+
+        // the result has as many lanes as the longest one
+        let sum = a + b;
+        assert_eq!(sum.len, 3);
+        assert_eq!(&sum.lanes[..3], &[5.0, 8.0, 3.0]);
+
+        let difference = b - a;
+        assert_eq!(difference.len, 3);
+        assert_eq!(&difference.lanes[..3], &[3.0, 4.0, -3.0]);
+
+        let mut accumulated = a;
+        accumulated += a;
+        assert_eq!(&(a * 2.0).lanes[..3], &accumulated.lanes[..3]);
+
+        // the unused lanes are left alone
+        assert!((a * 2.0).lanes[3..].iter().all(|lane| *lane == 0.0));
+
+        let half = a.lerp(&b, 0.5);
+        assert_eq!(&half.lanes[..3], &[2.5, 4.0, 1.5]);
+    }
+
+    #[test]
+    fn test_perspective_correct() {
+        // a varying going from 0 to 4 between a close (z = 1) and a far vertex (z = 3)
+        let (near_z, far_z) = (1.0, 3.0);
+        let near = varyings(&[0.0]);
+        let far = varyings(&[4.0]);
+
+        // the rasterizer steps the varyings divided by z in screen-space and multiplies by z for every pixel
+        let t = 0.5;
+        let one_over_z = (1.0 / near_z) * (1.0 - t) + (1.0 / far_z) * t;
+        let pixel = (near * (1.0 / near_z)).lerp(&(far * (1.0 / far_z)), t) * (1.0 / one_over_z);
+
+        // half way across the screen is only a quarter of the way into the depth (z = 1.5)
+        assert!((pixel.get(0) - 1.0).abs() < 0.0001);
+
+        // without the perspective it would be half way
+        assert_eq!(near.lerp(&far, t).get(0), 2.0);
+    }
+}
@@ -1,11 +1,29 @@
 use crate::math::linear_algebra::{matrix::Matrix4, vector::Vector4};
 
-#[derive(Debug, Default, Clone, Copy)]
+use super::varyings::Varyings;
+
+#[derive(Debug, Clone, Copy)]
 pub struct Vertex {
     pub position: Vector4,
     pub texcoords: Vector4,
     pub normal: Vector4,
-    pub shadow_map_coords: Vector4,
+    pub tangent: Vector4, // xyz: tangent, w: handedness of the bitangent (1 or -1), zero when there is none
+    pub color: Vector4, // rgba (0 - 1) that tints the material, white when the model doesn't have colors
+    pub joints: [u16; 4], // the joints of the skeleton that move this vertex
+    pub weights: Vector4, // how much each joint moves it (they add up to 1), all zero when there's no skeleton
+}
+
+impl Default for Vertex {
+    fn default() -> Self {
+        return Self::new(Vector4::ZERO, Vector4::ZERO, Vector4::ZERO);
+    }
+}
+
+// vertex that came out of the vertex stage: a clip-space position and the varyings
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ClipVertex {
+    pub position: Vector4,
+    pub varyings: Varyings,
 }
 
 impl Vertex {
@@ -14,14 +32,61 @@ impl Vertex {
             position,
             texcoords,
             normal,
-            shadow_map_coords: Vector4::ZERO,
+            tangent: Vector4::ZERO,
+            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            joints: [0; 4],
+            weights: Vector4::ZERO,
         };
     }
 
+    pub fn with_tangent(mut self, tangent: Vector4) -> Self {
+        self.tangent = tangent;
+        return self;
+    }
+
+    pub fn with_color(mut self, color: Vector4) -> Self {
+        self.color = color;
+        return self;
+    }
+
+    pub fn with_skin(mut self, joints: [u16; 4], weights: Vector4) -> Self {
+        self.joints = joints;
+        self.weights = weights;
+        return self;
+    }
+
     pub fn transform(mut self, transform_mat: &Matrix4, normal_mat: &Matrix4) -> Self {
         self.position = Matrix4::multiply_vector(transform_mat, self.position);
         self.normal = Matrix4::multiply_vector(normal_mat, self.normal); // for light direction
-                                                                         // self.shadow_map_coords = Matrix4::multiply_vector(normal_mat, self.shadow_map_coords); // for light direction
+
+        // tangents follow the surface so they move with the model, the handedness stays the same
+        let handedness = self.tangent.w;
+        self.tangent.w = 0.0;
+        self.tangent = Matrix4::multiply_vector(transform_mat, self.tangent);
+        self.tangent.w = handedness;
+        return self;
+    }
+
+    // lerp all vertex values, the skin can't be blended (the joints could be different) so it's the one of `self`
+    pub fn lerp(&self, other: &Vertex, lerp_amt: f32) -> Self {
+        return Self::new(
+            self.position.lerp(other.position, lerp_amt),
+            self.texcoords.lerp(other.texcoords, lerp_amt),
+            self.normal.lerp(other.normal, lerp_amt),
+        )
+        .with_tangent(self.tangent.lerp(other.tangent, lerp_amt))
+        .with_color(self.color.lerp(other.color, lerp_amt))
+        .with_skin(self.joints, self.weights);
+    }
+}
+
+impl ClipVertex {
+    pub fn new(position: Vector4, varyings: Varyings) -> Self {
+        return Self { position, varyings };
+    }
+
+    pub fn transform(mut self, transform_mat: &Matrix4) -> Self {
+        self.position = Matrix4::multiply_vector(transform_mat, self.position);
         return self;
     }
 
@@ -49,7 +114,7 @@ impl Vertex {
     // | /  |
     // |/   |
     // ------
-    pub fn triangle_area_times_two(&self, b: &Vertex, c: &Vertex) -> f32 {
+    pub fn triangle_area_times_two(&self, b: &ClipVertex, c: &ClipVertex) -> f32 {
         let x1 = b.position.x - self.position.x;
         let y1 = b.position.y - self.position.y;
 
@@ -59,20 +124,12 @@ impl Vertex {
         return x1 * y2 - x2 * y1;
     }
 
-    // lerp all vertex values, it's used for clipping vertices
-    pub fn lerp(&self, other: &Vertex, lerp_amt: f32) -> Self {
-        let mut vertex = Self::new(
+    // lerp the position and all varyings, it's used for clipping vertices
+    pub fn lerp(&self, other: &ClipVertex, lerp_amt: f32) -> Self {
+        return Self::new(
             self.position.lerp(other.position, lerp_amt),
-            self.texcoords.lerp(other.texcoords, lerp_amt),
-            self.normal.lerp(other.normal, lerp_amt),
+            self.varyings.lerp(&other.varyings, lerp_amt),
         );
-
-        // lerp with shadow-map-coords
-        vertex.shadow_map_coords = self
-            .shadow_map_coords
-            .lerp(other.shadow_map_coords, lerp_amt);
-
-        return vertex;
     }
 
     // clipping before perspective divide
@@ -104,18 +161,18 @@ mod tests {
     #[test]
     fn test_view_frustum() {
         // in view
-        let v1 = Vertex::new(Vector4::ZERO, Vector4::ZERO, Vector4::ONE);
+        let v1 = ClipVertex::new(Vector4::ZERO, Varyings::new());
         assert!(v1.is_inside_view_frustum());
 
         let high_w = Vector4::new(-1.0, 1.0, -1.0, 2.0);
 
-        let v1 = Vertex::new(high_w, Vector4::ZERO, Vector4::ONE);
+        let v1 = ClipVertex::new(high_w, Varyings::new());
         assert!(v1.is_inside_view_frustum());
 
         // not in view
         let low_w = Vector4::new(1.0, -1.0, 1.0, 0.5);
 
-        let v1 = Vertex::new(low_w, Vector4::ZERO, Vector4::ONE);
+        let v1 = ClipVertex::new(low_w, Varyings::new());
         assert!(!v1.is_inside_view_frustum());
     }
 }
@@ -426,6 +426,17 @@ impl Matrix4 {
         return true;
     }
 
+    // flip the matrix over its diagonal: [col][row] -> [row][col]
+    pub fn transpose(&mut self) {
+        for i in 0..4 {
+            for j in (i + 1)..4 {
+                let value = self[i][j];
+                self[i][j] = self[j][i];
+                self[j][i] = value;
+            }
+        }
+    }
+
     #[inline(always)]
     pub fn translation(&self) -> Vector4 {
         return Vector4::new(self[3][0], self[3][1], self[3][2], 1.0);
@@ -574,4 +585,21 @@ mod tests {
 
         assert_eq!(*m1, *m2);
     }
+
+    #[test]
+    fn test_matrix_transpose() {
+        let mut m1 = Matrix4::new_identity();
+
+        m1[3][0] = 5.0; // move x
+        m1[3][1] = 6.0; // move y
+        m1[1][2] = 7.0;
+
+        m1.transpose();
+
+        assert_eq!(m1[0][3], 5.0);
+        assert_eq!(m1[1][3], 6.0);
+        assert_eq!(m1[2][1], 7.0);
+        assert_eq!(m1[3][0], 0.0);
+        assert_eq!(m1[0][0], 1.0);
+    }
 }
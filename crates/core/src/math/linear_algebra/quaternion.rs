@@ -1,7 +1,14 @@
-use super::vector::Vector4;
+use crate::math::PI;
+
+use super::{matrix::Matrix4, vector::Vector4};
 
 /// # Quaternion
 /// Good old quaternion - pointing somewhere in 4D
+///
+/// a rotation is a unit quaternion, `q` and `-q` are the same rotation
+///
+/// euler angles are in degrees (like `Matrix4::rotate_y`) and applied as yaw (y) * pitch (x) * roll (z):
+/// roll around the forward axis first, then pitch up or down and then yaw around the up axis
 #[derive(Clone, Copy, Debug)]
 pub struct Quaternion {
     pub x: f32,
@@ -11,6 +18,13 @@ pub struct Quaternion {
 }
 
 impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        w: 1.0,
+    };
+
     pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
         return Self { x, y, z, w };
     }
@@ -47,6 +61,186 @@ impl Quaternion {
     pub fn dot(&self, q: Vector4) -> f32 {
         return self.x * q.x + self.y * q.y + self.z * q.z + self.w * q.w;
     }
+
+    // undoes the rotation, for unit quaternions it's the same as the conjugate
+    pub fn inverse(&self) -> Self {
+        let length_squared = self.dot_quaternion(*self);
+
+        if length_squared <= 0.0 {
+            panic!("can't invert a quaternion with zero length")
+        }
+
+        let conjugate = self.conjugate();
+        return Self::new(
+            conjugate.x / length_squared,
+            conjugate.y / length_squared,
+            conjugate.z / length_squared,
+            conjugate.w / length_squared,
+        );
+    }
+
+    // lerp and normalize, fast but the speed isn't constant (it's quicker in the middle)
+    pub fn nlerp(&self, dest: Quaternion, factor: f32) -> Self {
+        // q and -q are the same rotation, going to the closer one takes the short way around
+        let dest = if self.dot_quaternion(dest) < 0.0 {
+            -dest
+        } else {
+            dest
+        };
+
+        let q = Self::new(
+            self.x + (dest.x - self.x) * factor,
+            self.y + (dest.y - self.y) * factor,
+            self.z + (dest.z - self.z) * factor,
+            self.w + (dest.w - self.w) * factor,
+        );
+
+        if q.length() <= 0.0 {
+            return *self;
+        }
+        return q.normalized();
+    }
+
+    // spherical lerp, turns at a constant speed along the shortest path
+    //
+    //      self
+    //        \  angle * factor
+    //         \____
+    //          '----'-- dest
+    pub fn slerp(&self, dest: Quaternion, factor: f32) -> Self {
+        let mut cos_angle = self.dot_quaternion(dest);
+        let mut dest = dest;
+        if cos_angle < 0.0 {
+            cos_angle = -cos_angle;
+            dest = -dest;
+        }
+
+        // the rotations are (almost) the same, sin(angle) is too close to zero to divide by
+        if cos_angle > 0.9995 {
+            return self.nlerp(dest, factor);
+        }
+
+        let angle = cos_angle.acos();
+        let sin_angle = angle.sin();
+        let a = ((1.0 - factor) * angle).sin() / sin_angle;
+        let b = (factor * angle).sin() / sin_angle;
+
+        return Self::new(
+            self.x * a + dest.x * b,
+            self.y * a + dest.y * b,
+            self.z * a + dest.z * b,
+            self.w * a + dest.w * b,
+        );
+    }
+
+    // the rotation matrix of a unit quaternion
+    pub fn to_matrix(&self) -> Matrix4 {
+        let (x, y, z, w) = (self.x, self.y, self.z, self.w);
+
+        let mut m = Matrix4::new_identity();
+        m[0][0] = 1.0 - 2.0 * (y * y + z * z);
+        m[0][1] = 2.0 * (x * y + z * w);
+        m[0][2] = 2.0 * (x * z - y * w);
+        m[1][0] = 2.0 * (x * y - z * w);
+        m[1][1] = 1.0 - 2.0 * (x * x + z * z);
+        m[1][2] = 2.0 * (y * z + x * w);
+        m[2][0] = 2.0 * (x * z + y * w);
+        m[2][1] = 2.0 * (y * z - x * w);
+        m[2][2] = 1.0 - 2.0 * (x * x + y * y);
+        return m;
+    }
+
+    // the rotation of a matrix, its scale and translation are ignored
+    pub fn from_matrix(matrix: &Matrix4) -> Self {
+        // r(row, col) of the rotation, every basis vector is normalized to take the scale out
+        let scale: Vec<f32> = (0..3)
+            .map(|col| Vector4::new(matrix[col][0], matrix[col][1], matrix[col][2], 0.0).length())
+            .map(|length| if length > 0.0 { length } else { 1.0 })
+            .collect();
+        let r = |row: usize, col: usize| matrix[col][row] / scale[col];
+
+        // the largest of w, x, y and z is found first, dividing by it keeps the others accurate
+        let trace = r(0, 0) + r(1, 1) + r(2, 2);
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self::new(
+                (r(2, 1) - r(1, 2)) / s,
+                (r(0, 2) - r(2, 0)) / s,
+                (r(1, 0) - r(0, 1)) / s,
+                s / 4.0,
+            )
+        } else if r(0, 0) > r(1, 1) && r(0, 0) > r(2, 2) {
+            let s = (1.0 + r(0, 0) - r(1, 1) - r(2, 2)).sqrt() * 2.0;
+            Self::new(
+                s / 4.0,
+                (r(0, 1) + r(1, 0)) / s,
+                (r(0, 2) + r(2, 0)) / s,
+                (r(2, 1) - r(1, 2)) / s,
+            )
+        } else if r(1, 1) > r(2, 2) {
+            let s = (1.0 + r(1, 1) - r(0, 0) - r(2, 2)).sqrt() * 2.0;
+            Self::new(
+                (r(0, 1) + r(1, 0)) / s,
+                s / 4.0,
+                (r(1, 2) + r(2, 1)) / s,
+                (r(0, 2) - r(2, 0)) / s,
+            )
+        } else {
+            let s = (1.0 + r(2, 2) - r(0, 0) - r(1, 1)).sqrt() * 2.0;
+            Self::new(
+                (r(0, 2) + r(2, 0)) / s,
+                (r(1, 2) + r(2, 1)) / s,
+                s / 4.0,
+                (r(1, 0) - r(0, 1)) / s,
+            )
+        };
+
+        return q.normalized();
+    }
+
+    // x: pitch, y: yaw, z: roll in degrees
+    pub fn from_euler(euler: Vector4) -> Self {
+        let radians = PI / 180.0;
+        let pitch = Self::from_angle(euler.x * radians, Vector4::RIGHT);
+        let yaw = Self::from_angle(euler.y * radians, Vector4::UP);
+        let roll = Self::from_angle(euler.z * radians, Vector4::FORWARD);
+        return yaw * pitch * roll;
+    }
+
+    // x: pitch (-90 to 90), y: yaw and z: roll (-180 to 180) in degrees
+    //
+    // looking straight up or down (gimbal lock) yaw and roll turn around the same axis,
+    // then the roll is 0 and the yaw has all of the turn
+    pub fn to_euler(&self) -> Vector4 {
+        let m = self.to_matrix();
+        let degrees = 180.0 / PI;
+
+        // r(1, 2) = -sin(pitch), r(1, 0) and r(1, 1) are cos(pitch) * sin(roll) and cos(pitch) * cos(roll)
+        // atan2 stays accurate close to 90 degrees where asin doesn't
+        let sin_pitch = -m[2][1];
+        let cos_pitch = (m[0][1] * m[0][1] + m[1][1] * m[1][1]).sqrt();
+        let pitch = sin_pitch.atan2(cos_pitch);
+
+        let (yaw, roll) = if cos_pitch > 0.001 {
+            (m[2][0].atan2(m[2][2]), m[0][1].atan2(m[1][1]))
+        } else {
+            ((-m[0][2]).atan2(m[0][0]), 0.0)
+        };
+
+        return Vector4::new(pitch * degrees, yaw * degrees, roll * degrees, 0.0);
+    }
+
+    fn dot_quaternion(&self, q: Quaternion) -> f32 {
+        return self.x * q.x + self.y * q.y + self.z * q.z + self.w * q.w;
+    }
+}
+
+impl std::ops::Neg for Quaternion {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        return Self::new(-self.x, -self.y, -self.z, -self.w);
+    }
 }
 
 impl std::ops::Add for Quaternion {
@@ -113,3 +307,144 @@ impl std::ops::Mul<&Vector4> for Quaternion {
         return Self::new(xx, yy, zz, ww);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn degrees(angle: f32) -> f32 {
+        angle * PI / 180.0
+    }
+
+    // q and -q are the same rotation
+    fn assert_same_rotation(a: Quaternion, b: Quaternion) {
+        assert!(
+            a.dot_quaternion(b).abs() > 0.9999,
+            "{a:?} isn't the same rotation as {b:?}"
+        );
+    }
+
+    fn assert_same_matrix(a: &Matrix4, b: &Matrix4) {
+        for col in 0..4 {
+            for row in 0..4 {
+                assert!((a[col][row] - b[col][row]).abs() < 0.0001, "{a:?} != {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_quaternion_to_matrix() {
+        assert_same_matrix(&Quaternion::IDENTITY.to_matrix(), &Matrix4::new_identity());
+
+        for angle in [30.0, 90.0, 180.0, -135.0] {
+            let mut expected = Matrix4::new_identity();
+            expected.rotate_y(angle);
+            let q = Quaternion::from_angle(degrees(angle), Vector4::UP);
+            assert_same_matrix(&q.to_matrix(), &expected);
+
+            let mut expected = Matrix4::new_identity();
+            expected.rotate_x(angle);
+            let q = Quaternion::from_angle(degrees(angle), Vector4::RIGHT);
+            assert_same_matrix(&q.to_matrix(), &expected);
+        }
+
+        // the matrix turns vectors the same way the quaternion does
+        let axis = Vector4::new(1.0, 2.0, 3.0, 0.0).normalized();
+        let q = Quaternion::from_angle(degrees(70.0), axis);
+        let v = Vector4::new(0.5, -1.0, 2.0, 0.0);
+        let turned = Matrix4::multiply_vector(&q.to_matrix(), v);
+        let mut expected = v.rotate_quaternion(q);
+        expected.w = 0.0;
+        assert!((turned - expected).length() < 0.0001);
+    }
+
+    #[test]
+    fn test_quaternion_from_matrix() {
+        // every branch: a small turn (positive trace) and half turns around x, y and z
+        let axes = [
+            Vector4::new(1.0, 2.0, 3.0, 0.0).normalized(),
+            Vector4::RIGHT,
+            Vector4::UP,
+            Vector4::FORWARD,
+        ];
+        for (axis, angle) in axes.into_iter().zip([40.0, 180.0, 180.0, 180.0]) {
+            let q = Quaternion::from_angle(degrees(angle), axis);
+            assert_same_rotation(Quaternion::from_matrix(&q.to_matrix()), q);
+        }
+
+        // scale and translation don't change the rotation
+        let mut m = Matrix4::new_identity();
+        m.translate(1.0, 2.0, 3.0);
+        m.rotate_z(60.0);
+        m.scale(2.0, 3.0, 4.0);
+        let q = Quaternion::from_angle(degrees(60.0), Vector4::FORWARD);
+        assert_same_rotation(Quaternion::from_matrix(&m), q);
+    }
+
+    #[test]
+    fn test_quaternion_inverse() {
+        let q = Quaternion::from_angle(degrees(50.0), Vector4::UP);
+        assert_same_rotation(q * q.inverse(), Quaternion::IDENTITY);
+
+        // not unit length
+        let q = Quaternion::new(1.0, 2.0, 3.0, 4.0);
+        let identity = q * q.inverse();
+        assert!((identity.w - 1.0).abs() < 0.0001);
+        assert!(Vector4::new(identity.x, identity.y, identity.z, 0.0).length() < 0.0001);
+    }
+
+    #[test]
+    fn test_quaternion_slerp() {
+        let from = Quaternion::IDENTITY;
+        let to = Quaternion::from_angle(degrees(90.0), Vector4::UP);
+
+        assert_same_rotation(from.slerp(to, 0.0), from);
+        assert_same_rotation(from.slerp(to, 1.0), to);
+
+        // constant speed: a quarter of the way is a quarter of the angle
+        let expected = Quaternion::from_angle(degrees(22.5), Vector4::UP);
+        assert_same_rotation(from.slerp(to, 0.25), expected);
+
+        // -to is the same rotation, it still takes the short way (and doesn't turn 270 degrees)
+        assert_same_rotation(from.slerp(-to, 0.25), expected);
+        assert_same_rotation(from.nlerp(-to, 0.5), from.slerp(to, 0.5));
+
+        // almost the same rotations don't divide by zero
+        let close = Quaternion::from_angle(0.0001, Vector4::UP);
+        let q = from.slerp(close, 0.5);
+        assert!(q.x.is_finite() && (q.length() - 1.0).abs() < 0.0001);
+
+        // opposite turns around the same axis meet in the middle
+        let left = Quaternion::from_angle(degrees(-170.0), Vector4::UP);
+        let right = Quaternion::from_angle(degrees(170.0), Vector4::UP);
+        let middle = Quaternion::from_angle(degrees(180.0), Vector4::UP);
+        assert_same_rotation(left.slerp(right, 0.5), middle);
+    }
+
+    #[test]
+    fn test_quaternion_euler() {
+        for euler in [
+            Vector4::new(0.0, 0.0, 0.0, 0.0),
+            Vector4::new(30.0, 0.0, 0.0, 0.0),
+            Vector4::new(0.0, 120.0, 0.0, 0.0),
+            Vector4::new(0.0, 0.0, -45.0, 0.0),
+            Vector4::new(-60.0, 170.0, 20.0, 0.0),
+        ] {
+            let q = Quaternion::from_euler(euler);
+            assert!((q.to_euler() - euler).length() < 0.01, "{euler:?}");
+        }
+
+        // yaw, pitch and roll turn around up, right and forward
+        let q = Quaternion::from_euler(Vector4::new(0.0, 90.0, 0.0, 0.0));
+        assert_same_rotation(q, Quaternion::from_angle(degrees(90.0), Vector4::UP));
+
+        // looking straight up or down yaw and roll turn around the same axis, the roll goes into the yaw
+        for pitch in [90.0, -90.0] {
+            let q = Quaternion::from_euler(Vector4::new(pitch, 30.0, 20.0, 0.0));
+            let euler = q.to_euler();
+            assert!((euler.x - pitch).abs() < 0.01);
+            assert_eq!(euler.z, 0.0);
+            assert_same_rotation(Quaternion::from_euler(euler), q);
+        }
+    }
+}
@@ -2,8 +2,10 @@
 pub use linear_algebra::matrix::Matrix4;
 pub use linear_algebra::quaternion::Quaternion;
 pub use linear_algebra::vector::Vector4;
+pub use transform::Transform;
 
 pub mod linear_algebra;
+pub mod transform;
 
 pub const PI: f32 = 3.14159265;
 
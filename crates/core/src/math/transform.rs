@@ -0,0 +1,289 @@
+use std::cell::OnceCell;
+
+use super::{Matrix4, Quaternion, Vector4};
+
+// where an object is, how it's turned and how big it is
+//
+// matrix = translation * rotation * scale      (scaled first, then turned and then moved)
+//
+// the parts are kept apart so they can be read back and changed without drifting (turning a matrix a little
+// every frame slowly skews it), the matrix is only made again after one of them changed
+//
+// the transform looks down its -z axis (like cameras and lights), +y is up and +x is right
+#[derive(Debug, Clone)]
+pub struct Transform {
+    translation: Vector4,
+    rotation: Quaternion,
+    scale: Vector4,
+    matrix: OnceCell<Matrix4>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new(
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+            Quaternion::IDENTITY,
+            Vector4::new(1.0, 1.0, 1.0, 0.0),
+        )
+    }
+}
+
+impl Transform {
+    pub fn new(translation: Vector4, rotation: Quaternion, scale: Vector4) -> Self {
+        Self {
+            translation: Vector4::new(translation.x, translation.y, translation.z, 1.0),
+            rotation,
+            scale: Vector4::new(scale.x, scale.y, scale.z, 0.0),
+            matrix: OnceCell::new(),
+        }
+    }
+
+    pub fn from_translation(translation: Vector4) -> Self {
+        let mut transform = Self::default();
+        transform.set_translation(translation);
+        transform
+    }
+
+    // the parts of a matrix that was made from a translation, rotation and scale (a skewed one loses its skew)
+    pub fn from_matrix(matrix: &Matrix4) -> Self {
+        let column = |col: usize| Vector4::new(matrix[col][0], matrix[col][1], matrix[col][2], 0.0);
+
+        Self::new(
+            column(3),
+            Quaternion::from_matrix(matrix),
+            Vector4::new(
+                column(0).length(),
+                column(1).length(),
+                column(2).length(),
+                0.0,
+            ),
+        )
+    }
+
+    pub fn translation(&self) -> Vector4 {
+        self.translation
+    }
+
+    pub fn rotation(&self) -> Quaternion {
+        self.rotation
+    }
+
+    pub fn scale(&self) -> Vector4 {
+        self.scale
+    }
+
+    pub fn set_translation(&mut self, translation: Vector4) {
+        self.translation = Vector4::new(translation.x, translation.y, translation.z, 1.0);
+        self.matrix.take();
+    }
+
+    pub fn set_rotation(&mut self, rotation: Quaternion) {
+        self.rotation = rotation;
+        self.matrix.take();
+    }
+
+    pub fn set_scale(&mut self, scale: Vector4) {
+        self.scale = Vector4::new(scale.x, scale.y, scale.z, 0.0);
+        self.matrix.take();
+    }
+
+    // moves along the axes of the parent (or the world), not the turned ones
+    pub fn translate(&mut self, offset: Vector4) {
+        self.set_translation(self.translation + Vector4::new(offset.x, offset.y, offset.z, 0.0));
+    }
+
+    // turns around the axes of the parent (or the world)
+    pub fn rotate(&mut self, rotation: Quaternion) {
+        self.set_rotation((rotation * self.rotation).normalized());
+    }
+
+    // turns the -z axis towards `target`, the y axis gets as close to `up` as it can
+    pub fn look_at(&mut self, target: Vector4, up: Vector4) {
+        let to_target = target - self.translation;
+        let to_target = Vector4::new(to_target.x, to_target.y, to_target.z, 0.0);
+        if to_target.length() <= 0.0 {
+            return;
+        }
+
+        let back = -to_target.normalized();
+
+        // looking straight along `up` any other axis will do
+        let mut right = up.cross(back);
+        if right.length() < 0.0001 {
+            right = Vector4::FORWARD.cross(back);
+        }
+        if right.length() < 0.0001 {
+            right = Vector4::UP.cross(back);
+        }
+        let right = right.normalized();
+        let up = back.cross(right);
+
+        let mut basis = Matrix4::new_identity();
+        for (col, axis) in [right, up, back].into_iter().enumerate() {
+            basis[col][0] = axis.x;
+            basis[col][1] = axis.y;
+            basis[col][2] = axis.z;
+        }
+
+        self.set_rotation(Quaternion::from_matrix(&basis));
+    }
+
+    pub fn forward(&self) -> Vector4 {
+        self.rotate_vector(Vector4::new(0.0, 0.0, -1.0, 0.0))
+    }
+
+    pub fn right(&self) -> Vector4 {
+        self.rotate_vector(Vector4::RIGHT)
+    }
+
+    pub fn up(&self) -> Vector4 {
+        self.rotate_vector(Vector4::UP)
+    }
+
+    // translation * rotation * scale, made the first time it's needed after a change
+    pub fn matrix(&self) -> &Matrix4 {
+        self.matrix.get_or_init(|| {
+            let rotation = self.rotation.to_matrix();
+            let (t, s) = (self.translation, self.scale);
+
+            let mut m = Matrix4::new_identity();
+            for (col, scale) in [s.x, s.y, s.z].into_iter().enumerate() {
+                for row in 0..3 {
+                    m[col][row] = rotation[col][row] * scale;
+                }
+            }
+            m[3][0] = t.x;
+            m[3][1] = t.y;
+            m[3][2] = t.z;
+            m
+        })
+    }
+
+    // `child` is in the space of `self`, the result is in the space of the parent of `self`
+    //
+    // like multiplying the matrices, but only exact when the scale of `self` is the same on every axis
+    // (a turned child of a stretched parent would need a skew that a transform doesn't have)
+    pub fn combine(&self, child: &Transform) -> Transform {
+        let scaled = Vector4::new(
+            child.translation.x * self.scale.x,
+            child.translation.y * self.scale.y,
+            child.translation.z * self.scale.z,
+            0.0,
+        );
+
+        Self::new(
+            self.translation + self.rotate_vector(scaled),
+            (self.rotation * child.rotation).normalized(),
+            Vector4::new(
+                self.scale.x * child.scale.x,
+                self.scale.y * child.scale.y,
+                self.scale.z * child.scale.z,
+                0.0,
+            ),
+        )
+    }
+
+    // undoes the transform, exact when the scale is the same on every axis (like `combine`)
+    pub fn inverse(&self) -> Transform {
+        let rotation = self.rotation.inverse();
+        let scale = Vector4::new(
+            1.0 / self.scale.x,
+            1.0 / self.scale.y,
+            1.0 / self.scale.z,
+            0.0,
+        );
+
+        let t = Self::rotate_by(rotation, -self.translation);
+        let translation = Vector4::new(t.x * scale.x, t.y * scale.y, t.z * scale.z, 1.0);
+
+        Self::new(translation, rotation, scale)
+    }
+
+    fn rotate_vector(&self, v: Vector4) -> Vector4 {
+        Self::rotate_by(self.rotation, v)
+    }
+
+    // `Vector4::rotate_quaternion` returns a point (w: 1), this keeps the w of `v`
+    fn rotate_by(rotation: Quaternion, v: Vector4) -> Vector4 {
+        let turned = v.rotate_quaternion(rotation);
+        Vector4::new(turned.x, turned.y, turned.z, v.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same_matrix(a: &Matrix4, b: &Matrix4) {
+        for col in 0..4 {
+            for row in 0..4 {
+                assert!((a[col][row] - b[col][row]).abs() < 0.0001, "{a:?} != {b:?}");
+            }
+        }
+    }
+
+    fn assert_near(a: Vector4, b: Vector4) {
+        assert!((a - b).length() < 0.0001, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn test_transform() {
+        let mut transform = Transform::from_translation(Vector4::new(1.0, 2.0, 3.0, 0.0));
+        transform.set_rotation(Quaternion::from_euler(Vector4::new(0.0, 90.0, 0.0, 0.0)));
+        transform.set_scale(Vector4::new(2.0, 2.0, 2.0, 0.0));
+
+        // the same as moving, turning and scaling a matrix
+        let mut expected = Matrix4::new_identity();
+        expected.translate(1.0, 2.0, 3.0);
+        expected.rotate_y(90.0);
+        expected.scale(2.0, 2.0, 2.0);
+        assert_same_matrix(transform.matrix(), &expected);
+
+        // the matrix is made again after a change
+        transform.translate(Vector4::new(1.0, 0.0, 0.0, 0.0));
+        expected = Matrix4::multiply(
+            &{
+                let mut m = Matrix4::new_identity();
+                m.translate(1.0, 0.0, 0.0);
+                m
+            },
+            &expected,
+        );
+        assert_same_matrix(transform.matrix(), &expected);
+        assert_eq!(transform.translation(), Vector4::new(2.0, 2.0, 3.0, 1.0));
+
+        // turned 90 degrees around y: -z (forward) turns to -x
+        assert_near(transform.forward(), Vector4::new(-1.0, 0.0, 0.0, 0.0));
+        assert_near(transform.right(), Vector4::new(0.0, 0.0, -1.0, 0.0));
+
+        let child = Transform::new(
+            Vector4::new(0.0, 1.0, -1.0, 1.0),
+            Quaternion::from_euler(Vector4::new(30.0, 0.0, 0.0, 0.0)),
+            Vector4::new(1.0, 3.0, 1.0, 0.0),
+        );
+        let combined = transform.combine(&child);
+        assert_same_matrix(
+            combined.matrix(),
+            &Matrix4::multiply(transform.matrix(), child.matrix()),
+        );
+
+        let identity = transform.combine(&transform.inverse());
+        assert_same_matrix(identity.matrix(), &Matrix4::new_identity());
+
+        let decomposed = Transform::from_matrix(combined.matrix());
+        assert_same_matrix(decomposed.matrix(), combined.matrix());
+
+        // looking at a point
+        let mut eye = Transform::from_translation(Vector4::new(0.0, 0.0, 5.0, 1.0));
+        eye.look_at(Vector4::new(5.0, 0.0, 0.0, 1.0), Vector4::UP);
+        assert_near(
+            eye.forward(),
+            Vector4::new(1.0, 0.0, -1.0, 0.0).normalized(),
+        );
+        assert_near(eye.up(), Vector4::UP);
+
+        // straight down, up can't be kept
+        eye.look_at(Vector4::new(0.0, -3.0, 5.0, 1.0), Vector4::UP);
+        assert_near(eye.forward(), Vector4::new(0.0, -1.0, 0.0, 0.0));
+    }
+}
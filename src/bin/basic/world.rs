@@ -1,26 +1,45 @@
+use std::error::Error;
+use std::path::Path;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use core::app::camera::Camera;
+use core::app::cascades::ShadowCascades;
+use core::app::cube_shadows::CubeShadows;
 use core::app::instance::Instance;
+use core::app::mesh_cache::{self, load_mesh_cache};
 use core::app::mesh_loader::load_mesh;
+use core::app::morph::{MorphAnimation, Playback};
 use core::app::renderer::Renderer;
-use core::graphics::light::Light;
+use core::app::scene::Scene;
+use core::graphics::light::{Light, ShadowFilter};
+use core::graphics::material::Material;
 use core::graphics::mesh::Mesh;
+use core::graphics::sampler::{Sampler, TextureFilter, Wrap};
+use core::graphics::texture::Texture;
 use core::graphics::vertex::Vertex;
 use core::graphics::{bitmap::Bitmap, color::Color};
 use core::math::lerp;
-use core::math::{Matrix4, Vector4};
+use core::math::{Matrix4, Quaternion, Transform, Vector4};
 use image::EncodableLayout;
 use rand::Rng;
 
+// vertical field of view and the near plane of the camera
+const FOV: f32 = 100.0;
+const Z_NEAR: f32 = 0.1;
+
 pub struct World {
     width: u32,
     height: u32,
     renderer: Renderer,
-    shadow_renderer: Renderer,
+    shadows: ShadowCascades,
+    cube_shadows: CubeShadows,
     camera: Camera,
     projection: Matrix4,
-    instances: Vec<Instance>,
+    scene: Scene, // the first light is the sun, it's the only one with a shadow-map
+    animations: Vec<(usize, MorphAnimation)>, // the node of the instance that's animated
+    spinners: Vec<(usize, Vector4)>, // nodes that keep turning, degrees per second around x, y and z
+    moved: Vec<usize>, // instances that moved since the last draw, their cube shadows are drawn again
     time: f32,
 }
 
@@ -33,16 +52,28 @@ impl World {
             width,
             height,
             renderer: Renderer::new(width, height),
-            shadow_renderer: Renderer::new(512, 512),
+            shadows: ShadowCascades::new(512, 3, 40.0),
+            cube_shadows: CubeShadows::new(128),
             camera: Camera::new(
                 Vector4::new(0.0, 2.0, 2.0, 1.0),
                 Vector4::new(0.0, 0.0, -1.0, 0.0),
             ),
-            projection: Matrix4::perspective(100.0, aspect_ratio, 0.1, 100.0),
-            instances: Vec::new(),
+            projection: Matrix4::perspective(FOV, aspect_ratio, Z_NEAR, 100.0),
+            scene: Scene::new(),
+            animations: Vec::new(),
+            spinners: Vec::new(),
+            moved: Vec::new(),
             time: 0.0,
         };
 
+        // the sun, soft shadows on the ground without acne
+        let mut sun = Light::directional(Vector4::new(-0.4, -0.6, -0.3, 0.0));
+        sun.shadow_filter = ShadowFilter::Poisson { radius: 1.5 };
+        sun.depth_bias = 0.002;
+        sun.slope_bias = 0.004;
+        sun.normal_offset = 0.05;
+        world.scene.lights.push(sun);
+
         // create a checker-board bitmap
         let mut bitmap = Bitmap::new(64, 64);
         for x in 0..bitmap.width {
@@ -55,32 +86,39 @@ impl World {
             }
         }
 
-        let bitmap_resource = Rc::new(Box::new(bitmap));
+        let ground_material = Self::make_material_res(&Arc::new(Texture::new(bitmap)), false);
 
-        let mario_mesh = load_mesh("./assets/mario.obj");
-        let mario_mesh_resource = Rc::new(Box::new(mario_mesh));
+        let mario_mesh_resource = Self::make_mesh_res("./assets/mario.obj")
+            .unwrap_or_else(|err| panic!("can't load mario: {err}"));
 
         let mario_image = image::open("./assets/mario.png").unwrap();
         let mut mario_bitmap = Bitmap::new(mario_image.width(), mario_image.height());
         mario_bitmap.pixels = mario_image.as_bytes().into();
 
-        let mario_bitmap_resource = Rc::new(Box::new(mario_bitmap));
+        let mario_bitmap_resource = Arc::new(Texture::new(mario_bitmap));
 
         let mario = Instance::new(
             Rc::clone(&mario_mesh_resource),
-            Rc::clone(&mario_bitmap_resource),
-            true,
+            Self::make_material_res(&mario_bitmap_resource, true),
         );
 
-        world.instances.push(mario);
+        let mario = world
+            .scene
+            .add_instance("mario", None, Transform::default(), mario);
+        world
+            .spinners
+            .push((mario, Vector4::new(0.0, 360.0 / 4.0, 0.0, 0.0)));
 
-        let box_mesh_res = Self::make_mesh_res("./assets/box.obj");
+        let box_mesh_res = Self::make_mesh_res("./assets/box.obj")
+            .unwrap_or_else(|err| panic!("can't load the box: {err}"));
 
         // ground
-        let mut instance = world.make_instance(&box_mesh_res, &bitmap_resource, false);
-        instance.transform.translate(0.0, -0.5, 0.0);
-        instance.transform.scale(40.0, 0.5, 40.0);
-        world.instances.push(instance);
+        let instance = world.make_instance(&box_mesh_res, &ground_material);
+        let mut transform = Transform::from_translation(Vector4::new(0.0, -0.5, 0.0, 1.0));
+        transform.set_scale(Vector4::new(40.0, 0.5, 40.0, 0.0));
+        world
+            .scene
+            .add_instance("ground", None, transform, instance);
 
         // simple box
         // create a checker-board bitmap
@@ -94,7 +132,11 @@ impl World {
                 bitmap.set_pixel(x, y, &color);
             }
         }
-        let bitmap_resource = Rc::new(Box::new(bitmap));
+        // the triangle can be seen from both sides
+        let mut triangle_material = Material::new(Arc::new(Texture::new(bitmap)));
+        triangle_material.double_sided = true;
+        let triangle_material = Rc::new(triangle_material);
+
         let triangle_mesh = Mesh::new(
             vec![
                 Vertex::new(
@@ -116,9 +158,11 @@ impl World {
             vec![0, 1, 2],
         );
         let triangle_resource = Rc::new(Box::new(triangle_mesh));
-        let mut instance = world.make_instance(&triangle_resource, &bitmap_resource, true);
-        instance.transform.translate(0.0, 1.0, 5.0);
-        world.instances.push(instance);
+        let instance = world.make_instance(&triangle_resource, &triangle_material);
+        let transform = Transform::from_translation(Vector4::new(0.0, 1.0, 5.0, 1.0));
+        world
+            .scene
+            .add_instance("triangle", None, transform, instance);
 
         // spawn some models
         world.spawn_instance(
@@ -139,7 +183,7 @@ impl World {
             true,
         );
 
-        world.spawn_instance(
+        let house = world.spawn_instance(
             "./assets/house.obj",
             "./assets/house.png",
             Vector4::new(-10.0, 0.0, -10.0, 0.0),
@@ -148,15 +192,67 @@ impl World {
             true,
         );
 
-        world.spawn_instance(
+        // a warm lantern by the door of the house, it casts shadows in every direction
+        if let Some(house) = house {
+            let mut lantern =
+                Light::point(Vector4::ZERO, 8.0).with_color(Vector4::new(1.0, 0.8, 0.5, 0.0), 1.5);
+            lantern.shadow_filter = ShadowFilter::Bilinear;
+            lantern.depth_bias = 0.05; // world units for cube shadow-maps
+
+            let transform = Transform::from_translation(Vector4::new(1.1, 2.5, 4.1, 1.0));
+            world
+                .scene
+                .add_light("lantern", Some(house), transform, lantern);
+        }
+
+        // the plane flies in circles around a pivot that turns, its nose (+z) points where it's going
+        let pivot = world.scene.add_node(
+            "flight",
+            None,
+            Transform::from_translation(Vector4::new(10.0, 8.0, -10.0, 1.0)),
+        );
+        world
+            .spinners
+            .push((pivot, Vector4::new(0.0, 20.0, 0.0, 0.0)));
+
+        let plane = world.spawn_instance(
             "./assets/plane.obj",
             "./assets/plane.png",
-            Vector4::new(10.0, 6.0, -10.0, 0.0),
-            -130.0,
+            Vector4::new(14.0, 0.0, 0.0, 0.0),
+            180.0,
             1.0,
             true,
         );
 
+        if let Some(plane) = plane {
+            world.scene.set_parent(plane, Some(pivot));
+
+            // leaning into the turn
+            world
+                .scene
+                .transform_mut(plane)
+                .set_rotation(Quaternion::from_euler(Vector4::new(0.0, 180.0, -15.0, 0.0)));
+
+            // spinning blades in front of the propellers on the wings
+            let mut bitmap = Bitmap::new(1, 1);
+            bitmap.set_pixel(0, 0, &Color::from_hex(0x333333FF));
+            let blade_material = Self::make_material_res(&Arc::new(Texture::new(bitmap)), true);
+
+            for x in [-3.2, 3.2] {
+                let instance = world.make_instance(&box_mesh_res, &blade_material);
+                let mut transform = Transform::from_translation(Vector4::new(x, 0.9, 6.1, 1.0));
+                transform.set_scale(Vector4::new(0.12, 1.6, 0.04, 0.0));
+
+                let propeller =
+                    world
+                        .scene
+                        .add_instance("propeller", Some(plane), transform, instance);
+                world
+                    .spinners
+                    .push((propeller, Vector4::new(0.0, 0.0, 720.0, 0.0)));
+            }
+        }
+
         world.spawn_instance(
             "./assets/ship.obj",
             "./assets/pirates.png",
@@ -166,6 +262,38 @@ impl World {
             true,
         );
 
+        // a swimming turtle, every frame is a separate obj
+        let turtle_frames: Vec<String> = (1..=8)
+            .map(|i| format!("./assets/animation/turtle{i}.obj"))
+            .collect();
+        let turtle_frames: Vec<&str> = turtle_frames.iter().map(|path| path.as_str()).collect();
+        match MorphAnimation::load(&turtle_frames, 0.1, Playback::PingPong) {
+            Ok(animation) => {
+                let material =
+                    Self::make_material_res(&Self::make_bitmap_res("./assets/turtle.png"), true);
+                let instance = Instance::new(Rc::new(Box::new(animation.mesh())), material);
+                let transform = Transform::from_translation(Vector4::new(3.0, 1.5, -4.0, 1.0));
+                let turtle = world
+                    .scene
+                    .add_instance("turtle", None, transform, instance);
+                world.animations.push((turtle, animation));
+            }
+            Err(err) => log::error!("can't load the turtle: {err}"),
+        }
+
+        // torches on the pirate ship pointing down at the deck
+        for x in [-12.0, -8.0] {
+            let torch = Light::spot(
+                Vector4::new(x, 5.0, 10.0, 1.0),
+                Vector4::new(0.0, -1.0, 0.0, 0.0),
+                10.0,
+                25.0,
+                40.0,
+            )
+            .with_color(Vector4::new(1.0, 0.5, 0.2, 0.0), 2.0);
+            world.scene.lights.push(torch);
+        }
+
         // create a sky bitmap
         let mut bitmap = Bitmap::new(1, 128);
         for y in 0..bitmap.height {
@@ -173,11 +301,23 @@ impl World {
             bitmap.set_pixel(0, y, &Color::newf(l * 0.1, l * 0.7, l, 1.0));
         }
 
-        let bitmap_resource = Rc::new(Box::new(bitmap));
+        let mut sky_material = Material::new(Arc::new(Texture::new(bitmap)));
+        sky_material.unlit = true;
+        // the gradient must not bleed from the bottom into the top
+        sky_material.sampler = Sampler::new(TextureFilter::Bilinear, Wrap::ClampToEdge);
+
+        // the scene still works without a sky
+        match Self::make_mesh_res("./assets/skydome.obj") {
+            Ok(sky) => {
+                let instance = world.make_instance(&sky, &Rc::new(sky_material));
+                world
+                    .scene
+                    .add_instance("sky", None, Transform::default(), instance);
+            }
+            Err(err) => log::error!("can't load the sky: {err}"),
+        }
 
-        let sky = Self::make_mesh_res("./assets/skydome.obj");
-        let instance = world.make_instance(&sky, &bitmap_resource, false);
-        world.instances.push(instance);
+        world.scene.update();
 
         return world;
     }
@@ -190,24 +330,35 @@ impl World {
         // # example: set the ground bitmap to use the same pixels as what the renderer sees
         // let mut render_bitmap = Box::new(Bitmap::new(self.width, self.height));
         // render_bitmap.pixels = self.renderer.color_buffer.pixels.clone();
-        // self.instances[1].bitmap = Rc::new(render_bitmap);
+        // Rc::get_mut(&mut self.scene.instances[1].material).unwrap().diffuse_map =
+        //     Some(Arc::new(Texture::new(*render_bitmap)));
 
         // # example: motion
-        // for instance in self.instances.iter_mut() {
-        //     instance
-        //         .transform
-        //         .translate(self.time.cos() * 0.01, 0.0, self.time.sin() * 0.01);
-
-        //     instance.transform.rotate_y(360.0 / 8.0 * dt);
+        // for node in 0..self.scene.nodes().len() {
+        //     self.scene.transform_mut(node).translate(Vector4::new(
+        //         self.time.cos() * 0.01,
+        //         0.0,
+        //         self.time.sin() * 0.01,
+        //         0.0,
+        //     ));
         // }
 
-        for instance in self.instances.iter_mut().take(1) {
-            // instance
-            //     .transform
-            //     .translate(self.time.cos() * 0.01, 0.0, self.time.sin() * 0.01);
+        for (node, speed) in self.spinners.iter() {
+            let turn = Quaternion::from_euler(*speed * dt);
+            self.scene.transform_mut(*node).rotate(turn);
+        }
 
-            instance.transform.rotate_y(360.0 / 4.0 * dt);
+        for (node, animation) in self.animations.iter_mut() {
+            animation.update(dt);
+            if let Some(instance) = self.scene.instance_mut(*node) {
+                animation.apply(instance);
+            }
+            self.moved.extend(self.scene.node(*node).instance);
         }
+
+        // the nodes that moved take their children (and the instances and lights on them) along
+        self.scene.update();
+        self.moved.extend_from_slice(self.scene.moved());
     }
 
     pub fn draw(&mut self, frame: &mut [u8], dt: f32) {
@@ -216,64 +367,37 @@ impl World {
         // self.renderer.color_buffer.fill(&Color::newf(0.1, 0.1, 0.1, 1.0));
         self.renderer.clear_depth_buffer();
 
-        // # shadow mapping experiment
-        // let shadow_projection = Matrix4::perspective(100.0, self.width as f32 / self.height as f32, 0.1, 100.0);
-        let range = 10.0;
+        // shadow-maps: draw all instances into every cascade of the sun
         let aspect = self.width as f32 / self.height as f32;
-        let shadow_projection =
-            Matrix4::orthographic(-range * aspect, range * aspect, -range, range, 25.0, -5.0);
-        // let shadow_projection = Matrix4::perspective(130.0, self.width as f32 / self.height as f32, 0.1, 100.0);
-
-        let mut shadow_light_transform = Matrix4::new_identity();
-        // #[rustfmt::skip]
-        // shadow_light_transform.look_at(self.camera.position, self.camera.position + self.camera.direction, Vector4::UP);
-        let follow = Vector4::new(self.camera.position.x, 0.0, self.camera.position.z, 0.0);
-        shadow_light_transform.look_at(
-            follow,
-            follow + Vector4::new(-0.4, -0.6, -0.3, 0.0).normalized(),
-            Vector4::UP,
+        self.shadows.draw(
+            &self.scene.instances,
+            &mut self.scene.lights[0],
+            &self.camera,
+            FOV,
+            aspect,
+            Z_NEAR,
         );
-        // shadow_light_transform.look_at(Vector4::new(1.58, 4.52, 2.7, 0.0), Vector4::new(-0.5, -0.8, -0.6, 0.0), Vector4::UP);
 
-        // dbg!(self.camera.position);
-        // dbg!(self.camera.direction);
-
-        let shadow_view_projection = Matrix4::multiply(&shadow_projection, &shadow_light_transform);
-
-        // shadow-map: draw all instances
-        self.shadow_renderer.clear_depth_buffer();
-        for instance in self.instances.iter() {
-            instance.draw(&mut self.shadow_renderer, &shadow_view_projection, None);
-        }
-
-        let shadow_depth = self.shadow_renderer.depth_buffer.clone();
-        let mut shadow_bitmap: Bitmap<f32> =
-            Bitmap::new(self.shadow_renderer.width, self.shadow_renderer.height);
-        for (i, value) in shadow_bitmap.chunks_exact_mut(4).enumerate() {
-            value[0] = shadow_depth[i];
-            value[1] = shadow_depth[i];
-            value[2] = shadow_depth[i];
-            value[3] = 1.0;
+        // cube shadow-maps: draw all instances around every point light, again only when something close moved
+        self.moved.sort_unstable();
+        self.moved.dedup();
+        for light in self.scene.lights.iter_mut() {
+            self.cube_shadows
+                .draw(&self.scene.instances, light, &self.moved);
         }
+        self.moved.clear();
 
-        let light = Light::new(
-            shadow_view_projection,
-            shadow_light_transform,
-            shadow_bitmap,
-        );
-
-        let view_projection = Matrix4::multiply(&self.projection, &self.camera.transform());
+        let view_projection = Matrix4::multiply(&self.projection, &self.camera.view());
 
         // draw all instances
-        for instance in self.instances.iter() {
-            instance.draw(&mut self.renderer, &view_projection, Option::Some(&light));
-        }
+        self.scene
+            .draw(&mut self.renderer, &view_projection, self.camera.position());
 
         // # debug: draw all vertices
         // let screenspace = Matrix4::screenspace(self.width as f32, self.height as f32);
-        // for instance in self.instances.iter() {
-        //     let mvp = Matrix4::multiply(&view_projection, &instance.transform);
-        //     // dbg!(&self.camera.transform());
+        // for instance in self.scene.instances.iter() {
+        //     let mvp = Matrix4::multiply(&view_projection, instance.transform.matrix());
+        //     // dbg!(&self.camera.view());
         //     let identity = Matrix4::new_identity();
         //     // dbg!(&mvp);
         //     for v in instance.mesh.vertices.iter() {
@@ -346,10 +470,10 @@ impl World {
 
         // draw shadow texture
         // let scale = 16;
-        // for x in 0..self.shadow_renderer.width / scale {
-        //     for y in 0..self.shadow_renderer.height / scale {
+        // for x in 0..self.shadows.renderer.width / scale {
+        //     for y in 0..self.shadows.renderer.height / scale {
         //         let index = (x * 4 * 4 + y * 4 * self.width * scale) as usize;
-        //         let d = self.shadow_renderer.depth_buffer[index];
+        //         let d = self.shadows.renderer.depth_buffer[index];
         //         self.renderer
         //             .color_buffer
         //             .set_pixel(x, y, &Color::newf(d, d, d, 1.0));
@@ -376,22 +500,32 @@ impl World {
     }
 
     pub fn spawn_instance_rand(&mut self, mesh_path: &str, bitmap_path: &str, scale: f32) {
-        let mesh_res = Self::make_mesh_res(mesh_path);
+        let mesh_res = match Self::make_mesh_res(mesh_path) {
+            Ok(mesh_res) => mesh_res,
+            Err(err) => {
+                log::error!("can't spawn instance: {err}");
+                return;
+            }
+        };
         let bitmap_res = Self::make_bitmap_res(bitmap_path);
 
-        let mut instance = Instance::new(Rc::clone(&mesh_res), Rc::clone(&bitmap_res), true);
+        let material_res = Self::make_material_res(&bitmap_res, true);
 
-        instance.transform.translate(
+        let instance = Instance::new(Rc::clone(&mesh_res), material_res);
+
+        let mut transform = Transform::from_translation(Vector4::new(
             rand::thread_rng().gen_range(-20.0..20.0),
             rand::thread_rng().gen_range(-20.0..20.0),
             rand::thread_rng().gen_range(-20.0..20.0),
-        );
-
-        instance.transform.scale(scale, scale, scale);
+            1.0,
+        ));
+        transform.set_scale(Vector4::new(scale, scale, scale, 0.0));
 
-        self.instances.push(instance);
+        self.scene
+            .add_instance(&Self::node_name(mesh_path), None, transform, instance);
     }
 
+    // the node of the new instance, named after the mesh file
     pub fn spawn_instance(
         &mut self,
         mesh_path: &str,
@@ -400,38 +534,74 @@ impl World {
         y_angle: f32,
         scale: f32,
         light: bool,
-    ) {
-        let mesh_res = Self::make_mesh_res(mesh_path);
+    ) -> Option<usize> {
+        let mesh_res = match Self::make_mesh_res(mesh_path) {
+            Ok(mesh_res) => mesh_res,
+            Err(err) => {
+                log::error!("can't spawn instance: {err}");
+                return None;
+            }
+        };
         let bitmap_res = Self::make_bitmap_res(bitmap_path);
 
-        let mut instance = Instance::new(Rc::clone(&mesh_res), Rc::clone(&bitmap_res), light);
+        let material_res = Self::make_material_res(&bitmap_res, light);
 
-        instance.transform.translate(pos.x, pos.y, pos.z);
-        instance.transform.rotate_y(y_angle);
-        instance.transform.scale(scale, scale, scale);
+        let instance = Instance::new(Rc::clone(&mesh_res), material_res);
 
-        self.instances.push(instance);
+        let transform = Transform::new(
+            pos,
+            Quaternion::from_euler(Vector4::new(0.0, y_angle, 0.0, 0.0)),
+            Vector4::new(scale, scale, scale, 0.0),
+        );
+
+        let node = self
+            .scene
+            .add_instance(&Self::node_name(mesh_path), None, transform, instance);
+        return Some(node);
     }
 
     pub fn make_instance(
         &mut self,
         mesh_res: &Rc<Box<Mesh>>,
-        bitmap_res: &Rc<Box<Bitmap<u8>>>,
-        light: bool,
+        material_res: &Rc<Material>,
     ) -> Instance {
-        Instance::new(Rc::clone(&mesh_res), Rc::clone(&bitmap_res), light)
+        Instance::new(Rc::clone(&mesh_res), Rc::clone(&material_res))
+    }
+
+    pub fn make_material_res(bitmap_res: &Arc<Texture>, light: bool) -> Rc<Material> {
+        let mut material = Material::new(Arc::clone(&bitmap_res));
+        material.unlit = !light;
+        Rc::new(material)
+    }
+
+    // obj files are parsed, mesh caches (made by the mesh_convert binary) are read as they are
+    pub fn make_mesh_res(path: &str) -> Result<Rc<Box<Mesh>>, Box<dyn Error>> {
+        let path_ref = Path::new(path);
+        let mesh = if path_ref
+            .extension()
+            .map_or(false, |ext| ext == mesh_cache::EXTENSION)
+        {
+            load_mesh_cache(path_ref)?
+        } else {
+            load_mesh(path)?
+        };
+        Ok(Rc::new(Box::new(mesh)))
     }
 
-    pub fn make_mesh_res(path: &str) -> Rc<Box<Mesh>> {
-        let mesh = load_mesh(path);
-        Rc::new(Box::new(mesh))
+    // "./assets/plane.obj" -> "plane"
+    fn node_name(mesh_path: &str) -> String {
+        Path::new(mesh_path)
+            .file_stem()
+            .map_or(mesh_path.to_string(), |stem| {
+                stem.to_string_lossy().into_owned()
+            })
     }
 
-    pub fn make_bitmap_res(path: &str) -> Rc<Box<Bitmap<u8>>> {
+    pub fn make_bitmap_res(path: &str) -> Arc<Texture> {
         let image = image::open(path).unwrap();
         let mut bitmap = Bitmap::new(image.width(), image.height());
         bitmap.pixels = image.to_rgba8().as_bytes().into();
-        Rc::new(Box::new(bitmap))
+        Arc::new(Texture::new(bitmap))
     }
 
     pub fn handle_event(&mut self, event: &winit::event::Event<()>) {
@@ -0,0 +1,127 @@
+use std::{error::Error, path::Path, process::ExitCode};
+
+use core::app::{
+    gltf_loader::load_gltf,
+    mesh_cache::{self, save_mesh_cache},
+    mesh_loader::{load_mesh, load_mesh_welded},
+    ply_loader::load_ply,
+    stl_loader::load_stl,
+};
+use core::graphics::mesh::Mesh;
+
+// turns a model into a mesh cache that loads without any parsing
+//
+// cargo run --bin mesh_convert --release -- ./assets/mech.obj [./assets/mech.mesh] [--weld 0.0001]
+//
+// supported sources: .obj, .stl, .ply, .gltf and .glb (every mesh of the scene is merged into one, moved by its node)
+// `--weld` closes the cracks of .obj files whose faces have their own copies of the positions
+fn main() -> ExitCode {
+    simple_logger::init_with_level(log::Level::Warn).unwrap();
+
+    let mut args: Vec<String> = std::env::args().collect();
+
+    let mut weld = None;
+    if let Some(flag) = args.iter().position(|arg| arg == "--weld") {
+        match args.get(flag + 1).map(|value| value.parse::<f32>()) {
+            Some(Ok(epsilon)) if epsilon > 0.0 => weld = Some(epsilon),
+            _ => {
+                eprintln!("--weld needs a distance above 0");
+                return ExitCode::FAILURE;
+            }
+        }
+        args.drain(flag..flag + 2);
+    }
+
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("usage: {} <source> [output] [--weld epsilon]", args[0]);
+        return ExitCode::FAILURE;
+    }
+
+    let source = Path::new(&args[1]);
+    let output = match args.get(2) {
+        Some(output) => Path::new(output).to_path_buf(),
+        None => source.with_extension(mesh_cache::EXTENSION),
+    };
+
+    let mesh = match load_source(source, weld) {
+        Ok(mesh) => mesh,
+        Err(err) => {
+            eprintln!("can't load {}: {err}", source.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(err) = save_mesh_cache(&output, &mesh) {
+        eprintln!("can't save {}: {err}", output.display());
+        return ExitCode::FAILURE;
+    }
+
+    println!(
+        "{} -> {} ({} vertices, {} triangles)",
+        source.display(),
+        output.display(),
+        mesh.vertices.len(),
+        mesh.indices.len() / 3
+    );
+
+    ExitCode::SUCCESS
+}
+
+fn load_source(source: &Path, weld: Option<f32>) -> Result<Mesh, Box<dyn Error>> {
+    let extension = source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "obj" => match weld {
+            Some(epsilon) => Ok(load_mesh_welded(&source.to_string_lossy(), epsilon)?),
+            None => Ok(load_mesh(&source.to_string_lossy())?),
+        },
+        _ if weld.is_some() => Err("only .obj files can be welded".into()),
+        "stl" => Ok(load_stl(source)?),
+        "ply" => Ok(load_ply(source)?),
+        "gltf" | "glb" => {
+            let scene = load_gltf(source)?;
+            let mut merged = Mesh::default();
+
+            for instance in scene.instances() {
+                let matrix = instance.matrix();
+
+                // normals are bent by the inverse-transpose (like the shader does) so they stay perpendicular
+                // to scaled surfaces, then both directions are made unit length again
+                let mut normal_matrix = matrix.clone();
+                if normal_matrix.invert() {
+                    normal_matrix.transpose();
+                } else {
+                    normal_matrix = matrix.clone();
+                }
+
+                let offset = merged.vertices.len();
+                merged
+                    .vertices
+                    .extend(instance.mesh.vertices.iter().map(|vertex| {
+                        let mut vertex = vertex.transform(matrix, &normal_matrix);
+                        if vertex.normal.length() > 0.0 {
+                            vertex.normal = vertex.normal.normalized();
+                        }
+
+                        let handedness = vertex.tangent.w;
+                        vertex.tangent.w = 0.0;
+                        if vertex.tangent.length() > 0.0 {
+                            vertex.tangent = vertex.tangent.normalized();
+                        }
+                        vertex.tangent.w = handedness;
+                        vertex
+                    }));
+                merged
+                    .indices
+                    .extend(instance.mesh.indices.iter().map(|index| index + offset));
+            }
+
+            Ok(merged)
+        }
+        _ => Err(format!("unsupported file type '{extension}'").into()),
+    }
+}